@@ -0,0 +1,10 @@
+use clap::ArgMatches;
+
+mod verify;
+
+pub fn execute(args: &ArgMatches) {
+    match args.subcommand() {
+        ("verify", Some(sub_matches)) => verify::execute(sub_matches),
+        _                             => error!("Not valid")
+    }
+}
@@ -0,0 +1,49 @@
+use carina_core;
+use carina_core::{Config, FileStorage, StorageBackend};
+use clap::ArgMatches;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+pub fn execute(args: &ArgMatches) {
+    let mut content = String::new();
+
+    // unwrap ok. CONFIG has a default value
+    match File::open(args.value_of("CONFIG").unwrap().to_string()) {
+        Ok(mut file) => match file.read_to_string(&mut content) {
+            Ok(_)  => (),
+            Err(e) => panic!("[CHAIN_VERIFY] Error readying config file. {}", e)
+        },
+        Err(e)     => panic!("[CHAIN_VERIFY] Error readying config file. {}", e)
+    };
+
+    let config: Config = match Config::from_str(&content) {
+        Ok(val) => val,
+        Err(e)  => panic!("[CHAIN_VERIFY] Error reading config file {:?}", e)
+    };
+
+    // the memory backend only lives inside the running node
+    if config.storage_backend == StorageBackend::Memory {
+        error!("[CHAIN_VERIFY] The memory storage backend can´t be verified.");
+        process::exit(1);
+    }
+
+    // read only, a running node may still write to the storage
+    let storage = match FileStorage::open_read_only(&config.storage) {
+        Ok(val) => val,
+        Err(e)  => panic!("[CHAIN_VERIFY] Error opening block storage {:?}", e)
+    };
+    if let Some((segment, offset)) = storage.incomplete_tail() {
+        warn!("[CHAIN_VERIFY] Segment {} ends with an incomplete block at offset {}. It is ignored.", segment, offset);
+    }
+
+    let result = carina_core::verify_chain(&storage, &config.retarget, config.max_time_drift);
+
+    match result {
+        Ok(blocks) => info!("[CHAIN_VERIFY] Verified {} blocks.", blocks),
+        Err(e)     => {
+            error!("[CHAIN_VERIFY] {}", e);
+            process::exit(1);
+        }
+    };
+}
@@ -0,0 +1,100 @@
+use carina_core_protocol::{Events, MessageBuilder, Payload};
+use carina_core_protocol::payloads::block::{BlockFoundPayload, BlockVotePayload};
+use carina_core;
+use carina_core::{ChainManager, ChainUpdate, Config, Event, Miner, SyncManager};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+
+pub struct BlockFound {
+    chain: Arc<Mutex<ChainManager>>,
+    miner: Arc<Mutex<Miner>>,
+    sync: Arc<Mutex<SyncManager>>
+}
+
+impl BlockFound {
+    pub fn new(chain: Arc<Mutex<ChainManager>>, miner: Arc<Mutex<Miner>>, sync: Arc<Mutex<SyncManager>>) -> Self {
+        Self {
+            chain,
+            miner,
+            sync
+        }
+    }
+}
+
+impl Event for BlockFound {
+    fn execute(&mut self, udp: UdpSocket, source: String, config: &mut Config, buffer: &[u8]) -> Result<(), Error> {
+        let parsed = Parser::parse_payload(&buffer);
+        let block = BlockFoundPayload::parse(parsed)?.block;
+        let index = block.header.index;
+        let hash = block.hash();
+        info!("[CONSOLE_BLOCK_FOUND] Received block {} with hash {} from {}", index, hash, source);
+
+        let result = match self.chain.lock() {
+            Ok(mut chain) => chain.add_block(block.clone()),
+            Err(e)        => return Err(format_err!("Error locking chain. {}", e))
+        };
+
+        // no vote for orphans, the chain decides once their ancestors arrived
+        if let Ok(ChainUpdate::Orphan) = result {
+            info!("[CONSOLE_BLOCK_FOUND] Parent of block {} is unknown, requesting headers from {}", hash, source);
+            let requests = match self.sync.lock() {
+                Ok(mut sync) => sync.on_orphan(&source, &block)?,
+                Err(e)       => return Err(format_err!("Error locking sync. {}", e))
+            };
+            carina_core::send_requests(&udp, config, requests);
+            return Ok(());
+        }
+
+        // only a block on the main chain is accepted, a block on a side
+        // branch doesn´t replace the block we may be mining
+        let (accepted, reason) = match result {
+            Ok(ChainUpdate::Extended { .. }) | Ok(ChainUpdate::Reorganised(_)) => {
+                debug!("[CONSOLE_BLOCK_FOUND] Added block {} to the main chain", hash);
+                (true, String::new())
+            },
+            Ok(ChainUpdate::Known)                                              => {
+                debug!("[CONSOLE_BLOCK_FOUND] Block {} is already known, not voting again", hash);
+                return Ok(());
+            },
+            Ok(update)                                                          => {
+                info!("[CONSOLE_BLOCK_FOUND] Block {} from {} is not part of the main chain. {:?}", hash, source, update);
+                (false, String::from("Block is not part of the main chain"))
+            },
+            Err(e)                                                              => {
+                error!("[CONSOLE_BLOCK_FOUND] Rejected block {} from {}. {}", hash, source, e);
+                (false, e.to_string())
+            }
+        };
+
+        if accepted {
+            match self.miner.lock() {
+                Ok(mut miner) => if miner.cancel_height(index) {
+                    info!("[CONSOLE_BLOCK_FOUND] Stopped mining block {}", index);
+                },
+                Err(e)        => error!("[CONSOLE_BLOCK_FOUND] Error locking miner. {}", e)
+            };
+        }
+
+        let payload = BlockVotePayload::signed(&config.nacl, index, hash, accepted, reason);
+
+        match config.peers.get(&source) {
+            Some(peer) => {
+                let key = config.seal_key(peer);
+                let message = MessageBuilder::new()
+                    .set_event_code(Events::as_val(Events::BlockVote))
+                    .set_payload(payload)
+                    .build_precomputed(&mut config.nacl, &key);
+
+                match config.transport.send_reliable(&udp, Events::BlockVote, &message, &source) {
+                    Ok(_)  => debug!("[CONSOLE_BLOCK_FOUND] Sending vote to peer {}", source),
+                    Err(e) => error!("[CONSOLE_BLOCK_FOUND] Error sending vote to peer: {}. Error: {}", source, e),
+                };
+            },
+            None => error!("[CONSOLE_BLOCK_FOUND] Error getting peer")
+        };
+
+        Ok(())
+    }
+}
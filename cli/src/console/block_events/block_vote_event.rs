@@ -0,0 +1,46 @@
+use carina_core_protocol::Payload;
+use carina_core_protocol::payloads::block::BlockVotePayload;
+use carina_core::{Config, Event, RoundResult, VoteRounds};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+
+pub struct BlockVote {
+    rounds: Arc<Mutex<VoteRounds>>
+}
+
+impl BlockVote {
+    pub fn new(rounds: Arc<Mutex<VoteRounds>>) -> Self {
+        Self {
+            rounds
+        }
+    }
+}
+
+impl Event for BlockVote {
+    fn execute(&mut self, _: UdpSocket, source: String, config: &mut Config, buffer: &[u8]) -> Result<(), Error> {
+        let parsed = Parser::parse_payload(&buffer);
+        let vote = BlockVotePayload::parse(parsed)?;
+
+        if vote.accepted {
+            debug!("[CONSOLE_BLOCK_VOTE] Peer {} accepted block {}", source, vote.hash);
+        } else {
+            error!("[CONSOLE_BLOCK_VOTE] Peer {} rejected block {}. {}", source, vote.hash, vote.reason);
+        }
+
+        let sign_key = config.peers.get(&source).and_then(|peer| peer.sign_key.as_ref());
+        let result = match self.rounds.lock() {
+            Ok(mut rounds) => rounds.add_vote(&source, sign_key, vote)?,
+            Err(e)         => return Err(format_err!("Error locking vote rounds. {}", e))
+        };
+
+        match result {
+            Some(RoundResult::Finalized(certificate)) => info!("[CONSOLE_BLOCK_VOTE] Block {} is final", certificate.index),
+            Some(RoundResult::Rejected(certificate))  => error!("[CONSOLE_BLOCK_VOTE] Block {} was rejected by the quorum", certificate.index),
+            _                                         => ()
+        };
+
+        Ok(())
+    }
+}
@@ -1,24 +1,29 @@
 use carina_core_protocol::Events;
 use carina_core_protocol::MessageBuilder;
 use carina_core_protocol::Payload;
-use carina_core_protocol::payloads::block::CalcBlockPayload;
-use carina_core_protocol::payloads::EmptyPayload;
+use carina_core_protocol::payloads::block::{Block, BlockFoundPayload, BlockVotePayload};
+use carina_core;
 use carina_core::Config;
 use carina_core::Event;
-use crypto::digest::Digest;
-use crypto::sha3::Sha3;
+use carina_core::{ChainManager, ChainUpdate, Miner, RoundResult, VoteRounds};
 use failure::Error;
 use protocol_builder_parser::Parser;
 use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 pub struct CalcBlock {
-    is_calculating: bool
+    chain: Arc<Mutex<ChainManager>>,
+    miner: Arc<Mutex<Miner>>,
+    rounds: Arc<Mutex<VoteRounds>>
 }
 
 impl CalcBlock {
-    pub fn new() -> Self {
+    pub fn new(chain: Arc<Mutex<ChainManager>>, miner: Arc<Mutex<Miner>>, rounds: Arc<Mutex<VoteRounds>>) -> Self {
         Self {
-            is_calculating: false
+            chain,
+            miner,
+            rounds
         }
     }
 }
@@ -26,50 +31,102 @@ impl CalcBlock {
 impl Event for CalcBlock {
     fn execute(&mut self, socket: UdpSocket, _: String, config: &mut Config, buffer: &[u8]) -> Result<(), Error> {
         let parsed = Parser::parse_payload(&buffer);
-        let parsed = CalcBlockPayload::parse(parsed)?;
+        let block = Block::parse(parsed)?;
 
-        let hash;
-        let mut nonce = 0;
-
-        let mut block_data = String::new();
-        block_data.push_str(&parsed.index.to_string());
-        block_data.push_str(&parsed.timestamp.to_string());
-        block_data.push_str(&parsed.prev);
-        block_data.push_str(&parsed.content);
+        if !block.is_consistent() {
+            return Err(format_err!("Block {} does not match its merkle root", block.header.index));
+        }
 
         info!("[CONSOLE_CALC_BLOCK] Starting generating a new block.");
-        self.is_calculating = true;
-        loop {
-            block_data.push_str(&nonce.to_string());
+        let chain = Arc::clone(&self.chain);
+        let rounds = Arc::clone(&self.rounds);
+        let mut config = config.clone();
 
-            let mut hasher = Sha3::sha3_256();
-            hasher.input_str(block_data.as_str());
-            let hex = hasher.result_str();
+        match self.miner.lock() {
+            Ok(mut miner) => miner.start(block, move |block| {
+                let hash = block.hash();
+                info!("[CONSOLE_CALC_BLOCK] Found hash for block {}", hash);
 
-            if String::from("0000") == &hex[..4] {
-                hash = hex.clone();
-                break;
-            } else {
-                nonce += 1;
-            }
-        }
+                let update = match chain.lock() {
+                    Ok(mut chain) => chain.add_block(block.clone()),
+                    Err(e)        => {
+                        error!("[CONSOLE_CALC_BLOCK] Error locking chain. {}", e);
+                        return;
+                    }
+                };
+                // orphans waiting for this block may have reorganised the chain
+                carina_core::notify_reorgs(&chain, &socket, &config);
 
-        info!("[CONSOLE_CALC_BLOCK] Found hash for block {}", hash);
+                match update {
+                    Ok(ChainUpdate::Extended { height }) => debug!("[CONSOLE_CALC_BLOCK] Saved block with height {}", height),
+                    Ok(update)                           => {
+                        // a block for this height was already added, nobody needs ours
+                        info!("[CONSOLE_CALC_BLOCK] Block was not appended. {:?}", update);
+                        return;
+                    },
+                    Err(e)                               => {
+                        error!("[CONSOLE_CALC_BLOCK] Error adding block. {}", e);
+                        return;
+                    }
+                };
 
-        for (_, peer) in &config.peers {
-            // TODO: Update event
-            let message = MessageBuilder::new()
-                .set_event_code(Events::as_val(Events::Ping))
-                .set_payload(EmptyPayload::new())
-                .build(&mut config.nacl, &peer.public_key);
+                for (_, peer) in &config.peers {
+                    let key = config.seal_key(peer);
+                    let message = MessageBuilder::new()
+                        .set_event_code(Events::as_val(Events::BlockFound))
+                        .set_payload(BlockFoundPayload { block: block.clone() })
+                        .build_precomputed(&mut config.nacl, &key);
 
-            match socket.send_to(&message, &peer.address) {
-                Ok(_)  => debug!("[CONSOLE_CALC_BLOCK] Send hash to {}", peer.address),
-                Err(e) => error!("[CONSOLE_CALC_BLOCK] Error sending hash to peer: {}. Error: {}", peer.address, e),
-            };
-        }
+                    match config.transport.send_reliable(&socket, Events::BlockFound, &message, &peer.address) {
+                        Ok(_)  => debug!("[CONSOLE_CALC_BLOCK] Send block to {}", peer.address),
+                        Err(e) => error!("[CONSOLE_CALC_BLOCK] Error sending block to peer: {}. Error: {}", peer.address, e),
+                    };
+                }
+
+                start_round(rounds, &config, block.header.index, hash);
+            }),
+            Err(e)        => error!("[CONSOLE_CALC_BLOCK] Error locking miner. {}", e)
+        };
 
-        self.is_calculating = false;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Starts the validation round for a mined block with our own vote
+///
+/// The round is given up after the configured timeout.
+fn start_round(rounds: Arc<Mutex<VoteRounds>>, config: &Config, index: u64, hash: String) {
+    let vote = BlockVotePayload::signed(&config.nacl, index, hash, true, String::new());
+    // only peers with a known signing key can take part in the vote
+    let voters = config.peers.values().filter(|peer| peer.sign_key.is_some()).count() + 1;
+
+    let timeout = match rounds.lock() {
+        Ok(mut rounds) => {
+            match rounds.start(&config.uri, &config.nacl.sign_public_key(), vote, voters) {
+                Ok(Some(RoundResult::Finalized(_))) => info!("[CONSOLE_CALC_BLOCK] Block {} is final", index),
+                Ok(_)                               => (),
+                Err(e)                              => {
+                    error!("[CONSOLE_CALC_BLOCK] Error starting validation round. {}", e);
+                    return;
+                }
+            };
+            rounds.timeout()
+        },
+        Err(e)         => {
+            error!("[CONSOLE_CALC_BLOCK] Error locking vote rounds. {}", e);
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        match rounds.lock() {
+            Ok(mut rounds) => for result in rounds.expire() {
+                if let RoundResult::TimedOut(certificate) = result {
+                    error!("[CONSOLE_CALC_BLOCK] Block {} did not reach the quorum in time", certificate.index);
+                }
+            },
+            Err(e)         => error!("[CONSOLE_CALC_BLOCK] Error locking vote rounds. {}", e)
+        };
+    });
+}
@@ -0,0 +1,60 @@
+use carina_core_protocol::{Events, MessageBuilder, Payload};
+use carina_core_protocol::payloads::block::{GetProofAckPayload, GetProofPayload};
+use carina_core;
+use carina_core::{BlockStorage, Config, Event};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+
+pub struct GetProof {
+    storage: Arc<Mutex<BlockStorage>>
+}
+
+impl GetProof {
+    pub fn new(storage: Arc<Mutex<BlockStorage>>) -> Self {
+        Self {
+            storage
+        }
+    }
+}
+
+impl Event for GetProof {
+    fn execute(&mut self, udp: UdpSocket, source: String, config: &mut Config, buffer: &[u8]) -> Result<(), Error> {
+        let parsed = Parser::parse_payload(&buffer);
+        let parsed = GetProofPayload::parse(parsed)?;
+        info!("[CONSOLE_GET_PROOF] Received proof request for {} in block {} from {}", parsed.unique_key, parsed.block, source);
+
+        let block = if parsed.block.is_empty() {
+            None
+        } else {
+            Some(parsed.block.as_str())
+        };
+        let proof = match self.storage.lock() {
+            Ok(storage) => carina_core::get_proof(&*storage, &parsed.unique_key, block)?,
+            Err(e)      => return Err(format_err!("Error locking storage. {}", e))
+        };
+        let payload = match proof {
+            Some(proof) => proof,
+            None        => GetProofAckPayload::not_found(parsed.unique_key)
+        };
+
+        match config.peers.get(&source) {
+            Some(peer) => {
+                let key = config.seal_key(peer);
+                let message = MessageBuilder::new()
+                    .set_event_code(Events::as_val(Events::GetProofAck))
+                    .set_payload(payload)
+                    .build_precomputed(&mut config.nacl, &key);
+
+                match config.transport.send_to(&udp, Events::GetProofAck, &message, &source) {
+                    Ok(_)  => debug!("[CONSOLE_GET_PROOF] Sending proof to peer {}", source),
+                    Err(e) => error!("[CONSOLE_GET_PROOF] Error sending proof to peer: {}. Error: {}", source, e),
+                };
+            },
+            None => error!("[CONSOLE_GET_PROOF] Error getting peer")
+        };
+
+        Ok(())
+    }
+}
@@ -1,7 +1,13 @@
-mod block_state;
+mod block_found_event;
+mod block_vote_event;
 mod calc_block;
+mod get_proof_event;
 mod new_block_content_event;
+mod reorg_event;
 
-pub use self::block_state::BlockState;
+pub use self::block_found_event::BlockFound;
+pub use self::block_vote_event::BlockVote;
 pub use self::calc_block::CalcBlock;
-pub use self::new_block_content_event::NewBlockContent;
\ No newline at end of file
+pub use self::get_proof_event::GetProof;
+pub use self::new_block_content_event::NewBlockContent;
+pub use self::reorg_event::Reorg;
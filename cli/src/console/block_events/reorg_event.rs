@@ -0,0 +1,27 @@
+use carina_core_protocol::Payload;
+use carina_core_protocol::payloads::block::ReorgPayload;
+use carina_core::{Config, Event};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use std::net::UdpSocket;
+
+pub struct Reorg;
+
+impl Event for Reorg {
+    fn execute(&mut self, _: UdpSocket, _: String, _: &mut Config, buffer: &[u8]) -> Result<(), Error> {
+        let parsed = Parser::parse_payload(&buffer);
+        let reorg = ReorgPayload::parse(parsed)?;
+
+        info!(
+            "[CONSOLE_REORG] Switched chain at height {}. Old tip {}, new tip {}",
+            reorg.height, reorg.old_tip, reorg.new_tip
+        );
+        for hash in &reorg.disconnected {
+            debug!("[CONSOLE_REORG] Disconnected block {}", hash);
+        }
+        for hash in &reorg.connected {
+            debug!("[CONSOLE_REORG] Connected block {}", hash);
+        }
+        Ok(())
+    }
+}
@@ -1,24 +1,34 @@
 use carina_core;
-use carina_core::{CarinaConfigBuilder, Config};
-use carina_core_protocol::payloads::block::CalcBlockPayload;
-use carina_core_protocol::{Events, MessageBuilder};
+use carina_core::{BlockStorage, CarinaConfig, CarinaConfigBuilder, ChainManager, Config, Mempool, Miner, SyncManager, VoteRounds};
+use carina_core_protocol::payloads::block::Block;
+use carina_core_protocol::payloads::EmptyPayload;
+use carina_core_protocol::{Events, MessageBuilder, Payload};
 use clap::ArgMatches;
-use console::block_events::{BlockState, CalcBlock, NewBlockContent};
-use console::misc_events::{Ping, Pong};
+use console::block_events::{BlockFound, BlockVote, CalcBlock, GetProof, NewBlockContent, Reorg};
+use console::misc_events::{Ping, Pong, Reload};
+use console::sync_events::{GetBlock, GetBlockAck, GetHeaders, GetHeadersAck, GetTip, GetTipAck};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
+use std::net::UdpSocket;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time as std_time;
 use time;
 
+/// Seconds between two checks for timed out sync requests
+const SYNC_TICK: u64 = 1;
+/// Number of sync ticks after that the tips of all peers are requested again
+const TIP_INTERVAL: u64 = 30;
+
 pub fn execute(args: &ArgMatches) {
-    let internal_state = Arc::new(Mutex::new(BlockState::new()));
+    let mempool = Arc::new(Mutex::new(Mempool::new()));
     let mut content = String::new();
 
     // unwrap ok. CONFIG has a default value
-    match File::open(args.value_of("CONFIG").unwrap().to_string()) {
+    let config_path = args.value_of("CONFIG").unwrap().to_string();
+    match File::open(&config_path) {
         Ok(mut file) => match file.read_to_string(&mut content) {
             Ok(_) => (),
             Err(e) => panic!("[CONSOLE] Error readying config file. {}", e),
@@ -31,21 +41,74 @@ pub fn execute(args: &ArgMatches) {
         Err(e) => panic!("[CONSOLE] Error reading config file {:?}", e),
     };
 
+    let storage = match carina_core::open_storage(&config) {
+        Ok(val) => val,
+        Err(e) => panic!("[CONSOLE] Error opening block storage {:?}", e),
+    };
+
+    let chain = match ChainManager::new(Arc::clone(&storage), config.retarget, config.max_time_drift) {
+        Ok(val) => Arc::new(Mutex::new(val)),
+        Err(e) => panic!("[CONSOLE] Error loading chain {:?}", e),
+    };
+
+    let miner = Arc::new(Mutex::new(Miner::new(config.miner_threads)));
+    let rounds = Arc::new(Mutex::new(VoteRounds::new(
+        Arc::clone(&storage),
+        config.quorum,
+        std_time::Duration::from_secs(config.quorum_timeout),
+        config.min_voters,
+    )));
+    let sync = Arc::new(Mutex::new(SyncManager::new(Arc::clone(&chain))));
+
     let carina_config_builder = CarinaConfigBuilder::new()
         .add_event(Events::Ping, Arc::new(Mutex::new(Ping {})))
         .add_event(Events::Pong, Arc::new(Mutex::new(Pong {})))
-        .add_event(Events::CalcBlock, Arc::new(Mutex::new(CalcBlock::new())))
+        .add_event(Events::CalcBlock, Arc::new(Mutex::new(CalcBlock::new(Arc::clone(&chain), Arc::clone(&miner), Arc::clone(&rounds)))))
+        .add_event(Events::BlockFound, Arc::new(Mutex::new(BlockFound::new(Arc::clone(&chain), Arc::clone(&miner), Arc::clone(&sync)))))
+        .add_event(Events::BlockVote, Arc::new(Mutex::new(BlockVote::new(Arc::clone(&rounds)))))
+        .add_event(Events::GetProof, Arc::new(Mutex::new(GetProof::new(Arc::clone(&storage)))))
+        .add_event(Events::Reorg, Arc::new(Mutex::new(Reorg)))
+        .add_event(Events::Reload, Arc::new(Mutex::new(Reload::new(Arc::clone(&miner), Arc::clone(&rounds)))))
+        .add_event(Events::GetTip, Arc::new(Mutex::new(GetTip::new(Arc::clone(&storage)))))
+        .add_event(Events::GetTipAck, Arc::new(Mutex::new(GetTipAck::new(Arc::clone(&sync)))))
+        .add_event(Events::GetHeaders, Arc::new(Mutex::new(GetHeaders::new(Arc::clone(&storage)))))
+        .add_event(Events::GetHeadersAck, Arc::new(Mutex::new(GetHeadersAck::new(Arc::clone(&sync)))))
+        .add_event(Events::GetBlock, Arc::new(Mutex::new(GetBlock::new(Arc::clone(&storage)))))
+        .add_event(Events::GetBlockAck, Arc::new(Mutex::new(GetBlockAck::new(Arc::clone(&sync)))))
         .add_event(
             Events::NewBlockContent,
             Arc::new(Mutex::new(NewBlockContent::new(Arc::clone(
-                &internal_state,
+                &mempool,
             )))),
         )
+        .set_chain(Arc::clone(&chain))
+        .set_mempool(Arc::clone(&mempool))
+        .set_miner(Arc::clone(&miner))
         .set_config(config);
     let (_, socket, config) = carina_core::init(carina_config_builder);
+    start_sync(Arc::clone(&sync), socket.try_clone().unwrap(), Arc::clone(&config));
+    carina_core::watch_config(Arc::clone(&config), socket.try_clone().unwrap(), config_path);
+    let shutdown = match config.lock() {
+        Ok(val) => Arc::clone(&val.shutdown),
+        Err(e) => panic!("[CONSOLE] Error locking state. {}", e),
+    };
 
+    // content of blocks up to this height was removed from the mempool
+    let mut confirmed = match storage.lock() {
+        Ok(val) => val.tip().map(|(height, _)| height),
+        Err(e) => panic!("[CONSOLE] Error locking storage. {}", e),
+    };
     let mut block_send = false;
     loop {
+        if shutdown.load(Ordering::SeqCst) {
+            info!("[THREAD_CONSOLE] Shutting down");
+            match miner.lock() {
+                Ok(mut val) => val.cancel(),
+                Err(e) => error!("[THREAD_CONSOLE] Error locking miner. {}", e),
+            };
+            return;
+        }
+
         let peers = {
             match config.lock() {
                 Ok(val) => val.config.peers.clone(),
@@ -59,6 +122,10 @@ pub fn execute(args: &ArgMatches) {
             let state = config.lock().unwrap();
             state.config.nacl.clone()
         };
+        let transport = {
+            let state = config.lock().unwrap();
+            state.config.transport.clone()
+        };
 
         let current_time = time::now_utc();
 
@@ -66,58 +133,69 @@ pub fn execute(args: &ArgMatches) {
             debug!("[THREAD_CONSOLE] Time to generate a new block.");
             block_send = true;
 
-            // TODO: Read out the database to find the real number of blocks
-            let blocks_saved = 0;
-            debug!("[THREAD_CONSOLE] Latest block number: {}", blocks_saved);
+            let tip = match storage.lock() {
+                Ok(val) => val.tip(),
+                Err(e) => {
+                    error!("[THREAD_CONSOLE] Error locking storage. {}", e);
+                    continue;
+                }
+            };
+            debug!("[THREAD_CONSOLE] Latest block: {:?}", tip);
 
-            let payload = CalcBlockPayload::block(0, String::from("0".repeat(64)), String::new());
+            // content of earlier rounds is proposed again until its block
+            // is part of the main chain
+            if let Some((height, _)) = tip {
+                let from = confirmed.map(|confirmed| confirmed + 1).unwrap_or(0);
+                confirm_content(&storage, &mempool, from, height);
+                confirmed = Some(height);
+            }
 
-            if blocks_saved > 0 {
-                let mut next_block = String::new();
+            let difficulty = match chain.lock() {
+                Ok(val) => val.next_difficulty(),
+                Err(e) => {
+                    error!("[THREAD_CONSOLE] Error locking chain. {}", e);
+                    continue;
+                }
+            };
+            let difficulty = match difficulty {
+                Ok(val) => val,
+                Err(e) => {
+                    error!("[THREAD_CONSOLE] Error calculating difficulty. {}", e);
+                    continue;
+                }
+            };
 
-                let content = {
-                    match internal_state.lock() {
-                        Ok(val) => val.content.clone(),
+            let mut payload = match tip {
+                Some((height, hash)) => {
+                    let entries = match mempool.lock() {
+                        Ok(val) => val.entries(),
                         Err(e) => {
-                            error!("[THREAD_CONSOLE] Error locking state. {}", e);
-                            HashMap::new()
+                            error!("[THREAD_CONSOLE] Error locking mempool. {}", e);
+                            Vec::new()
                         }
-                    }
-                };
+                    };
 
-                for (_, content) in &content {
-                    next_block.push_str(&content);
+                    Block::new(height + 1, hash, entries)
                 }
+                None => Block::new(0, String::from("0".repeat(64)), Vec::new()),
+            };
+            payload.header.difficulty = difficulty;
+            debug!("[THREAD_CONSOLE] Difficulty of the next block: {}", difficulty);
 
-                {
-                    match internal_state.lock() {
-                        Ok(mut val) => val.reset(),
-                        Err(e) => error!("[THREAD_CONSOLE] Error locking state. {}", e),
+            for (_, peer) in peers.clone() {
+                let key = match config.lock() {
+                    Ok(val) => val.config.seal_key(&peer),
+                    Err(e) => {
+                        error!("[THREAD_CONSOLE] Error locking state. {}", e);
+                        continue;
                     }
                 };
-
-                // TODO: add save
-                /*if Path::new(&format!("{}/last", state_lock.storage)).exists() {
-                    let mut file = File::open(format!("{}/last", state_lock.storage))
-                        .expect("Should be able to read the last block");
-                    let mut content = String::new();
-
-                    file.read_to_string(&mut content)
-                        .expect("Should be able to read last block");
-
-                    let result: Vec<&str> = content.split('\n').collect();
-                    payload =
-                        BlockGen::block(blocks_saved as u64 - 1, result[5].to_string(), next_block);
-                    debug!("[THREAD BLOCK] Written block files");
-                }*/            }
-
-            for (_, peer) in peers.clone() {
                 let message = MessageBuilder::new()
-                    .set_event_code(Events::as_val(Events::Ping))
+                    .set_event_code(Events::as_val(Events::CalcBlock))
                     .set_payload(payload.clone())
-                    .build(&mut nacl, &peer.public_key);
+                    .build_precomputed(&mut nacl, &key);
 
-                match socket.send_to(&message, &peer.address) {
+                match transport.send_reliable(&socket, Events::CalcBlock, &message, &peer.address) {
                     Ok(_) => debug!("[THREAD_CONSOLE] Send calc_block to {}", peer.address),
                     Err(e) => error!(
                         "[THREAD_CONSOLE] Error sending calc_block to peer: {}. Error: {}",
@@ -126,10 +204,104 @@ pub fn execute(args: &ArgMatches) {
                 };
             }
         } else {
-            thread::sleep(std_time::Duration::from_secs(
-                (60 - current_time.tm_sec) as u64,
-            ));
+            // sleep in steps of a second, so a shutdown is noticed
+            for _ in current_time.tm_sec..60 {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                thread::sleep(std_time::Duration::from_secs(1));
+            }
             block_send = false;
         }
     }
 }
+
+/// Removes the content of the main chain blocks from `from` up to and
+/// including `to` from the mempool
+fn confirm_content(storage: &Arc<Mutex<BlockStorage>>, mempool: &Arc<Mutex<Mempool>>, from: u64, to: u64) {
+    for height in from..to + 1 {
+        let bytes = match storage.lock() {
+            Ok(val) => val.get_by_height(height),
+            Err(e) => {
+                error!("[THREAD_CONSOLE] Error locking storage. {}", e);
+                return;
+            }
+        };
+        let block = match bytes {
+            Ok(Some(bytes)) => match Block::from_bytes(&bytes) {
+                Ok(block) => block,
+                Err(e) => {
+                    error!("[THREAD_CONSOLE] Error reading block {}. {}", height, e);
+                    continue;
+                }
+            },
+            Ok(None) => return,
+            Err(e) => {
+                error!("[THREAD_CONSOLE] Error reading block {}. {}", height, e);
+                return;
+            }
+        };
+
+        match mempool.lock() {
+            Ok(mut val) => val.confirm(&block.entries),
+            Err(e) => error!("[THREAD_CONSOLE] Error locking mempool. {}", e),
+        };
+    }
+}
+
+/// Asks all peers for their tip and keeps the sync going
+///
+/// Requests that timed out are sent to other peers and the tips are
+/// refreshed regularly, so new peers and longer chains are noticed.
+fn start_sync(sync: Arc<Mutex<SyncManager>>, socket: UdpSocket, config: Arc<Mutex<CarinaConfig>>) {
+    thread::spawn(move || {
+        let mut ticks = 0;
+        loop {
+            let mut state = match config.lock() {
+                Ok(val) => val.config.clone(),
+                Err(e) => {
+                    error!("[THREAD_SYNC] Error locking state. {}", e);
+                    return;
+                }
+            };
+
+            if ticks % TIP_INTERVAL == 0 {
+                request_tips(&socket, &mut state);
+            }
+            ticks += 1;
+
+            let requests = match sync.lock() {
+                Ok(mut val) => val.tick(),
+                Err(e) => {
+                    error!("[THREAD_SYNC] Error locking sync. {}", e);
+                    return;
+                }
+            };
+            match requests {
+                Ok(requests) => carina_core::send_requests(&socket, &mut state, requests),
+                Err(e) => error!("[THREAD_SYNC] Error checking sync requests. {}", e),
+            };
+
+            thread::sleep(std_time::Duration::from_secs(SYNC_TICK));
+        }
+    });
+}
+
+/// Sends `GetTip` to all peers
+fn request_tips(socket: &UdpSocket, config: &mut Config) {
+    for (_, peer) in config.peers.clone() {
+        let key = config.seal_key(&peer);
+        let message = MessageBuilder::new()
+            .set_event_code(Events::as_val(Events::GetTip))
+            .set_payload(EmptyPayload::new())
+            .build_precomputed(&mut config.nacl, &key);
+
+        match config.transport.send_to(socket, Events::GetTip, &message, &peer.address) {
+            Ok(_) => debug!("[THREAD_SYNC] Send get_tip to {}", peer.address),
+            Err(e) => error!(
+                "[THREAD_SYNC] Error sending get_tip to peer: {}. Error: {}",
+                peer.address, e
+            ),
+        };
+    }
+}
@@ -1,5 +1,7 @@
 mod ping_event;
 mod pong_event;
+mod reload_event;
 
 pub use self::ping_event::Ping;
-pub use self::pong_event::Pong;
\ No newline at end of file
+pub use self::pong_event::Pong;
+pub use self::reload_event::Reload;
\ No newline at end of file
@@ -12,12 +12,13 @@ impl Event for Ping {
         info!("[CONSOLE_PING] Received ping event from {:?}", source);
         match config.peers.get(&source) {
             Some(peer) => {
+                let key = config.seal_key(peer);
                 let message = MessageBuilder::new()
                     .set_event_code(Events::as_val(Events::Pong))
                     .set_payload(EmptyPayload::new())
-                    .build(&mut config.nacl, &peer.public_key);
+                    .build_precomputed(&mut config.nacl, &key);
 
-                match udp.send_to(&message, &source) {
+                match config.transport.send_to(&udp, Events::Pong, &message, &source) {
                     Ok(_)  => debug!("[CONSOLE_PING] Sending pong to peer {}", source),
                     Err(e) => error!("[CONSOLE_PING] Error sending pong to peer: {}. Error: {}", source, e),
                 };
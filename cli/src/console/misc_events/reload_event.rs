@@ -0,0 +1,35 @@
+use carina_core::{Config, Event, Miner, VoteRounds};
+use failure::Error;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub struct Reload {
+    miner: Arc<Mutex<Miner>>,
+    rounds: Arc<Mutex<VoteRounds>>,
+}
+
+impl Reload {
+    pub fn new(miner: Arc<Mutex<Miner>>, rounds: Arc<Mutex<VoteRounds>>) -> Self {
+        Self {
+            miner,
+            rounds,
+        }
+    }
+}
+
+impl Event for Reload {
+    fn execute(&mut self, _: UdpSocket, _: String, config: &mut Config, _: &[u8]) -> Result<(), Error> {
+        match self.miner.lock() {
+            Ok(mut miner) => miner.set_threads(config.miner_threads),
+            Err(e)        => return Err(format_err!("Error locking miner. {}", e)),
+        };
+        match self.rounds.lock() {
+            Ok(mut rounds) => rounds.set_quorum(config.quorum, Duration::from_secs(config.quorum_timeout), config.min_voters),
+            Err(e)         => return Err(format_err!("Error locking vote rounds. {}", e)),
+        };
+
+        info!("[CONSOLE_RELOAD] Using {} miner threads and a quorum of {:?}", config.miner_threads, config.quorum);
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 pub mod block_events;
 pub mod misc_events;
+pub mod sync_events;
 
 mod exec;
 
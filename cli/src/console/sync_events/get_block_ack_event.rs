@@ -0,0 +1,36 @@
+use carina_core_protocol::Payload;
+use carina_core_protocol::payloads::block::GetBlockAckPayload;
+use carina_core;
+use carina_core::{Config, Event, SyncManager};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+
+pub struct GetBlockAck {
+    sync: Arc<Mutex<SyncManager>>
+}
+
+impl GetBlockAck {
+    pub fn new(sync: Arc<Mutex<SyncManager>>) -> Self {
+        Self {
+            sync
+        }
+    }
+}
+
+impl Event for GetBlockAck {
+    fn execute(&mut self, udp: UdpSocket, source: String, config: &mut Config, buffer: &[u8]) -> Result<(), Error> {
+        let parsed = Parser::parse_payload(&buffer);
+        let parsed = GetBlockAckPayload::parse(parsed)?;
+        debug!("[CONSOLE_GET_BLOCK_ACK] Received block from {}", source);
+
+        let requests = match self.sync.lock() {
+            Ok(mut sync) => sync.on_block(&source, parsed)?,
+            Err(e)       => return Err(format_err!("Error locking sync. {}", e))
+        };
+        carina_core::send_requests(&udp, config, requests);
+
+        Ok(())
+    }
+}
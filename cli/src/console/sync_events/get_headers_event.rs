@@ -0,0 +1,51 @@
+use carina_core_protocol::{Events, MessageBuilder, Payload};
+use carina_core_protocol::payloads::block::GetHeadersPayload;
+use carina_core;
+use carina_core::{BlockStorage, Config, Event};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+
+pub struct GetHeaders {
+    storage: Arc<Mutex<BlockStorage>>
+}
+
+impl GetHeaders {
+    pub fn new(storage: Arc<Mutex<BlockStorage>>) -> Self {
+        Self {
+            storage
+        }
+    }
+}
+
+impl Event for GetHeaders {
+    fn execute(&mut self, udp: UdpSocket, source: String, config: &mut Config, buffer: &[u8]) -> Result<(), Error> {
+        let parsed = Parser::parse_payload(&buffer);
+        let parsed = GetHeadersPayload::parse(parsed)?;
+        debug!("[CONSOLE_GET_HEADERS] Received request for {} headers starting at {} from {}", parsed.count, parsed.start, source);
+
+        let payload = match self.storage.lock() {
+            Ok(storage) => carina_core::get_headers(&*storage, &parsed)?,
+            Err(e)      => return Err(format_err!("Error locking storage. {}", e))
+        };
+
+        match config.peers.get(&source) {
+            Some(peer) => {
+                let key = config.seal_key(peer);
+                let message = MessageBuilder::new()
+                    .set_event_code(Events::as_val(Events::GetHeadersAck))
+                    .set_payload(payload)
+                    .build_precomputed(&mut config.nacl, &key);
+
+                match config.transport.send_to(&udp, Events::GetHeadersAck, &message, &source) {
+                    Ok(_)  => debug!("[CONSOLE_GET_HEADERS] Sending headers to peer {}", source),
+                    Err(e) => error!("[CONSOLE_GET_HEADERS] Error sending headers to peer: {}. Error: {}", source, e),
+                };
+            },
+            None => error!("[CONSOLE_GET_HEADERS] Error getting peer")
+        };
+
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use carina_core_protocol::Payload;
+use carina_core_protocol::payloads::block::TipPayload;
+use carina_core;
+use carina_core::{Config, Event, SyncManager};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+
+pub struct GetTipAck {
+    sync: Arc<Mutex<SyncManager>>
+}
+
+impl GetTipAck {
+    pub fn new(sync: Arc<Mutex<SyncManager>>) -> Self {
+        Self {
+            sync
+        }
+    }
+}
+
+impl Event for GetTipAck {
+    fn execute(&mut self, udp: UdpSocket, source: String, config: &mut Config, buffer: &[u8]) -> Result<(), Error> {
+        let parsed = Parser::parse_payload(&buffer);
+        let parsed = TipPayload::parse(parsed)?;
+        debug!("[CONSOLE_GET_TIP_ACK] Received tip from {}", source);
+
+        let requests = match self.sync.lock() {
+            Ok(mut sync) => sync.on_tip(&source, parsed)?,
+            Err(e)       => return Err(format_err!("Error locking sync. {}", e))
+        };
+        carina_core::send_requests(&udp, config, requests);
+
+        Ok(())
+    }
+}
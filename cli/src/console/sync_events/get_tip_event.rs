@@ -0,0 +1,47 @@
+use carina_core_protocol::{Events, MessageBuilder};
+use carina_core;
+use carina_core::{BlockStorage, Config, Event};
+use failure::Error;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+
+pub struct GetTip {
+    storage: Arc<Mutex<BlockStorage>>
+}
+
+impl GetTip {
+    pub fn new(storage: Arc<Mutex<BlockStorage>>) -> Self {
+        Self {
+            storage
+        }
+    }
+}
+
+impl Event for GetTip {
+    fn execute(&mut self, udp: UdpSocket, source: String, config: &mut Config, _: &[u8]) -> Result<(), Error> {
+        debug!("[CONSOLE_GET_TIP] Received tip request from {}", source);
+
+        let payload = match self.storage.lock() {
+            Ok(storage) => carina_core::get_tip(&*storage),
+            Err(e)      => return Err(format_err!("Error locking storage. {}", e))
+        };
+
+        match config.peers.get(&source) {
+            Some(peer) => {
+                let key = config.seal_key(peer);
+                let message = MessageBuilder::new()
+                    .set_event_code(Events::as_val(Events::GetTipAck))
+                    .set_payload(payload)
+                    .build_precomputed(&mut config.nacl, &key);
+
+                match config.transport.send_to(&udp, Events::GetTipAck, &message, &source) {
+                    Ok(_)  => debug!("[CONSOLE_GET_TIP] Sending tip to peer {}", source),
+                    Err(e) => error!("[CONSOLE_GET_TIP] Error sending tip to peer: {}. Error: {}", source, e),
+                };
+            },
+            None => error!("[CONSOLE_GET_TIP] Error getting peer")
+        };
+
+        Ok(())
+    }
+}
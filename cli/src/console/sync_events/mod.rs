@@ -0,0 +1,13 @@
+mod get_block_ack_event;
+mod get_block_event;
+mod get_headers_ack_event;
+mod get_headers_event;
+mod get_tip_ack_event;
+mod get_tip_event;
+
+pub use self::get_block_ack_event::GetBlockAck;
+pub use self::get_block_event::GetBlock;
+pub use self::get_headers_ack_event::GetHeadersAck;
+pub use self::get_headers_event::GetHeaders;
+pub use self::get_tip_ack_event::GetTipAck;
+pub use self::get_tip_event::GetTip;
@@ -14,6 +14,7 @@ extern crate clap;
 extern crate carina_core;
 extern crate carina_core_protocol;
 extern crate crypto;
+#[macro_use]
 extern crate failure;
 #[macro_use]
 extern crate log;
@@ -24,6 +25,7 @@ extern crate protocol_builder_parser;
 extern crate sodiumoxide;
 extern crate time;
 
+mod chain;
 mod console;
 mod misc;
 
@@ -41,7 +43,7 @@ fn main() {
                 .about("Misc commands")
                 .subcommand(
                     SubCommand::with_name("content")
-                        .about("Adds new content to the next block.")
+                        .about("Adds new content to the next block. Uses the local node if one is running.")
                         .arg(Arg::with_name("CONFIG")
                             .value_name("config")
                             .help("Sets the location of the config file.")
@@ -63,6 +65,23 @@ fn main() {
                         .long("config")
                         .default_value("./config.yml"))
                 )
+                .subcommand(
+                    SubCommand::with_name("proof")
+                        .about("Requests and verifies the proof that content is part of a block.")
+                        .arg(Arg::with_name("CONFIG")
+                            .value_name("config")
+                            .help("Sets the location of the config file.")
+                            .takes_value(true)
+                            .long("config")
+                            .default_value("./config.yml"))
+                        .arg(Arg::with_name("UNIQUE_KEY")
+                            .help("Unique key of the content.")
+                            .takes_value(true)
+                            .required(true))
+                        .arg(Arg::with_name("BLOCK")
+                            .help("Hash of the block containing the content. If omitted, the peers search their chain.")
+                            .takes_value(true))
+                )
                 .subcommand(
                     SubCommand::with_name("genkey")
                         .about("Generates a new secret key")
@@ -72,6 +91,25 @@ fn main() {
                         .about("Generates a new public key from a secret key")
                         .arg(Arg::with_name("secret key").required(true))
                 )
+                .subcommand(
+                    SubCommand::with_name("signkey")
+                        .about("Shows the key for verifying votes, the sign_key of the peer file")
+                        .arg(Arg::with_name("secret key").required(true))
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("chain")
+                .about("Commands for the local chain")
+                .subcommand(
+                    SubCommand::with_name("verify")
+                        .about("Verifies all stored blocks starting with the first block.")
+                        .arg(Arg::with_name("CONFIG")
+                            .value_name("config")
+                            .help("Sets the location of the config file.")
+                            .takes_value(true)
+                            .long("config")
+                            .default_value("./config.yml"))
+                )
         )
         .subcommand(
             SubCommand::with_name("console")
@@ -86,6 +124,7 @@ fn main() {
         .get_matches();
 
     match matches.subcommand() {
+        ("chain", Some(sub_matches))   => chain::execute(sub_matches),
         ("misc", Some(sub_matches))    => misc::execute(sub_matches),
         ("console", Some(sub_matches)) => console::execute(sub_matches),
         _                              => error!("Not valid")
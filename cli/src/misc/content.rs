@@ -1,11 +1,13 @@
 use carina_core_protocol::{Events, MessageBuilder, Payload};
 use carina_core_protocol::payloads::block::NewBlockContentPayload;
 use carina_core;
-use carina_core::{Config, CarinaConfigBuilder};
+use carina_core::{Config, CarinaConfigBuilder, Request, Response};
 use clap::ArgMatches;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
+use std::thread;
+use std::time::Duration;
 
 pub fn execute(args: &ArgMatches) {
     let mut content = String::new();
@@ -24,9 +26,30 @@ pub fn execute(args: &ArgMatches) {
         Err(e)  => panic!("[MISC_CONTENT] Error reading config file {:?}", e)
     };
 
+    let mut payload = NewBlockContentPayload::new();
+    // save, because it is forced by clap
+    payload.content = String::from(args.value_of("CONTENT").unwrap());
+
+    // a node running on this machine adds the content and sends it to its peers
+    let request = Request::Content {
+        unique_key: payload.unique_key.clone(),
+        content: payload.content.clone(),
+    };
+    match carina_core::request(&config.socket, &request) {
+        Ok(Response::Ok(_))    => {
+            info!("[MISC_CONTENT] Added content to the local node");
+            return;
+        },
+        Ok(Response::Error(e)) => {
+            error!("[MISC_CONTENT] Error adding content to the local node. {}", e);
+            return;
+        },
+        Err(e)                 => debug!("[MISC_CONTENT] No local node at {}, sending content to the peers. {}", config.socket, e),
+    };
+
     let carina_config_builder = CarinaConfigBuilder::new()
         .set_config(config);
-    let (_, socket, config) = carina_core::init(carina_config_builder);
+    let (_, socket, config) = carina_core::init_client(carina_config_builder);
 
     let peers = {
         match config.lock() {
@@ -41,20 +64,26 @@ pub fn execute(args: &ArgMatches) {
         let state = config.lock().unwrap();
         state.config.nacl.clone()
     };
-
-    let mut payload = NewBlockContentPayload::new();
-    // save, because it is forced by clap
-    payload.content = String::from(args.value_of("CONTENT").unwrap());
+    let transport = {
+        let state = config.lock().unwrap();
+        state.config.transport.clone()
+    };
 
     for (_, peer) in &peers {
         let message = MessageBuilder::new()
             .set_event_code(Events::as_val(Events::NewBlockContent))
             .set_payload(payload.clone())
-            .build(&mut nacl, &peer.public_key);
+            .build_introduction(&mut nacl, &peer.public_key);
 
-        match socket.send_to(&message, &peer.address) {
+        match transport.send_reliable(&socket, Events::NewBlockContent, &message, &peer.address) {
             Ok(_)  => debug!("[MISC_CONTENT] Added content"),
             Err(e) => error!("[MISC_CONTENT] Error adding content. {}", e),
         };
     }
-}
\ No newline at end of file
+
+    // the content is sent again until every peer acknowledged it or the
+    // transport gave up
+    while transport.pending() > 0 {
+        thread::sleep(Duration::from_millis(100));
+    }
+}
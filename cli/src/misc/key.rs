@@ -1,4 +1,5 @@
 use base64::{decode, encode};
+use carina_core_protocol::Nacl;
 use clap::ArgMatches;
 
 use sodiumoxide::crypto::box_;
@@ -14,4 +15,11 @@ pub fn pubkey(arg: &ArgMatches) {
     let secret_key = SecretKey::from_slice(&decoded).unwrap();
     let public_key = secret_key.public_key();
     println!("{}", encode(&public_key.0));
-}
\ No newline at end of file
+}
+
+pub fn signkey(arg: &ArgMatches) {
+    let decoded = decode(arg.value_of("secret key").unwrap()).unwrap();
+    let secret_key = SecretKey::from_slice(&decoded).unwrap();
+    let sign_key = Nacl::new(secret_key).sign_public_key();
+    println!("{}", encode(&sign_key.0));
+}
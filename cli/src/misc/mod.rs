@@ -2,6 +2,7 @@ use clap::ArgMatches;
 
 mod content;
 mod ping;
+mod proof;
 mod key;
 
 pub fn execute(args: &ArgMatches) {
@@ -9,7 +10,9 @@ pub fn execute(args: &ArgMatches) {
         ("content", Some(sub_matches)) => content::execute(sub_matches),
         ("genkey", Some(sub_matches))  => key::genkey(sub_matches),
         ("pubkey", Some(sub_matches))  => key::pubkey(sub_matches),
+        ("signkey", Some(sub_matches)) => key::signkey(sub_matches),
         ("ping", Some(sub_matches))    => ping::execute(sub_matches),
+        ("proof", Some(sub_matches))   => proof::execute(sub_matches),
         _                              => error!("Not valid")
     }
 }
\ No newline at end of file
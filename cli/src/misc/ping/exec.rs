@@ -35,7 +35,7 @@ pub fn execute(args: &ArgMatches) {
     let carina_config_builder = CarinaConfigBuilder::new()
         .add_event(Events::Pong, Arc::clone(&pong_event))
         .set_config(config);
-    let (_, socket, config) = carina_core::init(carina_config_builder);
+    let (_, socket, config) = carina_core::init_client(carina_config_builder);
 
     let peers = {
         match config.lock() {
@@ -50,14 +50,18 @@ pub fn execute(args: &ArgMatches) {
         let state = config.lock().unwrap();
         state.config.nacl.clone()
     };
+    let transport = {
+        let state = config.lock().unwrap();
+        state.config.transport.clone()
+    };
 
     for (_, peer) in &peers {
         let message = MessageBuilder::new()
             .set_event_code(Events::as_val(Events::Ping))
             .set_payload(EmptyPayload::new())
-            .build(&mut nacl, &peer.public_key);
+            .build_introduction(&mut nacl, &peer.public_key);
 
-        match socket.send_to(&message, &peer.address) {
+        match transport.send_to(&socket, Events::Ping, &message, &peer.address) {
             Ok(_)  => debug!("[MISC_PING] Send ping to peer {}", peer.address),
             Err(e) => error!("[MISC_PING] Error sending ping to peer: {}. Error: {}", peer.address, e),
         };
@@ -0,0 +1,99 @@
+use carina_core_protocol::{Events, MessageBuilder, Payload};
+use carina_core_protocol::payloads::block::GetProofPayload;
+use carina_core;
+use carina_core::{Config, CarinaConfigBuilder};
+use clap::ArgMatches;
+use misc::proof::ProofAck;
+use prettytable::{Attr, color, Table};
+use prettytable::cell::Cell;
+use prettytable::row::Row;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub fn execute(args: &ArgMatches) {
+    let mut content = String::new();
+
+    // unwrap ok. CONFIG has a default value
+    match File::open(args.value_of("CONFIG").unwrap().to_string()) {
+        Ok(mut file) => match file.read_to_string(&mut content) {
+            Ok(_)  => (),
+            Err(e) => panic!("[MISC_PROOF] Error readying config file. {}", e)
+        },
+        Err(e)     => panic!("[MISC_PROOF] Error readying config file. {}", e)
+    };
+
+    let config: Config = match Config::from_str(&content) {
+        Ok(val) => val,
+        Err(e)  => panic!("[MISC_PROOF] Error reading config file {:?}", e)
+    };
+
+    let block = args.value_of("BLOCK").map(String::from);
+    let proof_ack_event = Arc::new(Mutex::new(ProofAck::new(block.clone())));
+    let carina_config_builder = CarinaConfigBuilder::new()
+        .add_event(Events::GetProofAck, Arc::clone(&proof_ack_event))
+        .set_config(config);
+    let (_, socket, config) = carina_core::init_client(carina_config_builder);
+
+    let peers = {
+        match config.lock() {
+            Ok(val) => val.config.peers.clone(),
+            Err(e)  => {
+                error!("[MISC_PROOF] Error locking state. {}", e);
+                HashMap::new()
+            }
+        }
+    };
+    let mut nacl = {
+        let state = config.lock().unwrap();
+        state.config.nacl.clone()
+    };
+    let transport = {
+        let state = config.lock().unwrap();
+        state.config.transport.clone()
+    };
+
+    let mut payload = GetProofPayload::new();
+    // save, because it is forced by clap
+    payload.unique_key = String::from(args.value_of("UNIQUE_KEY").unwrap());
+    payload.block = block.unwrap_or_default();
+
+    for (_, peer) in &peers {
+        let message = MessageBuilder::new()
+            .set_event_code(Events::as_val(Events::GetProof))
+            .set_payload(payload.clone())
+            .build_introduction(&mut nacl, &peer.public_key);
+
+        match transport.send_to(&socket, Events::GetProof, &message, &peer.address) {
+            Ok(_)  => debug!("[MISC_PROOF] Requested proof from peer {}", peer.address),
+            Err(e) => error!("[MISC_PROOF] Error requesting proof from peer: {}. Error: {}", peer.address, e),
+        };
+    }
+
+    info!("[MISC_PROOF] Waiting 10 seconds.");
+    thread::sleep(Duration::from_secs(10));
+    {
+        match proof_ack_event.lock() {
+            Ok(event) => {
+                let mut table = Table::new();
+                table.add_row(row!["Address", "Block", "Proof"]);
+
+                for (key, value) in &event.answered {
+                    let row = match value {
+                        Some((index, true))  => Row::new(vec![Cell::new(key), Cell::new(&index.to_string()), Cell::new("Valid").with_style(Attr::ForegroundColor(color::GREEN))]),
+                        Some((index, false)) => Row::new(vec![Cell::new(key), Cell::new(&index.to_string()), Cell::new("Invalid").with_style(Attr::ForegroundColor(color::RED))]),
+                        None                 => Row::new(vec![Cell::new(key), Cell::new("-"), Cell::new("Not found").with_style(Attr::ForegroundColor(color::RED))])
+                    };
+                    table.add_row(row);
+                }
+
+                table.printstd();
+                ()
+            },
+            Err(_)    => error!("[MISC_PROOF] Error locking mutex.")
+        };
+    }
+}
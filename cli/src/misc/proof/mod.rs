@@ -0,0 +1,5 @@
+mod exec;
+mod proof_ack_event;
+
+pub use self::exec::execute;
+pub use self::proof_ack_event::ProofAck;
@@ -0,0 +1,43 @@
+use carina_core_protocol::{verify_proof, Payload};
+use carina_core_protocol::payloads::block::GetProofAckPayload;
+use carina_core::{Config, Event};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use std::collections::HashMap;
+use std::net::UdpSocket;
+
+pub struct ProofAck {
+    /// hash of the requested block, `None` if any block of the chain may contain the content
+    block: Option<String>,
+    /// peer -> (block index, proof valid), `None` if the content is unknown to the peer
+    pub answered: HashMap<String, Option<(u64, bool)>>
+}
+
+impl ProofAck {
+    pub fn new(block: Option<String>) -> Self {
+        Self {
+            block,
+            answered: HashMap::new()
+        }
+    }
+}
+
+impl Event for ProofAck {
+    fn execute(&mut self, _: UdpSocket, source: String, _: &mut Config, buffer: &[u8]) -> Result<(), Error> {
+        debug!("[MISC_PROOF_ACK] Received proof from {}", source);
+        let parsed = Parser::parse_payload(&buffer);
+        let parsed = GetProofAckPayload::parse(parsed)?;
+
+        if parsed.found {
+            let block_matches = match self.block {
+                Some(ref block) => parsed.header.hash() == *block,
+                None            => true,
+            };
+            let valid = block_matches && verify_proof(&parsed.header, &parsed.entry, &parsed.proof);
+            self.answered.insert(source, Some((parsed.header.index, valid)));
+        } else {
+            self.answered.insert(source, None);
+        }
+        Ok(())
+    }
+}
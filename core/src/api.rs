@@ -0,0 +1,272 @@
+//! Read only HTTP API of the node
+//!
+//! Enabled with the config key `http_api`. All responses are JSON.
+//!
+//! - `/status` -> version, height, tip and number of peers
+//! - `/blocks/{height}` -> a single block
+//! - `/blocks?from=&to=` -> the blocks from `from` to `to`, both included,
+//!   at most `MAX_BLOCKS`
+//! - `/peers` -> all known peers
+//! - `/mempool` -> content for the next block
+use base64::encode;
+use carina_config::CarinaConfig;
+use carina_core_protocol::payloads::block::{Block, NewBlockContentPayload};
+use failure::Error;
+use http;
+use http::{HttpRequest, HttpResponse};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+/// Maximum number of blocks returned by `/blocks`
+pub const MAX_BLOCKS: u64 = 100;
+
+/// Escapes a string and puts it in quotes
+fn string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"'                    => escaped.push_str("\\\""),
+            '\\'                   => escaped.push_str("\\\\"),
+            '\n'                   => escaped.push_str("\\n"),
+            '\r'                   => escaped.push_str("\\r"),
+            '\t'                   => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c                      => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Serializes the entries of a block or the mempool
+fn entries(entries: &[NewBlockContentPayload]) -> String {
+    let entries = entries
+        .iter()
+        .map(|entry| format!("{{\"unique_key\":{},\"content\":{}}}", string(&entry.unique_key), string(&entry.content)))
+        .collect::<Vec<String>>();
+    format!("[{}]", entries.join(","))
+}
+
+/// Serializes a block
+fn block(block: &Block) -> String {
+    format!(
+        "{{\"height\":{},\"hash\":{},\"prev\":{},\"timestamp\":{},\"merkle_root\":{},\"difficulty\":{},\"nonce\":{},\"entries\":{}}}",
+        block.header.index,
+        string(&block.header.hash()),
+        string(&block.header.prev),
+        block.header.timestamp,
+        string(&block.header.merkle_root),
+        block.header.difficulty,
+        block.header.nonce,
+        entries(&block.entries)
+    )
+}
+
+/// Answers `/status`
+fn status(carina_config: &CarinaConfig) -> Result<String, Error> {
+    let config = &carina_config.config;
+    let tip = match carina_config.chain {
+        Some(_) => match carina_config.storage()?.lock() {
+            Ok(val) => val.tip(),
+            Err(e)  => return Err(format_err!("Error locking storage. {}", e)),
+        },
+        None    => None,
+    };
+    let (height, tip) = match tip {
+        Some((height, hash)) => (height.to_string(), string(&hash)),
+        None                 => (String::from("null"), String::from("null")),
+    };
+
+    Ok(format!(
+        "{{\"version\":{},\"uri\":{},\"public_key\":{},\"height\":{},\"tip\":{},\"peers\":{},\"learned_peers\":{}}}",
+        string(env!("CARGO_PKG_VERSION")),
+        string(&config.uri),
+        string(&encode(&config.nacl.public_key().0)),
+        height,
+        tip,
+        config.peers.len(),
+        config.learned_peers.len()
+    ))
+}
+
+/// Answers `/peers`, sorted by the address
+fn peers(carina_config: &CarinaConfig) -> String {
+    let config = &carina_config.config;
+    let mut peers = config.peers.values().collect::<Vec<_>>();
+    peers.sort_by(|a, b| a.address.cmp(&b.address));
+
+    let peers = peers
+        .iter()
+        .map(|peer| format!(
+            "{{\"address\":{},\"public_key\":{},\"learned\":{}}}",
+            string(&peer.address),
+            string(&encode(&peer.public_key.0)),
+            config.learned_peers.contains(&peer.address)
+        ))
+        .collect::<Vec<String>>();
+    format!("[{}]", peers.join(","))
+}
+
+/// Answers `/mempool`, sorted by the unique key
+fn mempool(carina_config: &CarinaConfig) -> Result<String, Error> {
+    let mut content = match carina_config.mempool.lock() {
+        Ok(val) => val.content()
+            .iter()
+            .map(|(unique_key, content)| NewBlockContentPayload { unique_key: unique_key.clone(), content: content.clone() })
+            .collect::<Vec<NewBlockContentPayload>>(),
+        Err(e)  => return Err(format_err!("Error locking mempool. {}", e)),
+    };
+    content.sort_by(|a, b| a.unique_key.cmp(&b.unique_key));
+    Ok(entries(&content))
+}
+
+/// Answers `/blocks/{height}` and `/blocks?from=&to=`
+///
+/// Without `to` the blocks up to the tip are returned.
+fn blocks(carina_config: &Arc<Mutex<CarinaConfig>>, from: u64, to: Option<u64>, single: bool) -> Result<HttpResponse, Error> {
+    // the storage is read without holding the config lock
+    let shared = lock(carina_config)?.storage()?;
+    let storage = match shared.lock() {
+        Ok(val) => val,
+        Err(e)  => return Err(format_err!("Error locking storage. {}", e)),
+    };
+
+    let tip = match storage.tip() {
+        Some((height, _)) => height,
+        None if single    => return Ok(HttpResponse::text(404, "Block not found")),
+        None              => return Ok(HttpResponse::ok("application/json", String::from("[]"))),
+    };
+    let to = to.unwrap_or(tip).min(tip).min(from.saturating_add(MAX_BLOCKS - 1));
+
+    let mut blocks = Vec::new();
+    for height in from..to.saturating_add(1) {
+        match storage.get_by_height(height)? {
+            Some(bytes) => blocks.push(block(&Block::from_bytes(&bytes)?)),
+            None        => break,
+        };
+    }
+
+    if single {
+        match blocks.pop() {
+            Some(block) => Ok(HttpResponse::ok("application/json", block)),
+            None        => Ok(HttpResponse::text(404, "Block not found")),
+        }
+    } else {
+        Ok(HttpResponse::ok("application/json", format!("[{}]", blocks.join(","))))
+    }
+}
+
+/// Parses a height from the path or the query
+fn height(value: Option<&String>) -> Result<Option<u64>, HttpResponse> {
+    match value {
+        Some(value) => match value.parse::<u64>() {
+            Ok(height) => Ok(Some(height)),
+            Err(_)     => Err(HttpResponse::text(400, &format!("Invalid height {}", value))),
+        },
+        None        => Ok(None),
+    }
+}
+
+/// Locks the config
+fn lock(carina_config: &Arc<Mutex<CarinaConfig>>) -> Result<MutexGuard<CarinaConfig>, Error> {
+    match carina_config.lock() {
+        Ok(val) => Ok(val),
+        Err(e)  => Err(format_err!("Error locking carina_config. {}", e)),
+    }
+}
+
+/// Creates the response for a request
+fn route(carina_config: &Arc<Mutex<CarinaConfig>>, request: &HttpRequest) -> Result<HttpResponse, Error> {
+    if request.path.starts_with("/blocks/") {
+        return match request.path["/blocks/".len()..].parse::<u64>() {
+            Ok(height) => blocks(carina_config, height, Some(height), true),
+            Err(_)     => Ok(HttpResponse::text(400, "Invalid height")),
+        };
+    }
+
+    match request.path.as_str() {
+        "/status"  => Ok(HttpResponse::ok("application/json", status(&*lock(carina_config)?)?)),
+        "/peers"   => Ok(HttpResponse::ok("application/json", peers(&*lock(carina_config)?))),
+        "/mempool" => Ok(HttpResponse::ok("application/json", mempool(&*lock(carina_config)?)?)),
+        "/blocks"  => match (height(request.query.get("from")), height(request.query.get("to"))) {
+            (Ok(from), Ok(to)) => {
+                let from = from.unwrap_or(0);
+                match to {
+                    Some(to) if to < from => Ok(HttpResponse::text(400, "to must not be smaller than from")),
+                    _                     => blocks(carina_config, from, to, false),
+                }
+            },
+            (Err(response), _) => Ok(response),
+            (_, Err(response)) => Ok(response),
+        },
+        _          => Ok(HttpResponse::text(404, "Not found")),
+    }
+}
+
+/// Starts the HTTP API if `http_api` is configured
+pub(crate) fn start(carina_config: Arc<Mutex<CarinaConfig>>) -> Option<JoinHandle<()>> {
+    let address = match carina_config.lock() {
+        Ok(val) => val.config.http_api.clone(),
+        Err(e)  => {
+            error!("[THREAD_API] Error locking carina_config. {}", e);
+            return None;
+        }
+    };
+
+    match address {
+        Some(address) => http::serve("THREAD_API", &address, move |request| {
+            match route(&carina_config, request) {
+                Ok(response) => response,
+                Err(e)       => {
+                    error!("[THREAD_API] Error answering {}. {}", request.path, e);
+                    HttpResponse::text(500, &e.to_string())
+                }
+            }
+        }),
+        None          => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Config, Peer};
+    use sodiumoxide::crypto::box_;
+    use std::collections::HashMap;
+
+    fn get(carina_config: &Arc<Mutex<CarinaConfig>>, line: &str) -> HttpResponse {
+        route(carina_config, &HttpRequest::parse(line).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!("\"a \\\"b\\\"\\n\\u0001\"", string("a \"b\"\n\u{1}"));
+    }
+
+    #[test]
+    fn test_peers_and_mempool() {
+        let mut config = Config::default();
+        let peer = Peer {
+            address: String::from("127.0.0.1:45002"),
+            public_key: box_::gen_keypair().0,
+            sign_key: None,
+        };
+        config.peers.insert(peer.address.clone(), peer.clone());
+        let carina_config = Arc::new(Mutex::new(CarinaConfig::new(config, HashMap::new())));
+        carina_config.lock().unwrap().mempool.lock().unwrap().add(String::from("key"), String::from("content"));
+
+        let response = get(&carina_config, "GET /peers HTTP/1.1");
+        assert_eq!(
+            format!("[{{\"address\":\"127.0.0.1:45002\",\"public_key\":\"{}\",\"learned\":false}}]", encode(&peer.public_key.0)),
+            response.body
+        );
+
+        let response = get(&carina_config, "GET /mempool HTTP/1.1");
+        assert_eq!("[{\"unique_key\":\"key\",\"content\":\"content\"}]", response.body);
+
+        assert_eq!(404, get(&carina_config, "GET /unknown HTTP/1.1").status);
+        assert_eq!(400, get(&carina_config, "GET /blocks/latest HTTP/1.1").status);
+        assert_eq!(400, get(&carina_config, "GET /blocks?from=5&to=1 HTTP/1.1").status);
+    }
+}
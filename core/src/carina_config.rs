@@ -1,10 +1,17 @@
 use carina_core_protocol::Events;
-use config::Config;
+use chain::ChainManager;
+use config::{Config, Peer};
 use event::Event;
+use failure::Error;
+use mempool::Mempool;
+use miner::Miner;
+use storage::BlockStorage;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::{Debug, Formatter};
+use std::mem;
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
 
 /// Contains the configuration and all events
@@ -13,12 +20,112 @@ pub struct CarinaConfig {
     pub config: Config,
     /// events to listen
     pub events: HashMap<Events, Vec<Arc<Mutex<Event>>>>,
+    /// chain manager, if the peer keeps a chain
+    pub chain: Option<Arc<Mutex<ChainManager>>>,
+    /// content for the next block
+    pub mempool: Arc<Mutex<Mempool>>,
+    /// miner, if the peer mines blocks
+    pub miner: Option<Arc<Mutex<Miner>>>,
+    /// set as soon as the node should shut down
+    pub shutdown: Arc<AtomicBool>,
 }
 
 impl CarinaConfig {
     /// creates a new instance
     pub fn new(config: Config, events: HashMap<Events, Vec<Arc<Mutex<Event>>>>) -> Self {
-        Self { config, events }
+        Self {
+            config,
+            events,
+            chain: None,
+            mempool: Arc::new(Mutex::new(Mempool::new())),
+            miner: None,
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Storage of the chain
+    ///
+    /// Fails if the peer keeps no chain.
+    pub fn storage(&self) -> Result<Arc<Mutex<BlockStorage>>, Error> {
+        match self.chain {
+            Some(ref chain) => match chain.lock() {
+                Ok(val) => Ok(val.storage()),
+                Err(e)  => Err(format_err!("Error locking chain. {}", e)),
+            },
+            None            => Err(format_err!("The node keeps no chain")),
+        }
+    }
+
+    /// Adds a new peer and writes it to the peer file
+    ///
+    /// A peer that was learned from other peers becomes a configured peer.
+    pub fn add_peer(&mut self, peer: Peer) -> Result<(), Error> {
+        if self.config.peers.contains_key(&peer.address) && !self.config.learned_peers.contains(&peer.address) {
+            return Err(format_err!("Peer {} already exists", peer.address));
+        }
+        self.set_peer(peer)
+    }
+
+    /// Replaces the public key of a known peer and writes it to the peer
+    /// file
+    pub fn update_peer(&mut self, peer: Peer) -> Result<(), Error> {
+        if !self.config.peers.contains_key(&peer.address) {
+            return Err(format_err!("Peer {} does not exist", peer.address));
+        }
+        self.set_peer(peer)
+    }
+
+    /// Removes a peer and writes the remaining peers to the peer file
+    pub fn remove_peer(&mut self, address: &str) -> Result<Peer, Error> {
+        let mut peers = self.config.peers.clone();
+        let removed = match peers.remove(address) {
+            Some(peer) => peer,
+            None       => return Err(format_err!("Peer {} does not exist", address)),
+        };
+        let mut learned_peers = self.config.learned_peers.clone();
+        learned_peers.remove(address);
+
+        self.commit_peers(peers, learned_peers)?;
+        self.config.forget(&removed);
+        Ok(removed)
+    }
+
+    /// Inserts or replaces a configured peer
+    fn set_peer(&mut self, peer: Peer) -> Result<(), Error> {
+        let old = self.config.peers.get(&peer.address).cloned();
+        let mut peers = self.config.peers.clone();
+        peers.insert(peer.address.clone(), peer.clone());
+        let mut learned_peers = self.config.learned_peers.clone();
+        learned_peers.remove(&peer.address);
+
+        self.commit_peers(peers, learned_peers)?;
+        if let Some(old) = old {
+            if old.public_key != peer.public_key {
+                self.config.forget(&old);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the peers if they could be written to the peer files
+    ///
+    /// On error the peers stay unchanged.
+    fn commit_peers(&mut self, peers: HashMap<String, Peer>, learned_peers: HashSet<String>) -> Result<(), Error> {
+        let learned_changed = learned_peers != self.config.learned_peers;
+        let old_peers = mem::replace(&mut self.config.peers, peers);
+        let old_learned = mem::replace(&mut self.config.learned_peers, learned_peers);
+
+        if let Err(e) = self.config.save_peers() {
+            self.config.peers = old_peers;
+            self.config.learned_peers = old_learned;
+            return Err(e);
+        }
+        if learned_changed {
+            if let Err(e) = self.config.save_known_peers() {
+                error!("[CARINA_CONFIG] Error saving known peers. {}", e);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -32,6 +139,9 @@ impl Debug for CarinaConfig {
 pub struct CarinaConfigBuilder {
     config: Config,
     events: HashMap<Events, Vec<Arc<Mutex<Event>>>>,
+    chain: Option<Arc<Mutex<ChainManager>>>,
+    mempool: Option<Arc<Mutex<Mempool>>>,
+    miner: Option<Arc<Mutex<Miner>>>,
 }
 
 impl CarinaConfigBuilder {
@@ -40,6 +150,9 @@ impl CarinaConfigBuilder {
         Self {
             config: Config::default(),
             events: HashMap::new(),
+            chain: None,
+            mempool: None,
+            miner: None,
         }
     }
 
@@ -49,6 +162,29 @@ impl CarinaConfigBuilder {
         self
     }
 
+    /// Sets the chain manager
+    ///
+    /// Handlers registered for `Events::Reorg` are notified after a
+    /// reorganisation, see `notify_reorgs`.
+    pub fn set_chain(mut self, chain: Arc<Mutex<ChainManager>>) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// Sets the mempool
+    ///
+    /// Content submitted over the control socket is added to it.
+    pub fn set_mempool(mut self, mempool: Arc<Mutex<Mempool>>) -> Self {
+        self.mempool = Some(mempool);
+        self
+    }
+
+    /// Sets the miner, its hash rate is part of the metrics
+    pub fn set_miner(mut self, miner: Arc<Mutex<Miner>>) -> Self {
+        self.miner = Some(miner);
+        self
+    }
+
     /// Adds a new event
     pub fn add_event<T: Event + 'static>(mut self, events: Events, event: Arc<Mutex<T>>) -> Self {
         match self.events.entry(events) {
@@ -63,7 +199,13 @@ impl CarinaConfigBuilder {
 
     /// Creates a new carina config instance
     pub fn build(self) -> CarinaConfig {
-        CarinaConfig::new(self.config, self.events)
+        let mut carina_config = CarinaConfig::new(self.config, self.events);
+        carina_config.chain = self.chain;
+        carina_config.miner = self.miner;
+        if let Some(mempool) = self.mempool {
+            carina_config.mempool = mempool;
+        }
+        carina_config
     }
 }
 
@@ -72,3 +214,84 @@ impl Debug for CarinaConfigBuilder {
         write!(f, "Carina: {{ config: {:?} }}", self.config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sodiumoxide::crypto::box_;
+    use std::env;
+    use std::fs::remove_file;
+    use std::process;
+
+    fn carina_config(name: &str) -> CarinaConfig {
+        let path = env::temp_dir().join(format!("carina_peers_{}_{}", name, process::id()));
+        let mut config = Config::default();
+        config.peer_path = path.to_str().unwrap().to_string();
+        CarinaConfig::new(config, HashMap::new())
+    }
+
+    fn peer(address: &str) -> Peer {
+        Peer {
+            address: address.to_string(),
+            public_key: box_::gen_keypair().0,
+            sign_key: None,
+        }
+    }
+
+    #[test]
+    fn test_add_update_remove() {
+        let mut carina_config = carina_config("manage");
+        let first = peer("127.0.0.1:45002");
+
+        carina_config.add_peer(first.clone()).unwrap();
+        assert!(carina_config.add_peer(first.clone()).is_err());
+
+        let mut reloaded = Config::default();
+        reloaded.peer_path = carina_config.config.peer_path.clone();
+        reloaded.load_peers().unwrap();
+        assert_eq!(Some(&first), reloaded.peers.get(&first.address));
+
+        let updated = Peer {
+            address: first.address.clone(),
+            public_key: box_::gen_keypair().0,
+            sign_key: None,
+        };
+        carina_config.update_peer(updated.clone()).unwrap();
+        assert_eq!(Some(&updated), carina_config.config.peers.get(&first.address));
+        assert!(carina_config.update_peer(peer("127.0.0.1:45003")).is_err());
+
+        assert_eq!(updated, carina_config.remove_peer(&first.address).unwrap());
+        assert!(carina_config.remove_peer(&first.address).is_err());
+        reloaded.load_peers().unwrap();
+        assert!(reloaded.peers.is_empty());
+
+        remove_file(&carina_config.config.peer_path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_write_keeps_peers() {
+        let mut carina_config = carina_config("failed");
+        carina_config.config.peer_path = env::temp_dir()
+            .join("carina_missing_directory")
+            .join("peers.yml")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        assert!(carina_config.add_peer(peer("127.0.0.1:45002")).is_err());
+        assert!(carina_config.config.peers.is_empty());
+    }
+
+    #[test]
+    fn test_add_learned_peer() {
+        let mut carina_config = carina_config("learned");
+        let learned = peer("127.0.0.1:45002");
+        carina_config.config.peers.insert(learned.address.clone(), learned.clone());
+        carina_config.config.learned_peers.insert(learned.address.clone());
+
+        carina_config.add_peer(learned.clone()).unwrap();
+        assert!(carina_config.config.learned_peers.is_empty());
+
+        remove_file(&carina_config.config.peer_path).unwrap();
+    }
+}
@@ -0,0 +1,675 @@
+//! Manages the main chain and competing branches
+use carina_core_protocol::payloads::block::{Block, BlockHeader, ReorgPayload};
+use carina_core_protocol::Payload;
+use config::Config;
+use difficulty::{block_work, Retarget};
+use event::Event;
+use failure::Error;
+use orphan::OrphanPool;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use storage::BlockStorage;
+use validation::{latest_timestamp, read_header, validate_block};
+
+/// Side blocks that are this far below the tip are dropped
+const MAX_SIDE_DEPTH: u64 = 100;
+/// Maximum number of side blocks kept in memory
+const MAX_SIDE_BLOCKS: usize = 1024;
+
+/// Result of adding a block to the chain
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChainUpdate {
+    /// The block is already known
+    Known,
+    /// The block was appended to the main chain
+    Extended {
+        /// height of the new block
+        height: u64,
+    },
+    /// The block was added to a side branch that has less work than the
+    /// main chain
+    SideBranch {
+        /// height of the new block
+        height: u64,
+    },
+    /// The parent of the block is not known
+    Orphan,
+    /// The block made a side branch the main chain
+    Reorganised(ReorgPayload),
+}
+
+/// Block that is not part of the main chain
+#[derive(Clone, Debug)]
+struct SideBlock {
+    /// the block itself
+    block: Block,
+    /// height of the block
+    height: u64,
+    /// work of the branch up to and including this block
+    work: u128,
+}
+
+/// Keeps track of the main chain and of side branches
+///
+/// The main chain is the branch with the most cumulative work and is the
+/// only branch written to the block storage. Side branches are kept in
+/// memory. As soon as a side branch has more work than the main chain, the
+/// storage is reorganised. The handlers registered for `Events::Reorg` are
+/// notified with `notify_reorgs`, after the lock of the chain is released.
+pub struct ChainManager {
+    /// storage containing the main chain
+    storage: Arc<Mutex<BlockStorage>>,
+    /// cumulative work of the main chain for every height
+    main_work: Vec<u128>,
+    /// blocks of all side branches by their hash
+    side: HashMap<String, SideBlock>,
+    /// maximum number of side blocks
+    max_side_blocks: usize,
+    /// blocks whose parent is not known yet
+    orphans: OrphanPool,
+    /// handlers that are notified about reorganisations
+    listeners: Vec<Arc<Mutex<Event>>>,
+    /// reorganisations the listeners were not notified about yet
+    reorgs: Vec<ReorgPayload>,
+    /// rules for adjusting the difficulty
+    retarget: Retarget,
+    /// seconds a block may be ahead of the local time
+    max_time_drift: u64,
+}
+
+impl ChainManager {
+    /// Creates a new instance for the chain in the given storage
+    ///
+    /// # Params
+    /// - `storage` -> storage containing the main chain
+    /// - `retarget` -> rules for adjusting the difficulty
+    /// - `max_time_drift` -> seconds a block may be ahead of the local time
+    pub fn new(storage: Arc<Mutex<BlockStorage>>, retarget: Retarget, max_time_drift: u64) -> Result<Self, Error> {
+        let mut main_work = Vec::new();
+        {
+            let storage = match storage.lock() {
+                Ok(storage) => storage,
+                Err(e)      => return Err(format_err!("Error locking storage. {}", e)),
+            };
+
+            if let Some((tip, _)) = storage.tip() {
+                let mut total = 0;
+                for height in 0..tip + 1 {
+                    total += block_work(&read_header(&*storage, height)?);
+                    main_work.push(total);
+                }
+            }
+        }
+
+        Ok(Self {
+            storage,
+            main_work,
+            side: HashMap::new(),
+            max_side_blocks: MAX_SIDE_BLOCKS,
+            orphans: OrphanPool::default(),
+            listeners: Vec::new(),
+            reorgs: Vec::new(),
+            retarget,
+            max_time_drift,
+        })
+    }
+
+    /// Sets the handlers that are notified about reorganisations
+    pub fn set_listeners(&mut self, listeners: Vec<Arc<Mutex<Event>>>) {
+        self.listeners = listeners;
+    }
+
+    /// Storage containing the main chain
+    pub fn storage(&self) -> Arc<Mutex<BlockStorage>> {
+        Arc::clone(&self.storage)
+    }
+
+    /// Rules for adjusting the difficulty
+    pub fn retarget(&self) -> Retarget {
+        self.retarget
+    }
+
+    /// Cumulative work of the main chain
+    pub fn work(&self) -> u128 {
+        self.main_work.last().cloned().unwrap_or(0)
+    }
+
+    /// Difficulty the block following the current tip must have
+    pub fn next_difficulty(&self) -> Result<u32, Error> {
+        let storage = match self.storage.lock() {
+            Ok(storage) => storage,
+            Err(e)      => return Err(format_err!("Error locking storage. {}", e)),
+        };
+
+        match storage.tip() {
+            Some((height, hash)) => {
+                let prev = read_header(&*storage, height)?;
+                self.required_difficulty(&*storage, Some((&hash, &prev)))
+            },
+            None                 => self.required_difficulty(&*storage, None),
+        }
+    }
+
+    /// Validates the given block and adds it to the chain
+    ///
+    /// Blocks whose parent is not known are kept in the orphan pool and
+    /// added as soon as their parent is added. Returns an error if the
+    /// block is not valid.
+    pub fn add_block(&mut self, block: Block) -> Result<ChainUpdate, Error> {
+        let hash = block.hash();
+        let update = self.insert(block.clone())?;
+
+        match update {
+            ChainUpdate::Orphan => if self.orphans.add(block) {
+                debug!("[CHAIN] Added block {} to the orphan pool", hash);
+            },
+            ChainUpdate::Known  => (),
+            _                   => {
+                self.announce(&update);
+                self.connect_orphans(hash);
+            }
+        };
+
+        Ok(update)
+    }
+
+    /// Number of blocks waiting for their parent
+    pub fn orphan_count(&self) -> usize {
+        self.orphans.len()
+    }
+
+    /// Remembers the reorganisation for the listeners
+    fn announce(&mut self, update: &ChainUpdate) {
+        if let ChainUpdate::Reorganised(ref reorg) = *update {
+            info!("[CHAIN] Reorganised chain from height {}. New tip {}", reorg.height, reorg.new_tip);
+            if !self.listeners.is_empty() {
+                self.reorgs.push(reorg.clone());
+            }
+        }
+    }
+
+    /// Adds all orphans that descend from the given block
+    fn connect_orphans(&mut self, parent: String) {
+        let mut parents = vec![parent];
+
+        while let Some(parent) = parents.pop() {
+            for block in self.orphans.take_children(&parent) {
+                let hash = block.hash();
+                match self.insert(block) {
+                    Ok(ChainUpdate::Orphan) => (),
+                    Ok(update)              => {
+                        debug!("[CHAIN] Connected orphan block {}. {:?}", hash, update);
+                        self.announce(&update);
+                        parents.push(hash);
+                    },
+                    Err(e)                  => error!("[CHAIN] Orphan block {} is invalid. {}", hash, e),
+                };
+            }
+        }
+    }
+
+    /// Adds the block to the main chain or to a side branch
+    fn insert(&mut self, block: Block) -> Result<ChainUpdate, Error> {
+        let hash = block.hash();
+        let storage = Arc::clone(&self.storage);
+        let mut storage = match storage.lock() {
+            Ok(storage) => storage,
+            Err(e)      => return Err(format_err!("Error locking storage. {}", e)),
+        };
+
+        if self.side.contains_key(&hash) || storage.get_height(&hash).is_some() {
+            return Ok(ChainUpdate::Known);
+        }
+
+        let tip = storage.tip();
+        let extends_tip = match tip {
+            Some((_, ref tip_hash)) => *tip_hash == block.header.prev,
+            None                    => block.header.index == 0,
+        };
+
+        if extends_tip {
+            let prev = match tip {
+                Some((height, _)) => Some(read_header(&*storage, height)?),
+                None              => None,
+            };
+            let difficulty = self.required_difficulty(&*storage, prev.as_ref().map(|prev| (&*block.header.prev, prev)))?;
+            validate_block(prev.as_ref(), difficulty, latest_timestamp(self.max_time_drift), &hash, &block)?;
+
+            let work = self.work() + block_work(&block.header);
+            let height = storage.append(&hash, &block.to_bytes())?;
+            self.main_work.push(work);
+            self.prune(height);
+            return Ok(ChainUpdate::Extended { height });
+        }
+
+        let (parent, parent_work) = if block.header.index == 0 {
+            (None, 0)
+        } else if let Some(height) = storage.get_height(&block.header.prev) {
+            (Some(read_header(&*storage, height)?), self.main_work[height as usize])
+        } else if let Some(parent) = self.side.get(&block.header.prev) {
+            (Some(parent.block.header.clone()), parent.work)
+        } else {
+            return Ok(ChainUpdate::Orphan);
+        };
+        let difficulty = self.required_difficulty(&*storage, parent.as_ref().map(|parent| (&*block.header.prev, parent)))?;
+        validate_block(parent.as_ref(), difficulty, latest_timestamp(self.max_time_drift), &hash, &block)?;
+
+        let height = block.header.index;
+        let work = parent_work + block_work(&block.header);
+        self.side.insert(hash.clone(), SideBlock { block, height, work });
+        self.limit_side_blocks(Some(&hash));
+
+        if work <= self.work() {
+            debug!("[CHAIN] Added block {} to a side branch", hash);
+            return Ok(ChainUpdate::SideBranch { height });
+        }
+
+        let reorg = self.reorganise(&mut *storage, &hash)?;
+        Ok(ChainUpdate::Reorganised(reorg))
+    }
+
+    /// Difficulty a block following the given parent must have
+    ///
+    /// The parent is given as hash and header and can be part of the main
+    /// chain or of a side branch.
+    fn required_difficulty(&self, storage: &BlockStorage, parent: Option<(&str, &BlockHeader)>) -> Result<u32, Error> {
+        match parent {
+            Some((hash, header)) => self.retarget.required_difficulty(Some(header), |height| self.ancestor(storage, hash, height)),
+            None                 => self.retarget.required_difficulty(None, |height| read_header(storage, height)),
+        }
+    }
+
+    /// Finds the header at the given height on the branch ending in `hash`
+    fn ancestor(&self, storage: &BlockStorage, hash: &str, height: u64) -> Result<BlockHeader, Error> {
+        let mut current = hash.to_string();
+
+        while let Some(side) = self.side.get(&current) {
+            if side.height == height {
+                return Ok(side.block.header.clone());
+            }
+            current = side.block.header.prev.clone();
+        }
+
+        match storage.get_height(&current) {
+            Some(main_height) if height <= main_height => read_header(storage, height),
+            _                                           => Err(format_err!("Block {} of branch {} is missing", height, hash)),
+        }
+    }
+
+    /// Replaces the main chain with the branch ending in the given side block
+    ///
+    /// The replaced blocks of the main chain become a side branch. If the
+    /// storage fails, the old main chain is written back and the chain is
+    /// left unchanged.
+    fn reorganise(&mut self, storage: &mut BlockStorage, new_tip: &str) -> Result<ReorgPayload, Error> {
+        let mut branch = Vec::new();
+        let mut current = new_tip.to_string();
+        while let Some(side) = self.side.get(&current) {
+            branch.push((current.clone(), side.clone()));
+            current = side.block.header.prev.clone();
+        }
+        branch.reverse();
+
+        let height = match branch.first() {
+            Some((_, side)) => side.height,
+            None            => return Err(format_err!("Branch of {} is empty", new_tip)),
+        };
+
+        let mut removed = Vec::new();
+        let old_tip = match storage.tip() {
+            Some((tip, hash)) => {
+                for removed_height in height..tip + 1 {
+                    let hash = match storage.get_hash(removed_height) {
+                        Some(hash) => hash,
+                        None       => return Err(format_err!("Block {} is missing", removed_height)),
+                    };
+                    let block = read_block(storage, removed_height)?;
+                    removed.push((hash, SideBlock {
+                        block,
+                        height: removed_height,
+                        work: self.main_work[removed_height as usize],
+                    }));
+                }
+                hash
+            },
+            None              => String::new(),
+        };
+
+        // the memory state is only changed after the storage holds the new
+        // branch
+        if let Err(e) = replace_blocks(storage, height, &branch) {
+            if let Err(restore_error) = replace_blocks(storage, height, &removed) {
+                error!("[CHAIN] Error restoring the main chain from height {}. {}", height, restore_error);
+            }
+            return Err(e);
+        }
+
+        self.main_work.truncate(height as usize);
+        for (hash, side) in &branch {
+            self.side.remove(hash);
+            self.main_work.push(side.work);
+        }
+        for (hash, side) in &removed {
+            self.side.insert(hash.clone(), side.clone());
+        }
+
+        if let Some((tip, _)) = storage.tip() {
+            self.prune(tip);
+        }
+
+        Ok(ReorgPayload {
+            height,
+            old_tip,
+            new_tip: new_tip.to_string(),
+            disconnected: removed.into_iter().map(|(hash, _)| hash).collect(),
+            connected: branch.into_iter().map(|(hash, _)| hash).collect(),
+        })
+    }
+
+    /// Drops side blocks that are too far below the tip or exceed the limit
+    fn prune(&mut self, tip: u64) {
+        self.side.retain(|_, side| side.height + MAX_SIDE_DEPTH >= tip);
+        self.limit_side_blocks(None);
+    }
+
+    /// Drops side blocks until there are no more than the maximum
+    ///
+    /// Only the ends of side branches are dropped, so the remaining
+    /// branches stay connected. The branch end with the least work goes
+    /// first.
+    ///
+    /// # Params
+    /// - `keep` -> hash of a block that must not be dropped
+    fn limit_side_blocks(&mut self, keep: Option<&str>) {
+        while self.side.len() > self.max_side_blocks {
+            let weakest = {
+                let parents: HashSet<&str> = self.side.values().map(|side| &*side.block.header.prev).collect();
+                self.side
+                    .iter()
+                    .filter(|(hash, _)| !parents.contains(hash.as_str()) && Some(hash.as_str()) != keep)
+                    .min_by(|(hash_a, a), (hash_b, b)| a.work.cmp(&b.work).then_with(|| hash_a.cmp(hash_b)))
+                    .map(|(hash, _)| hash.clone())
+            };
+
+            match weakest {
+                Some(hash) => {
+                    debug!("[CHAIN] Dropping side block {}", hash);
+                    self.side.remove(&hash);
+                },
+                None       => break,
+            }
+        }
+    }
+
+}
+
+/// Calls the reorg handlers for all reorganisations since the last call
+///
+/// The handlers run after the lock of the chain is released, so they may
+/// use the chain themselves.
+///
+/// # Params
+/// - `chain` -> chain manager
+/// - `socket` -> socket handed to the handlers
+/// - `config` -> current configuration, every handler gets its own copy
+pub fn notify_reorgs(chain: &Arc<Mutex<ChainManager>>, socket: &UdpSocket, config: &Config) {
+    let (reorgs, listeners) = match chain.lock() {
+        Ok(mut chain) => (chain.reorgs.drain(..).collect::<Vec<ReorgPayload>>(), chain.listeners.clone()),
+        Err(e)        => {
+            error!("[CHAIN] Error locking chain manager. {}", e);
+            return;
+        }
+    };
+
+    for reorg in reorgs {
+        let payload = reorg.to_bytes();
+
+        for listener in &listeners {
+            let socket = match socket.try_clone() {
+                Ok(socket) => socket,
+                Err(e)     => {
+                    error!("[CHAIN] Error cloning socket. {}", e);
+                    continue;
+                }
+            };
+            let mut config = config.clone();
+
+            match listener.lock() {
+                Ok(mut listener) => {
+                    if let Err(e) = listener.execute(socket, config.uri.clone(), &mut config, &payload) {
+                        error!("[CHAIN] Error calling reorg handler {:?}", e);
+                    }
+                },
+                Err(_)           => error!("[CHAIN] Error locking mutex."),
+            };
+        }
+    }
+}
+
+impl Debug for ChainManager {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "ChainManager: {{ height: {}, side_blocks: {} }}", self.main_work.len(), self.side.len())
+    }
+}
+
+/// Replaces all blocks of the storage starting with the given height
+fn replace_blocks(storage: &mut BlockStorage, height: u64, blocks: &[(String, SideBlock)]) -> Result<(), Error> {
+    storage.truncate(height)?;
+    for (hash, side) in blocks {
+        storage.append(hash, &side.block.clone().to_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads the block at the given height
+fn read_block(storage: &BlockStorage, height: u64) -> Result<Block, Error> {
+    match storage.get_by_height(height)? {
+        Some(bytes) => Block::from_bytes(&bytes),
+        None        => Err(format_err!("Block {} is missing", height)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carina_core_protocol::payloads::block::NewBlockContentPayload;
+    use difficulty::meets_target;
+    use storage::MemoryStorage;
+    use validation::{InvalidBlock, DEFAULT_MAX_TIME_DRIFT};
+
+    /// Storage that fails to append the block with the given hash
+    struct FailingStorage {
+        storage: MemoryStorage,
+        fail: String,
+    }
+
+    impl BlockStorage for FailingStorage {
+        fn append(&mut self, hash: &str, block: &[u8]) -> Result<u64, Error> {
+            if hash == self.fail {
+                return Err(format_err!("Disk full"));
+            }
+            self.storage.append(hash, block)
+        }
+
+        fn get_by_height(&self, height: u64) -> Result<Option<Vec<u8>>, Error> {
+            self.storage.get_by_height(height)
+        }
+
+        fn get_by_hash(&self, hash: &str) -> Result<Option<Vec<u8>>, Error> {
+            self.storage.get_by_hash(hash)
+        }
+
+        fn get_hash(&self, height: u64) -> Option<String> {
+            self.storage.get_hash(height)
+        }
+
+        fn get_height(&self, hash: &str) -> Option<u64> {
+            self.storage.get_height(hash)
+        }
+
+        fn truncate(&mut self, height: u64) -> Result<(), Error> {
+            self.storage.truncate(height)
+        }
+
+        fn tip(&self) -> Option<(u64, String)> {
+            self.storage.tip()
+        }
+
+        fn put_certificate(&mut self, hash: &str, certificate: &[u8]) -> Result<(), Error> {
+            self.storage.put_certificate(hash, certificate)
+        }
+
+        fn get_certificate(&self, hash: &str) -> Result<Option<Vec<u8>>, Error> {
+            self.storage.get_certificate(hash)
+        }
+    }
+
+    fn mine(index: u64, prev: String, content: &str) -> Block {
+        let entries = vec![NewBlockContentPayload {
+            unique_key: format!("key_{}", index),
+            content: content.to_string(),
+        }];
+        let mut block = Block::new(index, prev, entries);
+        while !meets_target(&block.hash(), block.header.difficulty) {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    #[test]
+    fn test_reorganise() {
+        let storage: Arc<Mutex<BlockStorage>> = Arc::new(Mutex::new(MemoryStorage::new()));
+        let mut chain = ChainManager::new(Arc::clone(&storage), Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap();
+
+        let genesis = mine(0, "0".repeat(64), "genesis");
+        let main_1 = mine(1, genesis.hash(), "main");
+        let side_1 = mine(1, genesis.hash(), "side");
+        let side_2 = mine(2, side_1.hash(), "side");
+
+        assert_eq!(ChainUpdate::Extended { height: 0 }, chain.add_block(genesis.clone()).unwrap());
+        assert_eq!(ChainUpdate::Extended { height: 1 }, chain.add_block(main_1.clone()).unwrap());
+        assert_eq!(ChainUpdate::Known, chain.add_block(main_1.clone()).unwrap());
+        assert_eq!(ChainUpdate::SideBranch { height: 1 }, chain.add_block(side_1.clone()).unwrap());
+
+        let expected = ReorgPayload {
+            height: 1,
+            old_tip: main_1.hash(),
+            new_tip: side_2.hash(),
+            disconnected: vec![main_1.hash()],
+            connected: vec![side_1.hash(), side_2.hash()],
+        };
+        assert_eq!(ChainUpdate::Reorganised(expected), chain.add_block(side_2.clone()).unwrap());
+
+        let storage = storage.lock().unwrap();
+        assert_eq!(Some((2, side_2.hash())), storage.tip());
+        assert_eq!(Some(side_1.hash()), storage.get_hash(1));
+        assert_eq!(None, storage.get_height(&main_1.hash()));
+    }
+
+    #[test]
+    fn test_reorganise_rollback() {
+        let genesis = mine(0, "0".repeat(64), "genesis");
+        let main_1 = mine(1, genesis.hash(), "main");
+        let side_1 = mine(1, genesis.hash(), "side");
+        let side_2 = mine(2, side_1.hash(), "side");
+
+        let storage: Arc<Mutex<BlockStorage>> = Arc::new(Mutex::new(FailingStorage {
+            storage: MemoryStorage::new(),
+            fail: side_2.hash(),
+        }));
+        let mut chain = ChainManager::new(Arc::clone(&storage), Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap();
+
+        chain.add_block(genesis).unwrap();
+        chain.add_block(main_1.clone()).unwrap();
+        chain.add_block(side_1.clone()).unwrap();
+        let work = chain.work();
+
+        assert!(chain.add_block(side_2).is_err());
+        assert_eq!(work, chain.work());
+        assert_eq!(Some((1, main_1.hash())), storage.lock().unwrap().tip());
+        assert_eq!(ChainUpdate::Known, chain.add_block(side_1).unwrap());
+    }
+
+    #[test]
+    fn test_connect_orphans() {
+        let storage: Arc<Mutex<BlockStorage>> = Arc::new(Mutex::new(MemoryStorage::new()));
+        let mut chain = ChainManager::new(Arc::clone(&storage), Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap();
+
+        let genesis = mine(0, "0".repeat(64), "genesis");
+        let block_1 = mine(1, genesis.hash(), "main");
+        let block_2 = mine(2, block_1.hash(), "main");
+
+        assert_eq!(ChainUpdate::Orphan, chain.add_block(block_2.clone()).unwrap());
+        assert_eq!(ChainUpdate::Orphan, chain.add_block(block_1.clone()).unwrap());
+        assert_eq!(2, chain.orphan_count());
+
+        assert_eq!(ChainUpdate::Extended { height: 0 }, chain.add_block(genesis).unwrap());
+        assert_eq!(0, chain.orphan_count());
+        assert_eq!(Some((2, block_2.hash())), storage.lock().unwrap().tip());
+    }
+
+    #[test]
+    fn test_limit_side_blocks() {
+        let storage: Arc<Mutex<BlockStorage>> = Arc::new(Mutex::new(MemoryStorage::new()));
+        let mut chain = ChainManager::new(Arc::clone(&storage), Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap();
+        chain.max_side_blocks = 2;
+
+        let genesis = mine(0, "0".repeat(64), "genesis");
+        let main_1 = mine(1, genesis.hash(), "main");
+        let main_2 = mine(2, main_1.hash(), "main");
+        chain.add_block(genesis.clone()).unwrap();
+        chain.add_block(main_1).unwrap();
+        chain.add_block(main_2).unwrap();
+
+        let siblings: Vec<Block> = (0..3).map(|i| mine(1, genesis.hash(), &format!("side_{}", i))).collect();
+        for sibling in &siblings {
+            assert_eq!(ChainUpdate::SideBranch { height: 1 }, chain.add_block(sibling.clone()).unwrap());
+        }
+        assert_eq!(2, chain.side.len());
+        assert!(chain.side.contains_key(&siblings[2].hash()));
+
+        // the end of a longer branch is kept, the other sibling is dropped
+        let child = mine(2, siblings[2].hash(), "side");
+        assert_eq!(ChainUpdate::SideBranch { height: 2 }, chain.add_block(child.clone()).unwrap());
+        assert_eq!(2, chain.side.len());
+        assert!(chain.side.contains_key(&siblings[2].hash()));
+        assert!(chain.side.contains_key(&child.hash()));
+    }
+
+    #[test]
+    fn test_invalid_block() {
+        let storage: Arc<Mutex<BlockStorage>> = Arc::new(Mutex::new(MemoryStorage::new()));
+        let mut chain = ChainManager::new(storage, Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap();
+
+        let mut genesis = mine(0, "0".repeat(64), "genesis");
+        genesis.entries.clear();
+        assert!(chain.add_block(genesis).is_err());
+    }
+
+    #[test]
+    fn test_future_block() {
+        let storage: Arc<Mutex<BlockStorage>> = Arc::new(Mutex::new(MemoryStorage::new()));
+        let mut chain = ChainManager::new(Arc::clone(&storage), Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap();
+
+        let genesis = mine(0, "0".repeat(64), "genesis");
+        chain.add_block(genesis.clone()).unwrap();
+
+        let mut future = Block::new(1, genesis.hash(), Vec::new());
+        future.header.timestamp = u64::max_value();
+        while !meets_target(&future.hash(), future.header.difficulty) {
+            future.header.nonce += 1;
+        }
+        let error = chain.add_block(future).unwrap_err();
+        match error.downcast::<InvalidBlock>() {
+            Ok(InvalidBlock::Future { .. }) => (),
+            result                          => panic!("Unexpected result {:?}", result),
+        };
+
+        // honest blocks are still accepted
+        let block = mine(1, genesis.hash(), "main");
+        assert_eq!(ChainUpdate::Extended { height: 1 }, chain.add_block(block).unwrap());
+    }
+}
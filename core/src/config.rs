@@ -1,11 +1,23 @@
-use base64::decode;
+use base64::{decode, encode};
 use carina_core_protocol::Nacl;
+use difficulty::Retarget;
+use discovery::TrustPolicy;
 use failure::Error;
-use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{PublicKey, SecretKey};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use keys::KeyCache;
+use log;
+use log::LevelFilter;
+use metrics::Metrics;
+use quorum::{Quorum, DEFAULT_MIN_VOTERS};
+use replay::ReplayProtection;
+use session::Sessions;
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{PrecomputedKey, PublicKey, SecretKey};
+use sodiumoxide::crypto::sign;
+use std::collections::{HashMap, HashSet};
+use std::fs::{rename, File};
+use std::io::{Read, Write};
 use std::path::Path;
+use transport::Transport;
+use validation::DEFAULT_MAX_TIME_DRIFT;
 use yaml_rust::{Yaml, YamlLoader};
 
 /// Parses the configuration files.
@@ -16,8 +28,26 @@ use yaml_rust::{Yaml, YamlLoader};
 /// socket: /tmp/carina.sock
 /// peers: ./example_peers.yml
 /// storage: ./block_data
+/// storage_backend: file
+/// retarget_interval: 10
+/// target_block_time: 120
+/// max_time_drift: 600
+/// miner_threads: 2
+/// quorum: 2/3
+/// quorum_timeout: 30
+/// min_voters: 3
 /// uri: 0.0.0.0:45000
 /// secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY=
+/// nonce_file: ./nonce
+/// rekey_interval: 3600
+/// trust_policy: seeds
+/// known_peers: ./known_peers.yml
+/// log_level: info
+/// clients:
+///   - /gfCzCrTj02YA+dAXCY2EODAYZFELeKH1bec5nenbU0=
+/// client_secret_key: v+rETx4VtczK/QSvl9OBfJfgVPEdjNpquVUq/8GFmWo=
+/// http_api: 45080
+/// metrics: 45090
 /// ```
 ///
 /// # Example peers config
@@ -30,18 +60,71 @@ use yaml_rust::{Yaml, YamlLoader};
 /// ```
 #[derive(Clone, Debug)]
 pub struct Config {
-    /// path to the socket file
+    /// path of the unix socket local tools use to control the node
     pub socket: String,
     /// path to the peers config file
     pub peer_path: String,
     /// block data storage location
     pub storage: String,
+    /// backend that is used for storing blocks
+    pub storage_backend: StorageBackend,
+    /// rules for adjusting the proof of work difficulty
+    pub retarget: Retarget,
+    /// seconds a block may be ahead of the local time
+    pub max_time_drift: u64,
+    /// number of threads used for mining
+    pub miner_threads: usize,
+    /// share of all peers that must accept a block
+    pub quorum: Quorum,
+    /// seconds after that a validation round is given up
+    pub quorum_timeout: u64,
+    /// number of peers with a signing key, including this peer, that are
+    /// needed to start a validation round
+    pub min_voters: usize,
     /// uri to listen on
     pub uri: String,
     /// vector of all peers to connect
     pub peers: HashMap<String, Peer>,
+    /// peers that are accepted when they are learned from other peers
+    pub trust_policy: TrustPolicy,
+    /// file the learned peers are persisted in, if any
+    pub known_peers: Option<String>,
+    /// addresses of the peers that were learned from other peers
+    pub learned_peers: HashSet<String>,
+    /// maximum level of log messages, if set
+    pub log_level: Option<LevelFilter>,
+    /// maximum level of log messages when the config was read, used again
+    /// if `log_level` is removed
+    pub default_log_level: LevelFilter,
+    /// file the nonce counter is persisted in, if any
+    pub nonce_file: Option<String>,
+    /// seconds after that a new session key is negotiated with a peer
+    pub rekey_interval: u64,
+    /// public keys of clients that may send the events of `Events::is_client`
+    pub clients: Vec<PublicKey>,
+    /// identity of the cli when it acts as a client of the peers, see
+    /// `init_client`
+    pub client_nacl: Option<Nacl>,
+    /// address of the HTTP API, disabled if not set
+    ///
+    /// Only a port binds the API to localhost.
+    pub http_api: Option<String>,
+    /// address of the metrics listener, disabled if not set
+    ///
+    /// Only a port binds the listener to localhost.
+    pub metrics_address: Option<String>,
     /// nacl instance containing the secret key and the nonce
     pub nacl: Nacl,
+    /// handle for sending messages to peers
+    pub transport: Transport,
+    /// keys shared with the peers
+    pub keys: KeyCache,
+    /// nonces already received from the peers
+    pub replay: ReplayProtection,
+    /// forward secret session keys of the peers
+    pub sessions: Sessions,
+    /// counters of the node
+    pub metrics: Metrics,
 }
 
 impl Config {
@@ -53,19 +136,38 @@ impl Config {
         uri: String,
         secret_key: String,
     ) -> Result<Self, Error> {
-        let decoded: Vec<u8> = decode(&secret_key)?;
-        let secret_key = match SecretKey::from_slice(&decoded) {
-            Some(v) => Ok(v),
-            None => Err(format_err!("Invalid secret key")),
-        }?;
+        let secret_key = secret_key_from_str(&secret_key)?;
 
         let mut config = Self {
             socket,
             peer_path,
             storage,
+            storage_backend: StorageBackend::File,
+            retarget: Retarget::default(),
+            max_time_drift: DEFAULT_MAX_TIME_DRIFT,
+            miner_threads: 1,
+            quorum: Quorum::default(),
+            quorum_timeout: 30,
+            min_voters: DEFAULT_MIN_VOTERS,
             uri,
             peers: HashMap::new(),
+            trust_policy: TrustPolicy::default(),
+            known_peers: None,
+            learned_peers: HashSet::new(),
+            log_level: None,
+            default_log_level: log::max_level(),
+            nonce_file: None,
+            rekey_interval: 3600,
+            clients: Vec::new(),
+            client_nacl: None,
+            http_api: None,
+            metrics_address: None,
             nacl: Nacl::new(secret_key),
+            transport: Transport::new(),
+            keys: KeyCache::new(),
+            replay: ReplayProtection::new(),
+            sessions: Sessions::new(),
+            metrics: Metrics::new(),
         };
 
         config.load_peers()?;
@@ -94,6 +196,76 @@ impl Config {
             Some(v) => Ok(v),
             None => Err(format_err!("Storage must be set")),
         }?.to_string();
+        let storage_backend = match yaml["storage_backend"].as_str() {
+            Some(v) => StorageBackend::from_str(v),
+            None => Ok(StorageBackend::File),
+        }?;
+        let default_retarget = Retarget::default();
+        let retarget_interval = match yaml["retarget_interval"].as_i64() {
+            Some(v) if v >= 0 => v as u64,
+            Some(_)           => return Err(format_err!("Retarget interval must not be negative")),
+            None              => default_retarget.interval,
+        };
+        let target_block_time = match yaml["target_block_time"].as_i64() {
+            Some(v) if v >= 0 => v as u64,
+            Some(_)           => return Err(format_err!("Target block time must not be negative")),
+            None              => default_retarget.block_time,
+        };
+        let retarget = Retarget::new(retarget_interval, target_block_time)?;
+        let max_time_drift = match yaml["max_time_drift"].as_i64() {
+            Some(v) if v >= 0 => v as u64,
+            Some(_)           => return Err(format_err!("Max time drift must not be negative")),
+            None              => DEFAULT_MAX_TIME_DRIFT,
+        };
+        let miner_threads = match yaml["miner_threads"].as_i64() {
+            Some(v) if v >= 1 => v as usize,
+            Some(_)           => return Err(format_err!("Miner threads must be at least 1")),
+            None              => 1,
+        };
+        let quorum = match yaml["quorum"].as_str() {
+            Some(v) => Quorum::from_str(v)?,
+            None    => Quorum::default(),
+        };
+        let quorum_timeout = match yaml["quorum_timeout"].as_i64() {
+            Some(v) if v >= 1 => v as u64,
+            Some(_)           => return Err(format_err!("Quorum timeout must be at least 1")),
+            None              => 30,
+        };
+        let min_voters = match yaml["min_voters"].as_i64() {
+            Some(v) if v >= 1 => v as usize,
+            Some(_)           => return Err(format_err!("Min voters must be at least 1")),
+            None              => DEFAULT_MIN_VOTERS,
+        };
+        let rekey_interval = match yaml["rekey_interval"].as_i64() {
+            Some(v) if v >= 1 => v as u64,
+            Some(_)           => return Err(format_err!("Rekey interval must be at least 1")),
+            None              => 3600,
+        };
+        let trust_policy = match yaml["trust_policy"].as_str() {
+            Some(v) => TrustPolicy::from_str(v)?,
+            None    => TrustPolicy::default(),
+        };
+        let known_peers = yaml["known_peers"].as_str().map(|v| v.to_string());
+        let mut clients = Vec::new();
+        for client in yaml["clients"].as_vec().cloned().unwrap_or_default() {
+            match client.as_str() {
+                Some(v) => clients.push(public_key_from_str(v)?),
+                None    => return Err(format_err!("Clients must be public keys")),
+            };
+        }
+        let http_api = listen_address(&yaml["http_api"])?;
+        let metrics_address = listen_address(&yaml["metrics"])?;
+        let client_nacl = match yaml["client_secret_key"].as_str() {
+            Some(v) => Some(Nacl::new(secret_key_from_str(v)?)),
+            None    => None,
+        };
+        let log_level = match yaml["log_level"].as_str() {
+            Some(v) => match v.parse::<LevelFilter>() {
+                Ok(level) => Some(level),
+                Err(_)    => return Err(format_err!("Unknown log level {}", v)),
+            },
+            None    => None,
+        };
         let uri = match yaml["uri"].as_str() {
             Some(v) => Ok(v),
             None => Err(format_err!("Uri must be set")),
@@ -103,48 +275,128 @@ impl Config {
             None => Err(format_err!("Secret key must be set")),
         }?;
 
-        let decoded: Vec<u8> = decode(&secret_key)?;
-        let secret_key = match SecretKey::from_slice(&decoded) {
-            Some(v) => Ok(v),
-            None => Err(format_err!("Invalid secret key")),
-        }?;
+        let secret_key = secret_key_from_str(secret_key)?;
+        let nonce_file = yaml["nonce_file"].as_str().map(|v| v.to_string());
+        let nacl = match nonce_file {
+            Some(ref path) => Nacl::with_nonce_file(secret_key, path)?,
+            None           => Nacl::new(secret_key),
+        };
 
         let mut config = Self {
             socket,
             peer_path,
             storage,
+            storage_backend,
+            retarget,
+            max_time_drift,
+            miner_threads,
+            quorum,
+            quorum_timeout,
+            min_voters,
             uri,
             peers: HashMap::new(),
-            nacl: Nacl::new(secret_key),
+            trust_policy,
+            known_peers,
+            learned_peers: HashSet::new(),
+            log_level,
+            default_log_level: log::max_level(),
+            nonce_file,
+            rekey_interval,
+            clients,
+            client_nacl,
+            http_api,
+            metrics_address,
+            nacl,
+            transport: Transport::new(),
+            keys: KeyCache::new(),
+            replay: ReplayProtection::new(),
+            sessions: Sessions::new(),
+            metrics: Metrics::new(),
         };
 
         config.load_peers()?;
+        config.load_known_peers()?;
         Ok(config)
     }
 
     /// Loads the peer config file and parses the peers
     pub fn load_peers(&mut self) -> Result<(), Error> {
-        let mut peers_storage = HashMap::new();
-
         if Path::new(&self.peer_path).exists() {
-            let mut file = File::open(self.peer_path.clone())?;
-            let mut content = String::new();
+            self.peers = read_peers(&self.peer_path)?;
+        } else {
+            error!("The given peer path does not exist.");
+        }
+        Ok(())
+    }
 
-            file.read_to_string(&mut content)?;
-            let peer_file = YamlLoader::load_from_str(&content)?;
+    /// Loads the peers learned during earlier runs
+    ///
+    /// Peers from the peer config file take precedence.
+    pub fn load_known_peers(&mut self) -> Result<(), Error> {
+        let path = match self.known_peers {
+            Some(ref path) if Path::new(path).exists() => path.clone(),
+            _                                          => return Ok(()),
+        };
 
-            for peers in peer_file {
-                for peer in peers {
-                    let read_peer = Peer::from_config_file(peer)?;
-                    peers_storage.insert(read_peer.address.clone(), read_peer);
-                }
+        for (address, peer) in read_peers(&path)? {
+            if !self.peers.contains_key(&address) {
+                self.learned_peers.insert(address.clone());
+                self.peers.insert(address, peer);
             }
-            self.peers = peers_storage;
-        } else {
-            error!("The given peer path does not exist.");
         }
         Ok(())
     }
+
+    /// Drops the shared key and the sessions of a removed or replaced peer
+    pub(crate) fn forget(&self, peer: &Peer) {
+        self.keys.remove(&peer.public_key);
+        self.sessions.remove(&peer.address);
+        self.replay.unpin(&peer.public_key);
+    }
+
+    /// Writes all peers, that were not learned from other peers, to the
+    /// peer config file
+    pub fn save_peers(&self) -> Result<(), Error> {
+        write_peers(
+            &self.peer_path,
+            self.peers.values().filter(|peer| !self.learned_peers.contains(&peer.address)),
+        )
+    }
+
+    /// Writes all learned peers to the `known_peers` file
+    pub fn save_known_peers(&self) -> Result<(), Error> {
+        match self.known_peers {
+            Some(ref path) => write_peers(
+                path,
+                self.peers.values().filter(|peer| self.learned_peers.contains(&peer.address)),
+            ),
+            None           => Ok(()),
+        }
+    }
+
+    /// Key for sealing messages to the given peer
+    ///
+    /// That is the key of the current session, or the key shared by the
+    /// long-term keys if there is no session yet.
+    pub fn seal_key(&self, peer: &Peer) -> PrecomputedKey {
+        match self.sessions.key(&peer.address) {
+            Some(key) => key,
+            None      => self.keys.get(&self.nacl, &peer.public_key),
+        }
+    }
+
+    /// true if the client with the given public key may send the events of
+    /// `Events::is_client`
+    ///
+    /// Clients must be listed in `clients` or use the key of a peer from the
+    /// peer file. With `TrustPolicy::Open` every client is accepted.
+    pub fn accepts_client(&self, public_key: &PublicKey) -> bool {
+        self.trust_policy == TrustPolicy::Open
+            || self.clients.contains(public_key)
+            || self.peers
+                .iter()
+                .any(|(address, peer)| peer.public_key == *public_key && !self.learned_peers.contains(address))
+    }
 }
 
 impl Default for Config {
@@ -153,9 +405,133 @@ impl Default for Config {
             socket: "/tmp/carina.sock".to_string(),
             peer_path: "./peers.yml".to_string(),
             storage: "./block_data".to_string(),
+            storage_backend: StorageBackend::File,
+            retarget: Retarget::default(),
+            max_time_drift: DEFAULT_MAX_TIME_DRIFT,
+            miner_threads: 1,
+            quorum: Quorum::default(),
+            quorum_timeout: 30,
+            min_voters: DEFAULT_MIN_VOTERS,
             uri: "0.0.0.0:45000".to_string(),
             peers: HashMap::new(),
+            trust_policy: TrustPolicy::default(),
+            known_peers: None,
+            learned_peers: HashSet::new(),
+            log_level: None,
+            default_log_level: log::max_level(),
+            nonce_file: None,
+            rekey_interval: 3600,
+            clients: Vec::new(),
+            client_nacl: None,
+            http_api: None,
+            metrics_address: None,
             nacl: Nacl::default(),
+            transport: Transport::new(),
+            keys: KeyCache::new(),
+            replay: ReplayProtection::new(),
+            sessions: Sessions::new(),
+            metrics: Metrics::new(),
+        }
+    }
+}
+
+/// Parses the address of a listener
+///
+/// A port without an address binds to localhost.
+fn listen_address(yaml: &Yaml) -> Result<Option<String>, Error> {
+    match *yaml {
+        Yaml::Integer(v) if v > 0 && v <= 65535 => Ok(Some(format!("127.0.0.1:{}", v))),
+        Yaml::Integer(v)                        => Err(format_err!("Invalid port {}", v)),
+        Yaml::String(ref v)                     => Ok(Some(v.clone())),
+        _                                       => Ok(None),
+    }
+}
+
+/// Decodes a base64 encoded public key
+fn public_key_from_str(value: &str) -> Result<PublicKey, Error> {
+    let decoded: Vec<u8> = decode(value)?;
+    match PublicKey::from_slice(&decoded) {
+        Some(v) => Ok(v),
+        None => Err(format_err!("Invalid public key")),
+    }
+}
+
+/// Decodes a base64 encoded signing public key
+pub(crate) fn sign_key_from_str(value: &str) -> Result<sign::PublicKey, Error> {
+    let decoded: Vec<u8> = decode(value)?;
+    match sign::PublicKey::from_slice(&decoded) {
+        Some(v) => Ok(v),
+        None => Err(format_err!("Invalid signing key")),
+    }
+}
+
+/// Decodes a base64 encoded secret key
+fn secret_key_from_str(value: &str) -> Result<SecretKey, Error> {
+    let decoded: Vec<u8> = decode(value)?;
+    match SecretKey::from_slice(&decoded) {
+        Some(v) => Ok(v),
+        None => Err(format_err!("Invalid secret key")),
+    }
+}
+
+/// Reads all peers from the given peer file
+fn read_peers(path: &str) -> Result<HashMap<String, Peer>, Error> {
+    let mut file = File::open(path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+
+    let mut peers_storage = HashMap::new();
+    for peers in YamlLoader::load_from_str(&content)? {
+        for peer in peers {
+            let read_peer = Peer::from_config_file(peer)?;
+            peers_storage.insert(read_peer.address.clone(), read_peer);
+        }
+    }
+    Ok(peers_storage)
+}
+
+/// Writes the given peers in the format of the peer file
+///
+/// The peers are written to a temporary file first, that is moved over the
+/// old file, so a crash never leaves a half written file behind.
+pub(crate) fn write_peers<'a, I: Iterator<Item = &'a Peer>>(path: &str, peers: I) -> Result<(), Error> {
+    let mut peers: Vec<&Peer> = peers.collect();
+    peers.sort_by(|a, b| a.address.cmp(&b.address));
+
+    let mut content = String::from("---\n");
+    for peer in peers {
+        content.push_str(&format!("- address: {}\n  public_key: {}\n", peer.address, encode(&peer.public_key.0)));
+        if let Some(sign_key) = peer.sign_key {
+            content.push_str(&format!("  sign_key: {}\n", encode(&sign_key.0)));
+        }
+    }
+
+    let temp = format!("{}.tmp", path);
+    {
+        let mut file = File::create(&temp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+    }
+    rename(&temp, path)?;
+    Ok(())
+}
+
+/// Available backends for storing blocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageBackend {
+    /// Blocks are stored in segment files in the `storage` directory
+    File,
+    /// Blocks are only kept in memory
+    Memory,
+}
+
+impl StorageBackend {
+    /// Parses the value of the `storage_backend` config key
+    pub fn from_str(value: &str) -> Result<Self, Error> {
+        match value {
+            "file"   => Ok(StorageBackend::File),
+            "memory" => Ok(StorageBackend::Memory),
+            _        => Err(format_err!("Unknown storage backend {}", value)),
         }
     }
 }
@@ -170,6 +546,12 @@ pub struct Peer {
     pub address: String,
     /// public key of the peer
     pub public_key: PublicKey,
+    /// key the peer signs its block votes with, `None` if unknown
+    ///
+    /// Votes of a peer are only counted if they are signed with this key.
+    /// It is set with `sign_key` in the peer file, peers learned from other
+    /// peers don´t have one.
+    pub sign_key: Option<sign::PublicKey>,
 }
 
 impl Peer {
@@ -180,19 +562,23 @@ impl Peer {
             None => Err(format_err!("Address must be set")),
         }?.to_string();
         let public_key = match yaml["public_key"].as_str() {
-            Some(v) => {
-                let decoded: Vec<u8> = decode(v)?;
-                match PublicKey::from_slice(&decoded) {
-                    Some(v) => Ok(v),
-                    None => Err(format_err!("Invalid secret key")),
-                }
-            }
+            Some(v) => Ok(v),
             None => Err(format_err!("Public key must be set")),
         }?;
 
+        let mut peer = Peer::from_str(&address, public_key)?;
+        if let Some(sign_key) = yaml["sign_key"].as_str() {
+            peer.sign_key = Some(sign_key_from_str(sign_key)?);
+        }
+        Ok(peer)
+    }
+
+    /// Creates a peer from its address and its base64 encoded public key
+    pub fn from_str(address: &str, public_key: &str) -> Result<Self, Error> {
         Ok(Peer {
-            address,
-            public_key,
+            address: address.to_string(),
+            public_key: public_key_from_str(public_key)?,
+            sign_key: None,
         })
     }
 }
@@ -200,6 +586,9 @@ impl Peer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::env;
+    use std::fs::remove_file;
+    use std::process;
 
     #[test]
     pub fn test_config() {
@@ -218,16 +607,46 @@ secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
             socket: "/tmp/carina.sock".to_string(),
             peer_path: "".to_string(),
             storage: "./block_data".to_string(),
+            storage_backend: StorageBackend::File,
+            retarget: Retarget::default(),
+            max_time_drift: DEFAULT_MAX_TIME_DRIFT,
+            miner_threads: 1,
+            quorum: Quorum::default(),
+            quorum_timeout: 30,
+            min_voters: DEFAULT_MIN_VOTERS,
             uri: "0.0.0.0:45000".to_string(),
             peers: HashMap::new(),
+            trust_policy: TrustPolicy::default(),
+            known_peers: None,
+            learned_peers: HashSet::new(),
+            log_level: None,
+            default_log_level: log::max_level(),
+            nonce_file: None,
+            rekey_interval: 3600,
+            clients: Vec::new(),
+            client_nacl: None,
+            http_api: None,
+            metrics_address: None,
             nacl: Nacl::new(secret_key),
+            transport: Transport::new(),
+            keys: KeyCache::new(),
+            replay: ReplayProtection::new(),
+            sessions: Sessions::new(),
+            metrics: Metrics::new(),
         };
 
         assert_eq!(expected.socket, config.socket);
         assert_eq!(expected.peer_path, config.peer_path);
         assert_eq!(expected.storage, config.storage);
+        assert_eq!(expected.storage_backend, config.storage_backend);
+        assert_eq!(expected.retarget, config.retarget);
+        assert_eq!(expected.miner_threads, config.miner_threads);
+        assert_eq!(expected.quorum, config.quorum);
+        assert_eq!(expected.quorum_timeout, config.quorum_timeout);
         assert_eq!(expected.uri, config.uri);
         assert_eq!(expected.peers, config.peers);
+        assert_eq!(expected.nonce_file, config.nonce_file);
+        assert_eq!(expected.rekey_interval, config.rekey_interval);
     }
 
     #[test]
@@ -241,13 +660,217 @@ secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
         assert!(Config::from_str(config_file).is_err(), true);
     }
 
+    #[test]
+    pub fn test_config_storage_backend() {
+        let config_file = r#"---
+socket: /tmp/carina.sock
+peers: ""
+storage: ./block_data
+storage_backend: memory
+uri: 0.0.0.0:45000
+secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
+
+        let config = Config::from_str(config_file).unwrap();
+        assert_eq!(StorageBackend::Memory, config.storage_backend);
+
+        let config_file = config_file.replace("storage_backend: memory", "storage_backend: tape");
+        assert!(Config::from_str(&config_file).is_err());
+    }
+
+    #[test]
+    pub fn test_config_retarget() {
+        let config_file = r#"---
+socket: /tmp/carina.sock
+peers: ""
+storage: ./block_data
+retarget_interval: 20
+target_block_time: 30
+uri: 0.0.0.0:45000
+secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
+
+        let config = Config::from_str(config_file).unwrap();
+        assert_eq!(Retarget::new(20, 30).unwrap(), config.retarget);
+        assert_eq!(DEFAULT_MAX_TIME_DRIFT, config.max_time_drift);
+
+        let drift = format!("{}\nmax_time_drift: 60", config_file);
+        assert_eq!(60, Config::from_str(&drift).unwrap().max_time_drift);
+
+        let config_file = config_file.replace("retarget_interval: 20", "retarget_interval: 1");
+        assert!(Config::from_str(&config_file).is_err());
+    }
+
+    #[test]
+    pub fn test_config_miner_threads() {
+        let config_file = r#"---
+socket: /tmp/carina.sock
+peers: ""
+storage: ./block_data
+miner_threads: 4
+uri: 0.0.0.0:45000
+secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
+
+        assert_eq!(4, Config::from_str(config_file).unwrap().miner_threads);
+
+        let config_file = config_file.replace("miner_threads: 4", "miner_threads: 0");
+        assert!(Config::from_str(&config_file).is_err());
+    }
+
+    #[test]
+    pub fn test_config_quorum() {
+        let config_file = r#"---
+socket: /tmp/carina.sock
+peers: ""
+storage: ./block_data
+quorum: 3/4
+quorum_timeout: 10
+uri: 0.0.0.0:45000
+secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
+
+        let config = Config::from_str(config_file).unwrap();
+        assert_eq!(Quorum::new(3, 4).unwrap(), config.quorum);
+        assert_eq!(10, config.quorum_timeout);
+        assert_eq!(DEFAULT_MIN_VOTERS, config.min_voters);
+
+        let config = Config::from_str(&format!("{}\nmin_voters: 4", config_file)).unwrap();
+        assert_eq!(4, config.min_voters);
+        assert!(Config::from_str(&format!("{}\nmin_voters: 0", config_file)).is_err());
+
+        let config_file = config_file.replace("quorum: 3/4", "quorum: 5/4");
+        assert!(Config::from_str(&config_file).is_err());
+    }
+
+    #[test]
+    pub fn test_config_rekey_interval() {
+        let config_file = r#"---
+socket: /tmp/carina.sock
+peers: ""
+storage: ./block_data
+rekey_interval: 600
+uri: 0.0.0.0:45000
+secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
+
+        assert_eq!(600, Config::from_str(config_file).unwrap().rekey_interval);
+
+        let config_file = config_file.replace("rekey_interval: 600", "rekey_interval: 0");
+        assert!(Config::from_str(&config_file).is_err());
+    }
+
+    #[test]
+    pub fn test_config_trust_policy() {
+        let config_file = r#"---
+socket: /tmp/carina.sock
+peers: ""
+storage: ./block_data
+trust_policy: open
+uri: 0.0.0.0:45000
+secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
+
+        assert_eq!(TrustPolicy::Open, Config::from_str(config_file).unwrap().trust_policy);
+
+        let config_file = config_file.replace("trust_policy: open", "trust_policy: everyone");
+        assert!(Config::from_str(&config_file).is_err());
+    }
+
+    #[test]
+    pub fn test_config_log_level() {
+        let config_file = r#"---
+socket: /tmp/carina.sock
+peers: ""
+storage: ./block_data
+log_level: warn
+uri: 0.0.0.0:45000
+secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
+
+        assert_eq!(Some(LevelFilter::Warn), Config::from_str(config_file).unwrap().log_level);
+
+        let config_file = config_file.replace("log_level: warn", "log_level: loud");
+        assert!(Config::from_str(&config_file).is_err());
+    }
+
+    #[test]
+    pub fn test_config_clients() {
+        let config_file = r#"---
+socket: /tmp/carina.sock
+peers: ""
+storage: ./block_data
+clients:
+  - /gfCzCrTj02YA+dAXCY2EODAYZFELeKH1bec5nenbU0=
+client_secret_key: v+rETx4VtczK/QSvl9OBfJfgVPEdjNpquVUq/8GFmWo=
+uri: 0.0.0.0:45000
+secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
+
+        let mut config = Config::from_str(config_file).unwrap();
+        let client = PublicKey::from_slice(&decode("/gfCzCrTj02YA+dAXCY2EODAYZFELeKH1bec5nenbU0=").unwrap()).unwrap();
+        let stranger = PublicKey::from_slice(&decode("OYGxJI79O18BFSCx3QUVNryww5v4i8qC85sdcx6N1SQ=").unwrap()).unwrap();
+        assert!(config.client_nacl.is_some());
+        assert!(config.accepts_client(&client));
+        assert!(!config.accepts_client(&stranger));
+
+        config.trust_policy = TrustPolicy::Open;
+        assert!(config.accepts_client(&stranger));
+
+        let config_file = config_file.replace("/gfCzCrTj02YA+dAXCY2EODAYZFELeKH1bec5nenbU0=", "invalid");
+        assert!(Config::from_str(&config_file).is_err());
+    }
+
+    #[test]
+    pub fn test_config_http_api() {
+        let config_file = r#"---
+socket: /tmp/carina.sock
+peers: ""
+storage: ./block_data
+http_api: 45080
+uri: 0.0.0.0:45000
+secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
+
+        assert_eq!(Some("127.0.0.1:45080".to_string()), Config::from_str(config_file).unwrap().http_api);
+
+        let config = config_file.replace("http_api: 45080", "http_api: 0.0.0.0:45080");
+        assert_eq!(Some("0.0.0.0:45080".to_string()), Config::from_str(&config).unwrap().http_api);
+
+        let config = config_file.replace("http_api: 45080", "http_api: 70000");
+        assert!(Config::from_str(&config).is_err());
+
+        let config = config_file.replace("http_api: 45080\n", "");
+        assert_eq!(None, Config::from_str(&config).unwrap().http_api);
+
+        let config = config_file.replace("http_api: 45080", "metrics: 45090");
+        assert_eq!(Some("127.0.0.1:45090".to_string()), Config::from_str(&config).unwrap().metrics_address);
+    }
+
+    #[test]
+    pub fn test_known_peers() {
+        let path = env::temp_dir().join(format!("carina_known_peers_{}", process::id()));
+        let path = path.to_str().unwrap().to_string();
+
+        let mut config = Config::default();
+        config.known_peers = Some(path.clone());
+        let peer = Peer {
+            address: "127.0.0.1:45002".to_string(),
+            public_key: PublicKey::from_slice(&decode("OYGxJI79O18BFSCx3QUVNryww5v4i8qC85sdcx6N1SQ=").unwrap()).unwrap(),
+            sign_key: Some(sign_key_from_str("JjOminHkHJoZ7YJEhcJ7S3gXzXTOZMp2NPoyVLlCeIY=").unwrap()),
+        };
+        config.peers.insert(peer.address.clone(), peer.clone());
+        config.learned_peers.insert(peer.address.clone());
+        config.save_known_peers().unwrap();
+
+        let mut loaded = Config::default();
+        loaded.known_peers = Some(path.clone());
+        loaded.load_known_peers().unwrap();
+        assert_eq!(Some(&peer), loaded.peers.get(&peer.address));
+        assert!(loaded.learned_peers.contains(&peer.address));
+
+        remove_file(&path).unwrap();
+    }
+
     #[test]
     pub fn test_peer_config() {
         let config_file = r#"---
 - address: 127.0.0.1:45002
   public_key: OYGxJI79O18BFSCx3QUVNryww5v4i8qC85sdcx6N1SQ=
 - address: 127.0.0.1:45003
-  public_key: /gfCzCrTj02YA+dAXCY2EODAYZFELeKH1bec5nenbU0="#;
+  public_key: /gfCzCrTj02YA+dAXCY2EODAYZFELeKH1bec5nenbU0=
+  sign_key: JjOminHkHJoZ7YJEhcJ7S3gXzXTOZMp2NPoyVLlCeIY="#;
 
         let mut deserialized = Vec::new();
         let peer_file = YamlLoader::load_from_str(&config_file).unwrap();
@@ -263,10 +886,12 @@ secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
         let peer_1 = Peer {
             address: "127.0.0.1:45002".to_string(),
             public_key: PublicKey::from_slice(&public_key_1).unwrap(),
+            sign_key: None,
         };
         let peer_2 = Peer {
             address: "127.0.0.1:45003".to_string(),
             public_key: PublicKey::from_slice(&public_key_2).unwrap(),
+            sign_key: Some(sign_key_from_str("JjOminHkHJoZ7YJEhcJ7S3gXzXTOZMp2NPoyVLlCeIY=").unwrap()),
         };
 
         assert_eq!(peer_1, deserialized[0]);
@@ -279,6 +904,7 @@ secret_key: W8TAQuFECexfADKJik6WBrh4G5qFaOhzX2eBZFIV8kY="#;
         let peer = Peer {
             address: "127.0.0.1:45002".to_string(),
             public_key: PublicKey::from_slice(&public_key).unwrap(),
+            sign_key: None,
         };
 
         let expected = PublicKey::from_slice(&[
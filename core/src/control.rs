@@ -0,0 +1,469 @@
+//! Local control socket of a running node
+//!
+//! The node listens on the unix domain socket configured with `socket`.
+//! Local tools connect to it to query or change the node without running
+//! a second peer with the same keys.
+//!
+//! Every request and every response is a single frame. A frame starts with
+//! the length of its body as unsigned 32 bit big endian integer, followed by
+//! the body. The body of a request is a command as UTF-8 text, for example
+//! `peer add 127.0.0.1:45002 <public key>`. The body of a response starts
+//! with a status byte, `0` for ok and `1` for an error, followed by UTF-8
+//! text. Successful responses contain one `key: value` pair per line.
+use base64::encode;
+use carina_config::CarinaConfig;
+use carina_core_protocol::payloads::block::{Block, NewBlockContentPayload};
+use carina_core_protocol::{Events, MessageBuilder};
+use config::{sign_key_from_str, Peer};
+use failure::Error;
+use std::fmt;
+use std::fs::{remove_file, set_permissions, Permissions};
+use std::io::{Read, Write};
+use std::net::UdpSocket;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Maximum size of a single frame in bytes
+pub const MAX_FRAME: usize = 64 * 1024;
+/// Seconds a client has to send its request or read the response
+const CLIENT_TIMEOUT: u64 = 5;
+
+/// Command sent to the control socket
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    /// Version, chain height and state of the node
+    Status,
+    /// All known peers
+    Peers,
+    /// Adds a peer and writes it to the peer file
+    AddPeer(Peer),
+    /// Removes the peer with the given address
+    RemovePeer(String),
+    /// Adds content to the next block and sends it to all peers
+    Content {
+        /// unique key of the content
+        unique_key: String,
+        /// the content
+        content: String,
+    },
+    /// Block at the given height
+    Block(u64),
+    /// Stops the node
+    Shutdown,
+}
+
+impl Request {
+    /// Parses a command
+    ///
+    /// Known commands are `status`, `peers`,
+    /// `peer add <address> <public key> [<sign key>]`,
+    /// `peer remove <address>`, `content <unique key> <content>`,
+    /// `block <height>` and `shutdown`.
+    pub fn parse(command: &str) -> Result<Self, Error> {
+        let command = command.trim();
+        let mut words = command.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (Some("status"), None)          => Ok(Request::Status),
+            (Some("peers"), None)           => Ok(Request::Peers),
+            (Some("shutdown"), None)        => Ok(Request::Shutdown),
+            (Some("peer"), Some("add"))     => match (words.next(), words.next(), words.next(), words.next()) {
+                (Some(address), Some(public_key), sign_key, None) => {
+                    let mut peer = Peer::from_str(address, public_key)?;
+                    if let Some(sign_key) = sign_key {
+                        peer.sign_key = Some(sign_key_from_str(sign_key)?);
+                    }
+                    Ok(Request::AddPeer(peer))
+                },
+                _                                                 => Err(format_err!("Usage: peer add <address> <public key> [<sign key>]")),
+            },
+            (Some("peer"), Some("remove"))  => match (words.next(), words.next()) {
+                (Some(address), None) => Ok(Request::RemovePeer(address.to_string())),
+                _                     => Err(format_err!("Usage: peer remove <address>")),
+            },
+            (Some("content"), Some(unique_key)) => {
+                // the content may contain whitespace, everything after the key belongs to it
+                let content = command["content".len()..].trim()[unique_key.len()..].trim();
+                Ok(Request::Content {
+                    unique_key: unique_key.to_string(),
+                    content: content.to_string(),
+                })
+            },
+            (Some("block"), Some(height))   => match (height.parse::<u64>(), words.next()) {
+                (Ok(height), None) => Ok(Request::Block(height)),
+                _                  => Err(format_err!("Usage: block <height>")),
+            },
+            _                               => Err(format_err!("Unknown command {}", command)),
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Request::Status                                  => write!(f, "status"),
+            Request::Peers                                   => write!(f, "peers"),
+            Request::AddPeer(ref peer)                       => match peer.sign_key {
+                Some(sign_key) => write!(f, "peer add {} {} {}", peer.address, encode(&peer.public_key.0), encode(&sign_key.0)),
+                None           => write!(f, "peer add {} {}", peer.address, encode(&peer.public_key.0)),
+            },
+            Request::RemovePeer(ref address)                 => write!(f, "peer remove {}", address),
+            Request::Content { ref unique_key, ref content } => write!(f, "content {} {}", unique_key, content),
+            Request::Block(height)                           => write!(f, "block {}", height),
+            Request::Shutdown                                => write!(f, "shutdown"),
+        }
+    }
+}
+
+/// Answer of the control socket
+#[derive(Clone, Debug, PartialEq)]
+pub enum Response {
+    /// The request was executed, contains one `key: value` pair per line
+    Ok(String),
+    /// The request failed, contains the reason
+    Error(String),
+}
+
+impl Response {
+    /// true if the request was executed
+    pub fn is_ok(&self) -> bool {
+        match *self {
+            Response::Ok(_)    => true,
+            Response::Error(_) => false,
+        }
+    }
+
+    /// Serializes the response to the body of a frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (status, text) = match *self {
+            Response::Ok(ref text)    => (0, text),
+            Response::Error(ref text) => (1, text),
+        };
+
+        let mut bytes = vec![status];
+        bytes.extend_from_slice(text.as_bytes());
+        bytes
+    }
+
+    /// Parses the body of a frame
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.is_empty() {
+            return Err(format_err!("Empty response"));
+        }
+
+        let text = String::from_utf8(bytes[1..].to_vec())?;
+        match bytes[0] {
+            0 => Ok(Response::Ok(text)),
+            1 => Ok(Response::Error(text)),
+            _ => Err(format_err!("Unknown response status {}", bytes[0])),
+        }
+    }
+}
+
+/// Writes a single frame
+pub fn write_frame<W: Write>(writer: &mut W, body: &[u8]) -> Result<(), Error> {
+    if body.len() > MAX_FRAME {
+        return Err(format_err!("Frame is too large"));
+    }
+
+    let length = body.len() as u32;
+    let header = [(length >> 24) as u8, (length >> 16) as u8, (length >> 8) as u8, length as u8];
+    writer.write_all(&header)?;
+    writer.write_all(body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a single frame
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let mut header = [0; 4];
+    reader.read_exact(&mut header)?;
+
+    let length = header.iter().fold(0usize, |length, byte| (length << 8) | *byte as usize);
+    if length > MAX_FRAME {
+        return Err(format_err!("Frame is too large"));
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+/// Sends a request to the control socket of a running node
+///
+/// # Params
+/// - `path` -> path of the socket file, see `Config::socket`
+/// - `request` -> request to send
+///
+/// # Return
+/// - `Result<Response, Error>` -> answer of the node
+pub fn request(path: &str, request: &Request) -> Result<Response, Error> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(Duration::from_secs(CLIENT_TIMEOUT)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(CLIENT_TIMEOUT)))?;
+
+    write_frame(&mut stream, request.to_string().as_bytes())?;
+    Response::from_bytes(&read_frame(&mut stream)?)
+}
+
+/// Executes a request
+fn execute(carina_config: &Arc<Mutex<CarinaConfig>>, socket: &UdpSocket, request: Request) -> Result<String, Error> {
+    let mut carina_config = match carina_config.lock() {
+        Ok(val) => val,
+        Err(e)  => return Err(format_err!("Error locking carina_config. {}", e)),
+    };
+
+    match request {
+        Request::Status                  => status(&carina_config),
+        Request::Peers                   => {
+            let mut peers = carina_config.config.peers.values().collect::<Vec<&Peer>>();
+            peers.sort_by(|a, b| a.address.cmp(&b.address));
+
+            Ok(peers
+                .iter()
+                .map(|peer| {
+                    let kind = if carina_config.config.learned_peers.contains(&peer.address) { "learned" } else { "configured" };
+                    format!("{}: {} {}\n", peer.address, encode(&peer.public_key.0), kind)
+                })
+                .collect())
+        },
+        Request::AddPeer(peer)           => {
+            let address = peer.address.clone();
+            carina_config.add_peer(peer)?;
+            Ok(format!("added: {}\n", address))
+        },
+        Request::RemovePeer(address)     => {
+            carina_config.remove_peer(&address)?;
+            Ok(format!("removed: {}\n", address))
+        },
+        Request::Content { unique_key, content } => {
+            let payload = NewBlockContentPayload { unique_key, content };
+            let added = match carina_config.mempool.lock() {
+                Ok(mut mempool) => mempool.add(payload.unique_key.clone(), payload.content.clone()),
+                Err(e)          => return Err(format_err!("Error locking mempool. {}", e)),
+            };
+            if !added {
+                return Err(format_err!("Unique key and content are empty"));
+            }
+
+            let config = &mut carina_config.config;
+            for peer in config.peers.values() {
+                let key = config.seal_key(peer);
+                let message = MessageBuilder::new()
+                    .set_event_code(Events::as_val(Events::NewBlockContent))
+                    .set_payload(payload.clone())
+                    .build_precomputed(&mut config.nacl, &key);
+
+                match config.transport.send_reliable(socket, Events::NewBlockContent, &message, &peer.address) {
+                    Ok(_)  => debug!("[THREAD_CONTROL] Send new_block_content to {}", peer.address),
+                    Err(e) => error!("[THREAD_CONTROL] Error sending new_block_content to peer: {}. Error: {}", peer.address, e),
+                };
+            }
+            Ok(format!("added: {}\n", payload.unique_key))
+        },
+        Request::Block(height)           => {
+            let bytes = match carina_config.storage()?.lock() {
+                Ok(val) => val.get_by_height(height)?,
+                Err(e)  => return Err(format_err!("Error locking storage. {}", e)),
+            };
+            let block = match bytes {
+                Some(bytes) => Block::from_bytes(&bytes)?,
+                None        => return Err(format_err!("Block {} does not exist", height)),
+            };
+
+            let mut text = format!(
+                "height: {}\nhash: {}\nprev: {}\ntimestamp: {}\nmerkle_root: {}\ndifficulty: {}\nnonce: {}\n",
+                block.header.index,
+                block.header.hash(),
+                block.header.prev,
+                block.header.timestamp,
+                block.header.merkle_root,
+                block.header.difficulty,
+                block.header.nonce
+            );
+            for entry in block.entries {
+                text.push_str(&format!("entry: {} {}\n", entry.unique_key, entry.content));
+            }
+            Ok(text)
+        },
+        Request::Shutdown                => {
+            carina_config.shutdown.store(true, Ordering::SeqCst);
+            Ok(String::from("shutdown: true\n"))
+        },
+    }
+}
+
+/// Collects the state of the node
+fn status(carina_config: &CarinaConfig) -> Result<String, Error> {
+    let config = &carina_config.config;
+    let tip = match carina_config.chain {
+        Some(_) => match carina_config.storage()?.lock() {
+            Ok(val) => val.tip(),
+            Err(e)  => return Err(format_err!("Error locking storage. {}", e)),
+        },
+        None    => None,
+    };
+    let mempool = match carina_config.mempool.lock() {
+        Ok(val) => val.len(),
+        Err(e)  => return Err(format_err!("Error locking mempool. {}", e)),
+    };
+
+    let mut text = format!(
+        "version: {}\nuri: {}\npublic_key: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        config.uri,
+        encode(&config.nacl.public_key().0)
+    );
+    match tip {
+        Some((height, hash)) => text.push_str(&format!("height: {}\ntip: {}\n", height, hash)),
+        None                 => text.push_str("height: none\n"),
+    };
+    text.push_str(&format!(
+        "peers: {}\nlearned_peers: {}\nmempool: {}\npending_messages: {}\nrejected_replays: {}\n",
+        config.peers.len(),
+        config.learned_peers.len(),
+        mempool,
+        config.transport.pending(),
+        config.replay.rejected()
+    ));
+    Ok(text)
+}
+
+/// Reads a single request from the client and answers it
+///
+/// # Return
+/// - `Result<bool, Error>` -> true if the node should shut down
+fn handle(carina_config: &Arc<Mutex<CarinaConfig>>, socket: &UdpSocket, mut stream: UnixStream) -> Result<bool, Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(CLIENT_TIMEOUT)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(CLIENT_TIMEOUT)))?;
+
+    let body = read_frame(&mut stream)?;
+    let request = String::from_utf8(body)
+        .map_err(Error::from)
+        .and_then(|command| Request::parse(&command));
+    let shutdown = request.as_ref().map(|request| *request == Request::Shutdown).unwrap_or(false);
+
+    let response = match request.and_then(|request| execute(carina_config, socket, request)) {
+        Ok(text) => Response::Ok(text),
+        Err(e)   => Response::Error(e.to_string()),
+    };
+    write_frame(&mut stream, &response.to_bytes())?;
+    Ok(shutdown && response.is_ok())
+}
+
+/// Binds the control socket
+///
+/// A socket file left behind by a node that is no longer running is
+/// replaced. Only the owner may connect to the socket.
+fn bind(path: &str) -> Result<UnixListener, Error> {
+    if Path::new(path).exists() {
+        match UnixStream::connect(path) {
+            Ok(_)  => return Err(format_err!("Another node is listening on {}", path)),
+            Err(_) => remove_file(path)?,
+        };
+    }
+
+    let listener = UnixListener::bind(path)?;
+    set_permissions(path, Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Starts the thread that answers requests on the control socket
+pub(crate) fn start(carina_config: Arc<Mutex<CarinaConfig>>, socket: UdpSocket) -> Option<JoinHandle<()>> {
+    let path = match carina_config.lock() {
+        Ok(val) => val.config.socket.clone(),
+        Err(e)  => {
+            error!("[THREAD_CONTROL] Error locking carina_config. {}", e);
+            return None;
+        }
+    };
+
+    let listener = match bind(&path) {
+        Ok(val) => val,
+        Err(e)  => {
+            error!("[THREAD_CONTROL] Error opening control socket {}. {}", path, e);
+            return None;
+        }
+    };
+    info!("[THREAD_CONTROL] Listening on {}", path);
+
+    Some(thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(val) => val,
+                Err(e)  => {
+                    error!("[THREAD_CONTROL] Error accepting connection. {}", e);
+                    continue;
+                }
+            };
+
+            match handle(&carina_config, &socket, stream) {
+                Ok(true)  => break,
+                Ok(false) => (),
+                Err(e)    => error!("[THREAD_CONTROL] Error answering request. {}", e),
+            };
+        }
+
+        info!("[THREAD_CONTROL] Shutting down");
+        if let Err(e) = remove_file(&path) {
+            error!("[THREAD_CONTROL] Error removing control socket {}. {}", path, e);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sodiumoxide::crypto::{box_, sign};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_requests() {
+        let peer = Peer {
+            address: String::from("127.0.0.1:45002"),
+            public_key: box_::gen_keypair().0,
+            sign_key: None,
+        };
+        let mut signing_peer = peer.clone();
+        signing_peer.sign_key = Some(sign::gen_keypair().0);
+        let requests = vec![
+            Request::Status,
+            Request::Peers,
+            Request::AddPeer(peer),
+            Request::AddPeer(signing_peer),
+            Request::RemovePeer(String::from("127.0.0.1:45002")),
+            Request::Content {
+                unique_key: String::from("key"),
+                content: String::from("some  content"),
+            },
+            Request::Block(42),
+            Request::Shutdown,
+        ];
+
+        for request in requests {
+            assert_eq!(request, Request::parse(&request.to_string()).unwrap());
+        }
+        assert!(Request::parse("block latest").is_err());
+        assert!(Request::parse("peer add 127.0.0.1:45002").is_err());
+        assert!(Request::parse("unknown").is_err());
+    }
+
+    #[test]
+    fn test_frames() {
+        let response = Response::Error(String::from("Peer 127.0.0.1:45002 does not exist"));
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &response.to_bytes()).unwrap();
+
+        let body = read_frame(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(response, Response::from_bytes(&body).unwrap());
+
+        let too_large = [0, 1, 0, 1];
+        assert!(read_frame(&mut Cursor::new(too_large.to_vec())).is_err());
+    }
+}
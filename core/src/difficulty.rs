@@ -0,0 +1,273 @@
+//! Proof of work difficulty and its adjustment
+//!
+//! The difficulty is the number of leading zero bits the hash of a block
+//! must have. Every `interval` blocks the difficulty is adjusted, so that
+//! blocks are created every `block_time` seconds, no matter how many peers
+//! are mining.
+use carina_core_protocol::payloads::block::{BlockHeader, DEFAULT_DIFFICULTY};
+use failure::Error;
+
+/// Lowest difficulty a block can have
+pub const MIN_DIFFICULTY: u32 = 1;
+/// Highest difficulty a block can have, a sha256 hash has 256 bits
+pub const MAX_DIFFICULTY: u32 = 255;
+/// Maximum number of bits the difficulty changes on a single retarget
+const MAX_ADJUSTMENT: u32 = 2;
+/// Number of blocks whose median timestamp is used as the time of a block
+pub const MEDIAN_TIME_SPAN: u64 = 11;
+
+/// Counts the leading zero bits of the given hex encoded hash
+///
+/// Counting stops at the first character that is not a hex digit.
+pub fn leading_zero_bits(hash: &str) -> u32 {
+    let mut bits = 0;
+    for c in hash.chars() {
+        match c.to_digit(16) {
+            Some(0)     => bits += 4,
+            Some(digit) => return bits + (digit as u8).leading_zeros() - 4,
+            None        => return bits,
+        }
+    }
+    bits
+}
+
+/// Checks if the given hash meets the given difficulty
+pub fn meets_target(hash: &str, difficulty: u32) -> bool {
+    leading_zero_bits(hash) >= difficulty
+}
+
+/// Work that was needed to create a block with the given header
+///
+/// Used to compare competing branches. Every bit of difficulty doubles the
+/// expected number of tries.
+pub fn block_work(header: &BlockHeader) -> u128 {
+    1 << header.difficulty.min(127)
+}
+
+/// Block with the median timestamp of the `MEDIAN_TIME_SPAN` blocks ending
+/// at `height`
+///
+/// A few blocks with a wrong timestamp don´t move the median, so they
+/// can´t steer the difficulty.
+///
+/// # Params
+/// - `height` -> height of the last block
+/// - `header_at` -> returns the header at the given height of the branch
+///
+/// # Return
+/// - `Result<(u64, u64), Error>` -> timestamp and height of the median block
+pub fn median_time_past<F>(height: u64, mut header_at: F) -> Result<(u64, u64), Error>
+where
+    F: FnMut(u64) -> Result<BlockHeader, Error>,
+{
+    let mut times = Vec::new();
+    for height in height.saturating_sub(MEDIAN_TIME_SPAN - 1)..height + 1 {
+        times.push((header_at(height)?.timestamp, height));
+    }
+    times.sort();
+    Ok(times[times.len() / 2])
+}
+
+/// Configures how the difficulty is adjusted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Retarget {
+    /// number of blocks between two adjustments
+    pub interval: u64,
+    /// time in seconds that should pass between two blocks
+    pub block_time: u64,
+}
+
+impl Retarget {
+    /// Creates a new instance
+    ///
+    /// The interval must be at least 2 and the block time at least 1.
+    pub fn new(interval: u64, block_time: u64) -> Result<Self, Error> {
+        if interval < 2 {
+            return Err(format_err!("Retarget interval must be at least 2"));
+        }
+        if block_time < 1 {
+            return Err(format_err!("Target block time must be at least 1"));
+        }
+
+        Ok(Self { interval, block_time })
+    }
+
+    /// Height of the first block that is used for calculating the
+    /// difficulty of the block following `prev_index`
+    ///
+    /// # Return
+    /// - `Option<u64>` -> `None` if the difficulty does not change
+    pub fn window_start(&self, prev_index: u64) -> Option<u64> {
+        let height = prev_index + 1;
+
+        if height % self.interval == 0 {
+            Some(height - self.interval)
+        } else {
+            None
+        }
+    }
+
+    /// Calculates the difficulty of the block following `prev`
+    ///
+    /// The time of the window is measured between the median times past
+    /// of its first and its last block, see `median_time_past`.
+    ///
+    /// # Params
+    /// - `prev` -> header of the previous block, `None` for the first block
+    /// - `header_at` -> returns the header at the given height of the
+    ///   branch ending in `prev`
+    pub fn required_difficulty<F>(&self, prev: Option<&BlockHeader>, mut header_at: F) -> Result<u32, Error>
+    where
+        F: FnMut(u64) -> Result<BlockHeader, Error>,
+    {
+        let prev = match prev {
+            Some(prev) => prev,
+            None       => return Ok(self.next_difficulty(None, None)),
+        };
+
+        let window = match self.window_start(prev.index) {
+            Some(start) => {
+                let (last_time, last_height) = median_time_past(prev.index, &mut header_at)?;
+                let (first_time, first_height) = median_time_past(start, &mut header_at)?;
+                Some((last_time.saturating_sub(first_time), last_height.saturating_sub(first_height)))
+            },
+            None        => None,
+        };
+        Ok(self.next_difficulty(Some(prev), window))
+    }
+
+    /// Calculates the difficulty of the block following `prev`
+    ///
+    /// # Params
+    /// - `prev` -> header of the previous block, `None` for the first block
+    /// - `window` -> seconds and number of blocks the window took, `None`
+    ///   if there is no retarget
+    pub fn next_difficulty(&self, prev: Option<&BlockHeader>, window: Option<(u64, u64)>) -> u32 {
+        let prev = match prev {
+            Some(prev) => prev,
+            None       => return DEFAULT_DIFFICULTY,
+        };
+        let (elapsed, blocks) = match window {
+            Some((elapsed, blocks)) if blocks > 0 => (elapsed, blocks),
+            _                                     => return prev.difficulty,
+        };
+
+        let expected = self.block_time * blocks;
+        let mut actual = elapsed.max(1);
+        let mut difficulty = prev.difficulty.max(MIN_DIFFICULTY).min(MAX_DIFFICULTY);
+
+        for _ in 0..MAX_ADJUSTMENT {
+            if actual * 2 <= expected && difficulty < MAX_DIFFICULTY {
+                difficulty += 1;
+                actual *= 2;
+            } else if actual >= expected * 2 && difficulty > MIN_DIFFICULTY {
+                difficulty -= 1;
+                actual /= 2;
+            } else {
+                break;
+            }
+        }
+
+        difficulty
+    }
+}
+
+impl Default for Retarget {
+    fn default() -> Self {
+        Self {
+            interval: 10,
+            block_time: 120,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carina_core_protocol::Payload;
+
+    fn header(index: u64, timestamp: u64, difficulty: u32) -> BlockHeader {
+        let mut header = BlockHeader::new();
+        header.index = index;
+        header.timestamp = timestamp;
+        header.difficulty = difficulty;
+        header
+    }
+
+    /// Returns the headers of the given branch by their height
+    fn branch(headers: &[BlockHeader]) -> impl Fn(u64) -> Result<BlockHeader, Error> {
+        let headers = headers.to_vec();
+        move |height| Ok(headers[height as usize].clone())
+    }
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(0, leading_zero_bits("f000"));
+        assert_eq!(3, leading_zero_bits("1000"));
+        assert_eq!(16, leading_zero_bits("0000f"));
+        assert_eq!(18, leading_zero_bits("00003"));
+        assert_eq!(16, leading_zero_bits("0000"));
+
+        assert!(meets_target("00003", 18));
+        assert!(!meets_target("00003", 19));
+    }
+
+    #[test]
+    fn test_window_start() {
+        let retarget = Retarget::new(10, 60).unwrap();
+        assert_eq!(None, retarget.window_start(0));
+        assert_eq!(Some(0), retarget.window_start(9));
+        assert_eq!(None, retarget.window_start(10));
+        assert_eq!(Some(10), retarget.window_start(19));
+
+        assert!(Retarget::new(1, 60).is_err());
+        assert!(Retarget::new(10, 0).is_err());
+    }
+
+    #[test]
+    fn test_next_difficulty() {
+        let retarget = Retarget::new(10, 60).unwrap();
+
+        assert_eq!(DEFAULT_DIFFICULTY, retarget.next_difficulty(None, None));
+        assert_eq!(16, retarget.next_difficulty(Some(&header(5, 1100, 16)), None));
+
+        // on time
+        assert_eq!(16, retarget.next_difficulty(Some(&header(9, 1540, 16)), Some((540, 9))));
+        // twice as fast
+        assert_eq!(17, retarget.next_difficulty(Some(&header(9, 1270, 16)), Some((270, 9))));
+        // much faster, limited by the maximum adjustment
+        assert_eq!(18, retarget.next_difficulty(Some(&header(9, 1001, 16)), Some((1, 9))));
+        // much slower
+        assert_eq!(14, retarget.next_difficulty(Some(&header(9, 9000, 16)), Some((8000, 9))));
+        // never below the minimum
+        assert_eq!(MIN_DIFFICULTY, retarget.next_difficulty(Some(&header(9, 9000, 1)), Some((8000, 9))));
+        // no blocks between the medians
+        assert_eq!(16, retarget.next_difficulty(Some(&header(9, 1540, 16)), Some((540, 0))));
+    }
+
+    #[test]
+    fn test_required_difficulty() {
+        let retarget = Retarget::new(10, 60).unwrap();
+        let mut headers: Vec<BlockHeader> = (0..20).map(|index| header(index, 1000 + index * 60, 16)).collect();
+
+        assert_eq!(16, retarget.required_difficulty(Some(&headers[19].clone()), branch(&headers)).unwrap());
+
+        // a single block stamped far in the future doesn´t change the
+        // difficulty
+        headers[19].timestamp = u64::max_value();
+        assert_eq!(16, retarget.required_difficulty(Some(&headers[19].clone()), branch(&headers)).unwrap());
+
+        // neither does one far in the past
+        headers[19].timestamp = 0;
+        assert_eq!(16, retarget.required_difficulty(Some(&headers[19].clone()), branch(&headers)).unwrap());
+    }
+
+    #[test]
+    fn test_median_time_past() {
+        let times = [100, 5000, 120, 130];
+        let header_at = |height: u64| Ok(header(height, times[height as usize], 16));
+
+        assert_eq!((100, 0), median_time_past(0, header_at).unwrap());
+        assert_eq!((130, 3), median_time_past(3, header_at).unwrap());
+    }
+}
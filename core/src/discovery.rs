@@ -0,0 +1,550 @@
+//! Learns new peers from other peers
+//!
+//! A peer registers itself at the peers from its peer file, the seeds.
+//! Because the seeds may not know its public key yet, `Register` is sent as
+//! an introduction that carries the public key of the sender. The answer
+//! `RegisterAck` and `GetPeersAck` contain the peers known by the sender.
+//!
+//! Anybody can send an introduction from a forged address, so an unknown
+//! peer first gets a `RegisterAck` with a cookie only. It has to register
+//! again with the cookie to prove that it receives messages at its
+//! address. Even then the answer is at most `MAX_AMPLIFICATION` times
+//! larger than the `Register`.
+//!
+//! Which peers are learned depends on the `TrustPolicy`.
+use carina_config::CarinaConfig;
+use carina_core_protocol::payloads::{EmptyPayload, PeerEntry, PeersPayload, RegisterPayload, MAX_PEERS};
+use carina_core_protocol::{Events, MessageBuilder, Payload};
+use config::{Config, Peer};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::PublicKey;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Maximum number of peers that are learned from other peers
+pub const MAX_LEARNED_PEERS: usize = 256;
+/// Seconds between two rounds of registering and exchanging peers
+const DISCOVERY_INTERVAL: u64 = 60;
+/// Seconds a cookie is valid at least, it is valid up to twice as long
+const COOKIE_LIFETIME: u64 = 120;
+/// Factor the peer list for an unknown peer may be larger than its `Register`
+const MAX_AMPLIFICATION: usize = 3;
+
+/// Decides which peers are learned from other peers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrustPolicy {
+    /// Only the peers from the peer file are used
+    Static,
+    /// Peers contained in the peer lists of seeds are learned
+    ///
+    /// Unknown peers that register themselves and prove their address get
+    /// the known peers, but they are not learned.
+    Seeds,
+    /// Every peer that registers itself and proves its address and every
+    /// peer contained in the peer list of a known peer is learned
+    Open,
+}
+
+impl TrustPolicy {
+    /// Parses the value of the `trust_policy` config key
+    pub fn from_str(value: &str) -> Result<Self, Error> {
+        match value {
+            "static" => Ok(TrustPolicy::Static),
+            "seeds"  => Ok(TrustPolicy::Seeds),
+            "open"   => Ok(TrustPolicy::Open),
+            _        => Err(format_err!("Unknown trust policy {}", value)),
+        }
+    }
+
+    /// true if an unknown peer may register itself
+    pub fn accepts_register(self) -> bool {
+        self == TrustPolicy::Open
+    }
+
+    /// true if an unknown peer that registers itself gets the known peers,
+    /// even if it is not learned
+    pub fn answers_register(self) -> bool {
+        self != TrustPolicy::Static
+    }
+
+    /// true if the peers in a peer list are learned
+    ///
+    /// # Params
+    /// - `from_seed` -> true if the list was sent by a peer from the peer file
+    pub fn accepts_list(self, from_seed: bool) -> bool {
+        match self {
+            TrustPolicy::Static => false,
+            TrustPolicy::Seeds  => from_seed,
+            TrustPolicy::Open   => true,
+        }
+    }
+}
+
+impl Default for TrustPolicy {
+    fn default() -> Self {
+        TrustPolicy::Seeds
+    }
+}
+
+/// Answer to a `Register`
+#[derive(Debug, PartialEq)]
+enum Answer {
+    /// All known peers, the sender was known already
+    Peers,
+    /// The known peers that fit into the size limit of an unknown sender,
+    /// true if the sender was learned
+    Unknown(bool),
+    /// A cookie the sender has to send back to prove its address
+    Cookie([u8; 32]),
+}
+
+/// Peers that are sent to the given peer
+///
+/// # Params
+/// - `config` -> config containing the known peers
+/// - `receiver` -> address of the peer that gets the list
+/// - `max_bytes` -> maximum size of the payload, `None` for no limit
+pub fn peer_list(config: &Config, receiver: &str, max_bytes: Option<usize>) -> PeersPayload {
+    let mut peers: Vec<PeerEntry> = config.peers
+        .values()
+        .filter(|peer| peer.address != receiver)
+        .map(|peer| PeerEntry {
+            address: peer.address.clone(),
+            public_key: peer.public_key,
+        })
+        .collect();
+    peers.sort_by(|a, b| a.address.cmp(&b.address));
+    peers.truncate(MAX_PEERS as usize);
+
+    let mut payload = PeersPayload { peers, cookie: None };
+    if let Some(max_bytes) = max_bytes {
+        while !payload.peers.is_empty() && payload.clone().to_bytes().len() > max_bytes {
+            payload.peers.pop();
+        }
+    }
+    payload
+}
+
+/// Current period of the cookies
+fn cookie_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0) / COOKIE_LIFETIME
+}
+
+/// Data a cookie is created for, it binds the address to the key
+fn cookie_data(peer: &Peer, epoch: u64) -> Vec<u8> {
+    let mut data = peer.address.as_bytes().to_vec();
+    data.extend_from_slice(&peer.public_key.0);
+    data.extend((0..8).map(|i| ((epoch >> (i * 8)) & 0xFF) as u8));
+    data
+}
+
+/// true if the cookie was created for the given peer in the current or the
+/// previous period
+fn valid_cookie(config: &Config, peer: &Peer, cookie: &[u8; 32]) -> bool {
+    let epoch = cookie_epoch();
+    [epoch, epoch.saturating_sub(1)]
+        .iter()
+        .any(|epoch| config.nacl.verify_cookie(cookie, &cookie_data(peer, *epoch)))
+}
+
+/// Adds or updates a peer learned from another peer
+///
+/// Keys and sessions of a replaced key are dropped.
+fn learn(config: &mut Config, peer: Peer) {
+    if let Some(old) = config.peers.get(&peer.address) {
+        if old.public_key != peer.public_key {
+            config.forget(old);
+        }
+    }
+
+    info!("[DISCOVERY] Learned peer {}", peer.address);
+    config.learned_peers.insert(peer.address.clone());
+    config.peers.insert(peer.address.clone(), peer);
+}
+
+/// Handles `Register` from the given peer
+///
+/// The known peers file is not written, so this can be called while the
+/// config is locked.
+///
+/// # Params
+/// - `config` -> config of this peer
+/// - `source` -> address of the sender
+/// - `introduced` -> public key of the sender, if it was an introduction
+/// - `cookie` -> cookie the sender sent back
+///
+/// # Return
+/// - `Result<(Peer, Answer), Error>` -> peer that gets the answer and the answer
+fn on_register(config: &mut Config, source: &str, introduced: Option<PublicKey>, cookie: Option<[u8; 32]>) -> Result<(Peer, Answer), Error> {
+    let known = config.peers.get(source).cloned();
+    let public_key = match (introduced, &known) {
+        (Some(public_key), _) => public_key,
+        (None, Some(peer))    => peer.public_key,
+        (None, None)          => return Err(format_err!("Register without a public key")),
+    };
+    let peer = Peer {
+        address: source.to_string(),
+        public_key,
+        sign_key: None,
+    };
+
+    match known {
+        Some(ref known) if known.public_key == public_key => return Ok((known.clone(), Answer::Peers)),
+        Some(_) if !config.learned_peers.contains(source) => {
+            return Err(format_err!("Key of the configured peer {} differs", source));
+        },
+        _ => (),
+    };
+
+    let answers = known.is_none() && config.trust_policy.answers_register();
+    if !config.trust_policy.accepts_register() && !answers {
+        return Err(format_err!("Trust policy doesn´t accept registering peers"));
+    }
+
+    // the address is forged easily, the answer must not go anywhere else
+    let proven = match cookie {
+        Some(ref cookie) => valid_cookie(config, &peer, cookie),
+        None             => false,
+    };
+    if !proven {
+        debug!("[DISCOVERY] Sending a cookie to {}", source);
+        let cookie = config.nacl.cookie(&cookie_data(&peer, cookie_epoch()));
+        return Ok((peer, Answer::Cookie(cookie)));
+    }
+
+    if !config.trust_policy.accepts_register() {
+        debug!("[DISCOVERY] Answering register of {} without learning it", source);
+        return Ok((peer, Answer::Unknown(false)));
+    }
+    if known.is_none() && config.learned_peers.len() >= MAX_LEARNED_PEERS {
+        return Err(format_err!("Too many learned peers"));
+    }
+
+    learn(config, peer.clone());
+    Ok((peer, Answer::Unknown(true)))
+}
+
+/// Handles a peer list from the given peer
+///
+/// Returns the newly learned peers. Like `on_register` the known peers
+/// file is not written.
+fn on_peers(config: &mut Config, source: &str, payload: PeersPayload) -> Vec<Peer> {
+    let from_seed = !config.learned_peers.contains(source);
+    if !config.trust_policy.accepts_list(from_seed) {
+        debug!("[DISCOVERY] Ignoring peers from {}", source);
+        return Vec::new();
+    }
+
+    let own_key = config.nacl.public_key();
+    let mut learned = Vec::new();
+    for entry in payload.peers {
+        if config.learned_peers.len() >= MAX_LEARNED_PEERS {
+            break;
+        }
+        // peers are only learned once, known keys are never replaced by
+        // hearsay, only by the peer itself
+        if entry.public_key == own_key || entry.address == source || config.peers.contains_key(&entry.address) {
+            continue;
+        }
+
+        let peer = Peer {
+            address: entry.address,
+            public_key: entry.public_key,
+            sign_key: None,
+        };
+        learn(config, peer.clone());
+        learned.push(peer);
+    }
+    learned
+}
+
+/// Handles a decrypted discovery event from the given address
+///
+/// `introduced` contains the public key of the sender if the message was
+/// an introduction, that is only the case for `Register`. The size of the
+/// `buffer` limits the answer to an unknown peer.
+pub(crate) fn handle(
+    socket: &UdpSocket,
+    carina_config: &Arc<Mutex<CarinaConfig>>,
+    source: &str,
+    introduced: Option<PublicKey>,
+    event: Events,
+    buffer: &[u8],
+) -> Result<(), Error> {
+    let config = {
+        let mut carina_config = match carina_config.lock() {
+            Ok(val) => val,
+            Err(e)  => return Err(format_err!("Error locking carina_config. {}", e)),
+        };
+        let config = &mut carina_config.config;
+
+        let learned = match event {
+            Events::Register    => {
+                let payload = RegisterPayload::parse(Parser::parse_payload(buffer))?;
+                let (peer, answer) = on_register(config, source, introduced, payload.cookie)?;
+                let (payload, learned) = match answer {
+                    Answer::Peers            => (peer_list(config, &peer.address, None), false),
+                    Answer::Unknown(learned) => {
+                        let max_bytes = buffer.len() * MAX_AMPLIFICATION;
+                        (peer_list(config, &peer.address, Some(max_bytes)), learned)
+                    },
+                    Answer::Cookie(cookie)   => (PeersPayload { peers: Vec::new(), cookie: Some(cookie) }, false),
+                };
+                send_peers(socket, config, Events::RegisterAck, &peer, payload)?;
+                learned
+            },
+            Events::GetPeers    => {
+                let peer = match config.peers.get(source) {
+                    Some(peer) => peer.clone(),
+                    None       => return Err(format_err!("Unknown peer {}", source)),
+                };
+                let payload = peer_list(config, &peer.address, None);
+                send_peers(socket, config, Events::GetPeersAck, &peer, payload)?;
+                false
+            },
+            Events::RegisterAck | Events::GetPeersAck => {
+                let payload = PeersPayload::parse(Parser::parse_payload(buffer))?;
+                // the peer wants a proof of the address before it answers
+                if let Some(cookie) = payload.cookie {
+                    if let (Events::RegisterAck, Some(peer)) = (event, config.peers.get(source).cloned()) {
+                        send_register(socket, config, &peer, Some(cookie));
+                    }
+                    return Ok(());
+                }
+                let learned = on_peers(config, source, payload);
+                for peer in &learned {
+                    send_register(socket, config, peer, None);
+                }
+                !learned.is_empty()
+            },
+            _                   => return Err(format_err!("Not a discovery event")),
+        };
+
+        if !learned {
+            return Ok(());
+        }
+        config.clone()
+    };
+
+    // the file is written without holding the lock
+    if let Err(e) = config.save_known_peers() {
+        error!("[DISCOVERY] Error saving known peers. {}", e);
+    }
+    Ok(())
+}
+
+/// Sends a peer list to the given peer
+///
+/// A peer that registered without being learned has no session, so the
+/// answer is sealed with its long-term key.
+fn send_peers(socket: &UdpSocket, config: &mut Config, event: Events, peer: &Peer, payload: PeersPayload) -> Result<(), Error> {
+    let key = if config.peers.contains_key(&peer.address) {
+        config.seal_key(peer)
+    } else {
+        config.nacl.precompute(&peer.public_key)
+    };
+    let message = MessageBuilder::new()
+        .set_event_code(Events::as_val(event))
+        .set_payload(payload)
+        .build_precomputed(&mut config.nacl, &key);
+
+    config.transport.send_to(socket, event, &message, &peer.address)
+}
+
+/// Registers at the given peer
+///
+/// `cookie` is the cookie of the `RegisterAck` of the peer, if it sent one.
+fn send_register(socket: &UdpSocket, config: &mut Config, peer: &Peer, cookie: Option<[u8; 32]>) {
+    let message = MessageBuilder::new()
+        .set_event_code(Events::as_val(Events::Register))
+        .set_payload(RegisterPayload { cookie })
+        .build_introduction(&mut config.nacl, &peer.public_key);
+
+    match config.transport.send_to(socket, Events::Register, &message, &peer.address) {
+        Ok(_)  => debug!("[DISCOVERY] Sent register to {}", peer.address),
+        Err(e) => error!("[DISCOVERY] Error sending register to {}. {}", peer.address, e),
+    };
+}
+
+/// Asks the given peer for its peers
+fn send_get_peers(socket: &UdpSocket, config: &mut Config, peer: &Peer) {
+    let key = config.seal_key(peer);
+    let message = MessageBuilder::new()
+        .set_event_code(Events::as_val(Events::GetPeers))
+        .set_payload(EmptyPayload::new())
+        .build_precomputed(&mut config.nacl, &key);
+
+    match config.transport.send_to(socket, Events::GetPeers, &message, &peer.address) {
+        Ok(_)  => debug!("[DISCOVERY] Sent get_peers to {}", peer.address),
+        Err(e) => error!("[DISCOVERY] Error sending get_peers to {}. {}", peer.address, e),
+    };
+}
+
+/// Starts a thread that registers at the seeds and exchanges peers
+///
+/// The seeds answer with their peers. The learned peers are asked for
+/// their peers regularly.
+pub(crate) fn start(carina_config: Arc<Mutex<CarinaConfig>>, socket: UdpSocket) -> JoinHandle<()> {
+    debug!("[THREAD_DISCOVERY] Starting discovery thread");
+    thread::spawn(move || loop {
+        let mut config = match carina_config.lock() {
+            Ok(val) => val.config.clone(),
+            Err(e)  => {
+                error!("[THREAD_DISCOVERY] Error locking carina_config. {}", e);
+                return;
+            }
+        };
+
+        for (address, peer) in config.peers.clone() {
+            if !config.learned_peers.contains(&address) {
+                send_register(&socket, &mut config, &peer, None);
+            } else if config.trust_policy != TrustPolicy::Static {
+                send_get_peers(&socket, &mut config, &peer);
+            }
+        }
+        thread::sleep(Duration::from_secs(DISCOVERY_INTERVAL));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sodiumoxide::crypto::box_;
+
+    fn peer(address: &str) -> Peer {
+        Peer {
+            address: address.to_string(),
+            public_key: box_::gen_keypair().0,
+            sign_key: None,
+        }
+    }
+
+    fn config(trust_policy: TrustPolicy) -> Config {
+        let mut config = Config::default();
+        config.trust_policy = trust_policy;
+        let seed = peer("127.0.0.1:45002");
+        config.peers.insert(seed.address.clone(), seed);
+        config
+    }
+
+    fn entries(peers: &[&Peer]) -> PeersPayload {
+        PeersPayload {
+            peers: peers.iter().map(|peer| PeerEntry {
+                address: peer.address.clone(),
+                public_key: peer.public_key,
+            }).collect(),
+            cookie: None,
+        }
+    }
+
+    /// Registers the peer at the config, first to get the cookie and then with the cookie
+    fn register(config: &mut Config, peer: &Peer) -> Result<Answer, Error> {
+        let cookie = match on_register(config, &peer.address, Some(peer.public_key), None)? {
+            (_, Answer::Cookie(cookie)) => cookie,
+            (_, answer)                 => return Ok(answer),
+        };
+        on_register(config, &peer.address, Some(peer.public_key), Some(cookie)).map(|(_, answer)| answer)
+    }
+
+    #[test]
+    fn test_register() {
+        let new = peer("127.0.0.1:45010");
+
+        // seeds answer unknown peers without learning them
+        let mut seeds = config(TrustPolicy::Seeds);
+        assert_eq!(Answer::Unknown(false), register(&mut seeds, &new).unwrap());
+        assert!(!seeds.peers.contains_key(&new.address));
+
+        let mut fixed = config(TrustPolicy::Static);
+        assert!(on_register(&mut fixed, &new.address, Some(new.public_key), None).is_err());
+
+        let mut open = config(TrustPolicy::Open);
+        assert_eq!(Answer::Unknown(true), register(&mut open, &new).unwrap());
+        assert!(open.learned_peers.contains(&new.address));
+
+        // the key of a configured peer is never replaced
+        let seed = open.peers["127.0.0.1:45002"].clone();
+        assert!(on_register(&mut open, &seed.address, Some(new.public_key), None).is_err());
+        assert_eq!((seed.clone(), Answer::Peers), on_register(&mut open, &seed.address, None, None).unwrap());
+    }
+
+    #[test]
+    fn test_register_cookie() {
+        let mut config = config(TrustPolicy::Open);
+        let new = peer("127.0.0.1:45010");
+        let other = peer("127.0.0.1:45011");
+
+        let cookie = match on_register(&mut config, &new.address, Some(new.public_key), None).unwrap() {
+            (_, Answer::Cookie(cookie)) => cookie,
+            (_, answer)                 => panic!("Expected a cookie, got {:?}", answer),
+        };
+        assert!(!config.peers.contains_key(&new.address));
+
+        // the cookie only proves the address and key it was sent to
+        let (_, answer) = on_register(&mut config, &other.address, Some(other.public_key), Some(cookie)).unwrap();
+        assert_ne!(Answer::Unknown(true), answer);
+        let (_, answer) = on_register(&mut config, &new.address, Some(other.public_key), Some(cookie)).unwrap();
+        assert_ne!(Answer::Unknown(true), answer);
+        assert!(!config.peers.contains_key(&new.address));
+        assert!(!config.peers.contains_key(&other.address));
+
+        let (_, answer) = on_register(&mut config, &new.address, Some(new.public_key), Some(cookie)).unwrap();
+        assert_eq!(Answer::Unknown(true), answer);
+        assert!(config.peers.contains_key(&new.address));
+    }
+
+    #[test]
+    fn test_peers_from_seed() {
+        let mut config = config(TrustPolicy::Seeds);
+        let own = Peer {
+            address: "127.0.0.1:45000".to_string(),
+            public_key: config.nacl.public_key(),
+            sign_key: None,
+        };
+        let first = peer("127.0.0.1:45010");
+        let second = peer("127.0.0.1:45011");
+
+        let learned = on_peers(&mut config, "127.0.0.1:45002", entries(&[&first, &own]));
+        assert_eq!(vec![first.clone()], learned);
+
+        // learned peers aren´t trusted to introduce other peers
+        assert!(on_peers(&mut config, &first.address, entries(&[&second])).is_empty());
+        assert!(!config.peers.contains_key(&second.address));
+    }
+
+    #[test]
+    fn test_static() {
+        let mut config = config(TrustPolicy::Static);
+        let first = peer("127.0.0.1:45010");
+
+        assert!(on_peers(&mut config, "127.0.0.1:45002", entries(&[&first])).is_empty());
+        assert_eq!(1, config.peers.len());
+    }
+
+    #[test]
+    fn test_peer_list() {
+        let mut config = config(TrustPolicy::Seeds);
+
+        assert_eq!(1, peer_list(&config, "127.0.0.1:45010", None).peers.len());
+        assert!(peer_list(&config, "127.0.0.1:45002", None).peers.is_empty());
+
+        // the list for an unknown peer is limited by the size of its request
+        for port in 45020..45030 {
+            let peer = peer(&format!("127.0.0.1:{}", port));
+            config.peers.insert(peer.address.clone(), peer);
+        }
+        let max_bytes = RegisterPayload { cookie: None }.to_bytes().len() / 2;
+        let limited = peer_list(&config, "127.0.0.1:45010", Some(max_bytes));
+        assert!(!limited.peers.is_empty());
+        assert!(limited.peers.len() < 11);
+        assert!(limited.clone().to_bytes().len() <= max_bytes);
+        assert!(peer_list(&config, "127.0.0.1:45010", Some(0)).peers.is_empty());
+    }
+}
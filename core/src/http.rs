@@ -0,0 +1,208 @@
+//! Minimal HTTP server for the read only listeners of the node
+//!
+//! Only `GET` requests without a body are supported. Every connection
+//! serves a single request and is closed after the response.
+use failure::Error;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Maximum size of the request line and all headers in bytes
+const MAX_HEAD: usize = 8 * 1024;
+/// Seconds a client has to send its request or read the response
+const CLIENT_TIMEOUT: u64 = 5;
+
+/// Parsed request
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HttpRequest {
+    /// method of the request, for example `GET`
+    pub method: String,
+    /// path without the query
+    pub path: String,
+    /// parameters of the query
+    pub query: HashMap<String, String>,
+}
+
+impl HttpRequest {
+    /// Parses the request line, for example `GET /blocks?from=1 HTTP/1.1`
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/") => (method, target),
+            _ => return Err(format_err!("Invalid request line {}", line)),
+        };
+
+        let (path, query) = match target.find('?') {
+            Some(position) => (&target[..position], &target[position + 1..]),
+            None           => (target, ""),
+        };
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.find('=') {
+                Some(position) => (pair[..position].to_string(), pair[position + 1..].to_string()),
+                None           => (pair.to_string(), String::new()),
+            })
+            .collect();
+
+        Ok(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query,
+        })
+    }
+}
+
+/// Response to a request
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HttpResponse {
+    /// status code
+    pub status: u16,
+    /// value of the `Content-Type` header
+    pub content_type: &'static str,
+    /// body of the response
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// Creates a response with status 200
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    /// Creates a response with a plain text body
+    pub fn text(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", body),
+        }
+    }
+
+    /// Serializes the response including the headers
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _   => "Internal Server Error",
+        };
+
+        let mut bytes = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        ).into_bytes();
+        bytes.extend_from_slice(self.body.as_bytes());
+        bytes
+    }
+}
+
+/// Reads the request line and skips all headers
+fn read_request(stream: &TcpStream) -> Result<HttpRequest, Error> {
+    let mut reader = BufReader::new(stream.take(MAX_HEAD as u64));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    HttpRequest::parse(request_line.trim())
+}
+
+/// Answers a single request
+fn handle<F>(mut stream: TcpStream, handler: &F) -> Result<(), Error>
+where
+    F: Fn(&HttpRequest) -> HttpResponse,
+{
+    stream.set_read_timeout(Some(Duration::from_secs(CLIENT_TIMEOUT)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(CLIENT_TIMEOUT)))?;
+
+    let response = match read_request(&stream) {
+        Ok(ref request) if request.method != "GET" => HttpResponse::text(405, "Only GET is supported"),
+        Ok(ref request)                            => handler(request),
+        Err(e)                                     => HttpResponse::text(400, &e.to_string()),
+    };
+
+    stream.write_all(&response.to_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Starts a thread that answers the requests to the given address with the
+/// handler
+///
+/// # Params
+/// - `name` -> name of the thread for the log, for example `THREAD_API`
+/// - `address` -> address to listen on
+/// - `handler` -> creates the response for a request
+pub(crate) fn serve<F>(name: &'static str, address: &str, handler: F) -> Option<JoinHandle<()>>
+where
+    F: Fn(&HttpRequest) -> HttpResponse + Send + 'static,
+{
+    let listener = match TcpListener::bind(address) {
+        Ok(val) => val,
+        Err(e)  => {
+            error!("[{}] Error listening on {}. {}", name, address, e);
+            return None;
+        }
+    };
+    info!("[{}] Listening on {}", name, address);
+
+    Some(thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(val) => val,
+                Err(e)  => {
+                    error!("[{}] Error accepting connection. {}", name, e);
+                    continue;
+                }
+            };
+
+            if let Err(e) = handle(stream, &handler) {
+                debug!("[{}] Error answering request. {}", name, e);
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request = HttpRequest::parse("GET /blocks?from=1&to=5&full HTTP/1.1").unwrap();
+        assert_eq!("GET", request.method);
+        assert_eq!("/blocks", request.path);
+        assert_eq!(Some(&String::from("1")), request.query.get("from"));
+        assert_eq!(Some(&String::from("5")), request.query.get("to"));
+        assert_eq!(Some(&String::new()), request.query.get("full"));
+
+        assert!(HttpRequest::parse("GET /status").is_err());
+        assert!(HttpRequest::parse("").is_err());
+    }
+
+    #[test]
+    fn test_response() {
+        let response = HttpResponse::ok("application/json", String::from("{}"));
+        assert_eq!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+            String::from_utf8(response.to_bytes()).unwrap()
+        );
+    }
+}
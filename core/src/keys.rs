@@ -0,0 +1,87 @@
+//! Caches the keys shared with the peers
+use carina_core_protocol::Nacl;
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{PrecomputedKey, PublicKey};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Cache of precomputed keys by the public key of the peer
+///
+/// Computing the shared key is the most expensive part of sealing and
+/// opening a message, so it is done once per peer. Clones share the cache.
+#[derive(Clone, Default)]
+pub struct KeyCache {
+    /// precomputed keys by the bytes of the public key
+    keys: Arc<Mutex<HashMap<[u8; 32], PrecomputedKey>>>,
+}
+
+impl KeyCache {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the key shared with the given peer
+    ///
+    /// The key is computed on first use.
+    pub fn get(&self, nacl: &Nacl, public_key: &PublicKey) -> PrecomputedKey {
+        match self.keys.lock() {
+            Ok(mut keys) => keys
+                .entry(public_key.0)
+                .or_insert_with(|| nacl.precompute(public_key))
+                .clone(),
+            Err(_)       => nacl.precompute(public_key),
+        }
+    }
+
+    /// Removes the key of the given peer
+    ///
+    /// Must be called when a peer is removed or its key changes.
+    pub fn remove(&self, public_key: &PublicKey) {
+        if let Ok(mut keys) = self.keys.lock() {
+            keys.remove(&public_key.0);
+        }
+    }
+
+    /// Number of cached keys
+    pub fn len(&self) -> usize {
+        match self.keys.lock() {
+            Ok(keys) => keys.len(),
+            Err(_)   => 0,
+        }
+    }
+
+    /// true if no key is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// the keys are secret, so only their number is shown
+impl fmt::Debug for KeyCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "KeyCache {{ keys: {} }}", self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sodiumoxide::crypto::box_;
+
+    #[test]
+    fn test_cache() {
+        let (_, secret_key) = box_::gen_keypair();
+        let (public_key, _) = box_::gen_keypair();
+        let nacl = Nacl::new(secret_key);
+        let cache = KeyCache::new();
+
+        let key = cache.get(&nacl, &public_key);
+        assert_eq!(key, cache.clone().get(&nacl, &public_key));
+        assert_eq!(key, nacl.precompute(&public_key));
+        assert_eq!(1, cache.len());
+
+        cache.remove(&public_key);
+        assert!(cache.is_empty());
+    }
+}
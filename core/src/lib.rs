@@ -20,6 +20,7 @@
 //!         socket: /tmp/carina.sock
 //!         peers: ./example_peers.yml
 //!         storage: ./block_data
+//!         storage_backend: file
 //!         uri: 127.0.0.1:45001
 //!         secret_key: v+rETx4VtczK/QSvl9OBfJfgVPEdjNpquVUq/8GFmWo=
 //!         "#).unwrap();
@@ -35,19 +36,60 @@ extern crate futures;
 extern crate futures_cpupool;
 #[macro_use]
 extern crate log;
+extern crate protocol_builder_parser;
 extern crate sodiumoxide;
 extern crate yaml_rust;
 
+mod api;
 /// See the config file struct for more information
 mod carina_config;
+mod chain;
 mod config;
+mod control;
+mod difficulty;
+mod discovery;
 mod event;
+mod http;
+mod keys;
+mod mempool;
+mod metrics;
+mod miner;
+mod orphan;
+mod proof;
+mod quorum;
+mod reload;
+mod replay;
+mod session;
+mod storage;
+mod sync;
+mod transport;
 mod udp;
+mod validation;
 
-pub use config::{Config, Peer};
+pub use api::MAX_BLOCKS;
+pub use chain::{notify_reorgs, ChainManager, ChainUpdate};
+pub use config::{Config, Peer, StorageBackend};
+pub use control::{read_frame, request, write_frame, Request, Response, MAX_FRAME};
+pub use difficulty::{block_work, leading_zero_bits, median_time_past, meets_target, Retarget, MAX_DIFFICULTY, MEDIAN_TIME_SPAN, MIN_DIFFICULTY};
+pub use discovery::{peer_list, TrustPolicy, MAX_LEARNED_PEERS};
 pub use event::Event;
+pub use keys::KeyCache;
+pub use mempool::Mempool;
+pub use metrics::{Metrics, PEER_TIMEOUT};
+pub use miner::{Miner, MiningStats};
+pub use orphan::{OrphanPool, MAX_ORPHANS, ORPHAN_EXPIRY};
+pub use proof::get_proof;
+pub use quorum::{Quorum, RoundResult, VoteRounds, DEFAULT_MIN_VOTERS};
+pub use reload::{apply_config, watch_config};
+pub use replay::ReplayProtection;
+pub use session::{Sessions, HANDSHAKE_TIMEOUT, SESSION_GRACE};
+pub use validation::{latest_timestamp, validate_block, verify_chain, InvalidBlock, InvalidChain, DEFAULT_MAX_TIME_DRIFT};
+pub use storage::{open_storage, BlockStorage, FileStorage, MemoryStorage};
+pub use sync::{get_block, get_headers, get_tip, send_requests, SyncManager, SyncRequest};
+pub use transport::Transport;
 pub use carina_config::{CarinaConfig, CarinaConfigBuilder};
 
+use carina_core_protocol::{Events, Nacl};
 use std::net::UdpSocket;
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
@@ -57,13 +99,61 @@ pub fn init(builder: CarinaConfigBuilder) -> (JoinHandle<()>, UdpSocket, Arc<Mut
     sodiumoxide::init().unwrap();
 
     let carina_config = builder.build();
+    if let Some(level) = carina_config.config.log_level {
+        log::set_max_level(level);
+    }
 
     let socket = UdpSocket::bind(&carina_config.config.uri).unwrap();
     info!("[THREAD_UDP] Listening on  {}", carina_config.config.uri);
+
+    if let Some(ref chain) = carina_config.chain {
+        let listeners = carina_config.events.get(&Events::Reorg).cloned().unwrap_or_default();
+        match chain.lock() {
+            Ok(mut chain) => chain.set_listeners(listeners),
+            Err(e)        => error!("[CHAIN] Error locking chain manager. {}", e),
+        };
+    }
+    transport::start(carina_config.config.transport.clone(), socket.try_clone().unwrap());
     let state = Arc::new(Mutex::new(carina_config));
+    session::start(Arc::clone(&state), socket.try_clone().unwrap());
+    discovery::start(Arc::clone(&state), socket.try_clone().unwrap());
+    control::start(Arc::clone(&state), socket.try_clone().unwrap());
+    api::start(Arc::clone(&state));
+    metrics::start(Arc::clone(&state));
 
     let socket_udp = socket.try_clone().unwrap();
     let udp_handle = udp::start(Arc::clone(&state), socket_udp);
 
     (udp_handle, socket.try_clone().unwrap(), Arc::clone(&state))
 }
+
+/// Initialises the library as a client of the peers
+///
+/// Instead of `uri` an ephemeral port is bound, so a client can run next to
+/// a node that uses the same config. The client uses its own identity from
+/// `client_secret_key` or a new one, because the nonces of the node´s key
+/// must not be used twice. Messages must be built with
+/// `MessageBuilder::build_introduction`, the peers only accept the events of
+/// `Events::is_client` from a client they accept, see
+/// `Config::accepts_client`.
+pub fn init_client(builder: CarinaConfigBuilder) -> (JoinHandle<()>, UdpSocket, Arc<Mutex<CarinaConfig>>) {
+    sodiumoxide::init().unwrap();
+
+    let mut carina_config = builder.build();
+    if let Some(level) = carina_config.config.log_level {
+        log::set_max_level(level);
+    }
+    carina_config.config.nacl = match carina_config.config.client_nacl.take() {
+        Some(nacl) => nacl,
+        None       => Nacl::default(),
+    };
+
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    info!("[THREAD_UDP] Client listening on {}", socket.local_addr().unwrap());
+
+    transport::start(carina_config.config.transport.clone(), socket.try_clone().unwrap());
+    let state = Arc::new(Mutex::new(carina_config));
+    let udp_handle = udp::start(Arc::clone(&state), socket.try_clone().unwrap());
+
+    (udp_handle, socket, state)
+}
@@ -0,0 +1,109 @@
+//! Content waiting for the next block
+use carina_core_protocol::payloads::block::NewBlockContentPayload;
+use std::collections::HashMap;
+
+/// Content for the next block by its unique key
+///
+/// Content with a key that already exists replaces the old content.
+/// Content stays in the mempool until a block containing it is part of the
+/// main chain, so it is proposed again if mining a block fails or the block
+/// ends up on a side branch.
+#[derive(Clone, Debug, Default)]
+pub struct Mempool {
+    /// content by its unique key
+    content: HashMap<String, String>,
+}
+
+impl Mempool {
+    /// Creates an empty mempool
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds content for the next block
+    ///
+    /// # Return
+    /// - `bool` -> false if the key and the content are both empty
+    pub fn add(&mut self, unique_key: String, content: String) -> bool {
+        if unique_key.is_empty() && content.is_empty() {
+            return false;
+        }
+
+        self.content.insert(unique_key, content);
+        true
+    }
+
+    /// All content as entries for a block
+    pub fn entries(&self) -> Vec<NewBlockContentPayload> {
+        self.content
+            .iter()
+            .map(|(unique_key, content)| NewBlockContentPayload {
+                unique_key: unique_key.clone(),
+                content: content.clone(),
+            })
+            .collect()
+    }
+
+    /// Removes the content of a block that is part of the main chain
+    ///
+    /// Content that was replaced in the meantime stays.
+    pub fn confirm(&mut self, entries: &[NewBlockContentPayload]) {
+        for entry in entries {
+            if self.content.get(&entry.unique_key) == Some(&entry.content) {
+                self.content.remove(&entry.unique_key);
+            }
+        }
+    }
+
+    /// All content by its unique key
+    pub fn content(&self) -> &HashMap<String, String> {
+        &self.content
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.content.len()
+    }
+
+    /// true if there is no content
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_confirm() {
+        let mut mempool = Mempool::new();
+
+        assert!(!mempool.add(String::new(), String::new()));
+        assert!(mempool.add("key".to_string(), "first".to_string()));
+        assert!(mempool.add("key".to_string(), "second".to_string()));
+        assert_eq!(1, mempool.len());
+
+        // the content stays until its block is part of the main chain
+        let entries = mempool.entries();
+        assert_eq!("second", entries[0].content);
+        assert_eq!(1, mempool.len());
+
+        mempool.confirm(&entries);
+        assert!(mempool.is_empty());
+    }
+
+    #[test]
+    fn test_confirm_keeps_replaced_content() {
+        let mut mempool = Mempool::new();
+        mempool.add("key".to_string(), "first".to_string());
+        mempool.add("other".to_string(), "other".to_string());
+
+        let entries = mempool.entries();
+        mempool.add("key".to_string(), "second".to_string());
+        mempool.confirm(&entries);
+
+        assert_eq!(1, mempool.len());
+        assert_eq!(Some(&"second".to_string()), mempool.content().get("key"));
+    }
+}
@@ -0,0 +1,304 @@
+//! Metrics of the node in the Prometheus text format
+//!
+//! Enabled with the config key `metrics`, the metrics are served at
+//! `/metrics`. Counters are collected while the node is running, gauges
+//! like the chain height are read when the metrics are requested.
+use carina_config::CarinaConfig;
+use carina_core_protocol::Events;
+use failure::Error;
+use http;
+use http::HttpResponse;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the buckets for the handler latency in seconds
+const LATENCY_BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+/// Seconds after the last message of a peer until it counts as down
+pub const PEER_TIMEOUT: u64 = 120;
+
+/// Distribution of the handler latency of a single event
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// number of observations per bucket of `LATENCY_BUCKETS`, not cumulative
+    buckets: [u64; 8],
+    /// sum of all observations in seconds
+    sum: f64,
+    /// number of observations
+    count: u64,
+}
+
+impl Histogram {
+    /// Adds an observation
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Counters collected while the node is running
+#[derive(Debug, Default)]
+struct Registry {
+    /// received messages by their event
+    received: HashMap<Events, u64>,
+    /// messages from known peers that could not be opened
+    decrypt_failures: u64,
+    /// messages from unknown sources that were dropped
+    unknown_sources: u64,
+    /// failed handlers by their event
+    handler_errors: HashMap<Events, u64>,
+    /// latency of the handlers by their event
+    handler_latency: HashMap<Events, Histogram>,
+    /// time of the last message by the address of the peer
+    last_seen: HashMap<String, Instant>,
+}
+
+/// Counters of the node
+///
+/// Clones share their state.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Metrics {
+    /// Creates a new instance with all counters set to 0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a received message
+    pub fn received(&self, event: Events) {
+        self.update(|registry| *registry.received.entry(event).or_insert(0) += 1);
+    }
+
+    /// Remembers that the given peer sent a message
+    pub fn seen(&self, address: &str) {
+        self.update(|registry| {
+            registry.last_seen.insert(address.to_string(), Instant::now());
+        });
+    }
+
+    /// Counts a message from a known peer that could not be opened
+    pub fn decrypt_failure(&self) {
+        self.update(|registry| registry.decrypt_failures += 1);
+    }
+
+    /// Counts a dropped message from an unknown source
+    pub fn unknown_source(&self) {
+        self.update(|registry| registry.unknown_sources += 1);
+    }
+
+    /// Counts a failed handler
+    pub fn handler_error(&self, event: Events) {
+        self.update(|registry| *registry.handler_errors.entry(event).or_insert(0) += 1);
+    }
+
+    /// Records the time a handler took
+    pub fn handler_latency(&self, event: Events, latency: Duration) {
+        let seconds = latency.as_secs() as f64 + f64::from(latency.subsec_nanos()) / 1_000_000_000.0;
+        self.update(|registry| registry.handler_latency.entry(event).or_insert_with(Histogram::default).observe(seconds));
+    }
+
+    /// true if the peer sent a message within `PEER_TIMEOUT`
+    pub fn is_alive(&self, address: &str) -> bool {
+        match self.registry.lock() {
+            Ok(registry) => registry.last_seen
+                .get(address)
+                .map(|seen| seen.elapsed() < Duration::from_secs(PEER_TIMEOUT))
+                .unwrap_or(false),
+            Err(_)       => false,
+        }
+    }
+
+    /// Changes the counters
+    fn update<F: FnOnce(&mut Registry)>(&self, change: F) {
+        match self.registry.lock() {
+            Ok(mut registry) => change(&mut registry),
+            Err(e)           => error!("[METRICS] Error locking registry. {}", e),
+        };
+    }
+}
+
+/// Writes the `HELP` and `TYPE` lines of a metric
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Writes a counter with one value per event, sorted by the event
+fn per_event(out: &mut String, name: &str, help: &str, values: &HashMap<Events, u64>) {
+    describe(out, name, "counter", help);
+
+    let mut values = values.iter().collect::<Vec<_>>();
+    values.sort_by_key(|(event, _)| Events::as_val(**event));
+    for (event, value) in values {
+        let _ = writeln!(out, "{}{{event=\"{:?}\"}} {}", name, event, value);
+    }
+}
+
+/// Renders all metrics of the node
+pub(crate) fn render(carina_config: &CarinaConfig) -> Result<String, Error> {
+    let config = &carina_config.config;
+    let mut out = String::new();
+
+    {
+        let registry = match config.metrics.registry.lock() {
+            Ok(val) => val,
+            Err(e)  => return Err(format_err!("Error locking registry. {}", e)),
+        };
+
+        per_event(&mut out, "carina_packets_received_total", "Messages received per event", &registry.received);
+        per_event(&mut out, "carina_packets_sent_total", "Messages sent per event", &config.transport.sent());
+
+        describe(&mut out, "carina_decrypt_failures_total", "counter", "Messages from known peers that could not be opened");
+        let _ = writeln!(out, "carina_decrypt_failures_total {}", registry.decrypt_failures);
+        describe(&mut out, "carina_unknown_source_drops_total", "counter", "Messages from unknown sources that were dropped");
+        let _ = writeln!(out, "carina_unknown_source_drops_total {}", registry.unknown_sources);
+        describe(&mut out, "carina_replays_rejected_total", "counter", "Messages rejected as replays");
+        let _ = writeln!(out, "carina_replays_rejected_total {}", config.replay.rejected());
+
+        per_event(&mut out, "carina_handler_errors_total", "Failed event handlers per event", &registry.handler_errors);
+
+        describe(&mut out, "carina_handler_duration_seconds", "histogram", "Time the event handlers took per event");
+        let mut latencies = registry.handler_latency.iter().collect::<Vec<_>>();
+        latencies.sort_by_key(|(event, _)| Events::as_val(**event));
+        for (event, histogram) in latencies {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(out, "carina_handler_duration_seconds_bucket{{event=\"{:?}\",le=\"{}\"}} {}", event, bound, cumulative);
+            }
+            let _ = writeln!(out, "carina_handler_duration_seconds_bucket{{event=\"{:?}\",le=\"+Inf\"}} {}", event, histogram.count);
+            let _ = writeln!(out, "carina_handler_duration_seconds_sum{{event=\"{:?}\"}} {}", event, histogram.sum);
+            let _ = writeln!(out, "carina_handler_duration_seconds_count{{event=\"{:?}\"}} {}", event, histogram.count);
+        }
+    }
+
+    let hash_rate = match carina_config.miner {
+        Some(ref miner) => match miner.lock() {
+            Ok(val) => val.stats().map(|stats| stats.hash_rate()).unwrap_or(0.0),
+            Err(e)  => return Err(format_err!("Error locking miner. {}", e)),
+        },
+        None            => 0.0,
+    };
+    describe(&mut out, "carina_mining_hash_rate", "gauge", "Hashes per second of the current or last mining job");
+    let _ = writeln!(out, "carina_mining_hash_rate {}", hash_rate);
+
+    if let Some(ref chain) = carina_config.chain {
+        let orphans = match chain.lock() {
+            Ok(val) => val.orphan_count(),
+            Err(e)  => return Err(format_err!("Error locking chain. {}", e)),
+        };
+        let tip = match carina_config.storage()?.lock() {
+            Ok(val) => val.tip(),
+            Err(e)  => return Err(format_err!("Error locking storage. {}", e)),
+        };
+
+        if let Some((height, _)) = tip {
+            describe(&mut out, "carina_chain_height", "gauge", "Height of the latest block");
+            let _ = writeln!(out, "carina_chain_height {}", height);
+        }
+        describe(&mut out, "carina_orphan_blocks", "gauge", "Blocks waiting for their parent");
+        let _ = writeln!(out, "carina_orphan_blocks {}", orphans);
+    }
+
+    describe(&mut out, "carina_pending_messages", "gauge", "Reliable messages that are not acknowledged yet");
+    let _ = writeln!(out, "carina_pending_messages {}", config.transport.pending());
+
+    describe(&mut out, "carina_peer_up", "gauge", "1 if the peer sent a message recently");
+    let mut peers = config.peers.keys().collect::<Vec<_>>();
+    peers.sort();
+    for address in peers {
+        let up = if config.metrics.is_alive(address) { 1 } else { 0 };
+        let _ = writeln!(out, "carina_peer_up{{peer=\"{}\"}} {}", address, up);
+    }
+
+    Ok(out)
+}
+
+/// Starts the metrics listener if `metrics` is configured
+pub(crate) fn start(carina_config: Arc<Mutex<CarinaConfig>>) -> Option<JoinHandle<()>> {
+    let address = match carina_config.lock() {
+        Ok(val) => val.config.metrics_address.clone(),
+        Err(e)  => {
+            error!("[THREAD_METRICS] Error locking carina_config. {}", e);
+            return None;
+        }
+    };
+
+    match address {
+        Some(address) => http::serve("THREAD_METRICS", &address, move |request| {
+            if request.path != "/metrics" {
+                return HttpResponse::text(404, "Not found");
+            }
+
+            let metrics = match carina_config.lock() {
+                Ok(val) => render(&val),
+                Err(e)  => Err(format_err!("Error locking carina_config. {}", e)),
+            };
+            match metrics {
+                Ok(metrics) => HttpResponse::ok("text/plain; version=0.0.4", metrics),
+                Err(e)      => {
+                    error!("[THREAD_METRICS] Error rendering metrics. {}", e);
+                    HttpResponse::text(500, &e.to_string())
+                }
+            }
+        }),
+        None          => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Config, Peer};
+    use sodiumoxide::crypto::box_;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.0005);
+        histogram.observe(0.2);
+        histogram.observe(10.0);
+
+        assert_eq!([1, 0, 0, 0, 0, 1, 0, 0], histogram.buckets);
+        assert_eq!(3, histogram.count);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut config = Config::default();
+        for address in &["127.0.0.1:45002", "127.0.0.1:45003"] {
+            config.peers.insert(address.to_string(), Peer {
+                address: address.to_string(),
+                public_key: box_::gen_keypair().0,
+                sign_key: None,
+            });
+        }
+        config.metrics.received(Events::Ping);
+        config.metrics.received(Events::Ping);
+        config.metrics.seen("127.0.0.1:45002");
+        config.metrics.decrypt_failure();
+        config.metrics.handler_error(Events::GetTip);
+        config.metrics.handler_latency(Events::Ping, Duration::from_millis(2));
+        let carina_config = CarinaConfig::new(config, HashMap::new());
+
+        let metrics = render(&carina_config).unwrap();
+        assert!(metrics.contains("carina_packets_received_total{event=\"Ping\"} 2\n"));
+        assert!(metrics.contains("carina_decrypt_failures_total 1\n"));
+        assert!(metrics.contains("carina_unknown_source_drops_total 0\n"));
+        assert!(metrics.contains("carina_handler_errors_total{event=\"GetTip\"} 1\n"));
+        assert!(metrics.contains("carina_handler_duration_seconds_bucket{event=\"Ping\",le=\"0.001\"} 0\n"));
+        assert!(metrics.contains("carina_handler_duration_seconds_bucket{event=\"Ping\",le=\"0.005\"} 1\n"));
+        assert!(metrics.contains("carina_handler_duration_seconds_count{event=\"Ping\"} 1\n"));
+        assert!(metrics.contains("carina_mining_hash_rate 0\n"));
+        assert!(metrics.contains("carina_peer_up{peer=\"127.0.0.1:45002\"} 1\n"));
+        assert!(metrics.contains("carina_peer_up{peer=\"127.0.0.1:45003\"} 0\n"));
+    }
+}
@@ -0,0 +1,275 @@
+//! Searches the nonce of a block on multiple threads
+//!
+//! Every worker thread gets its own, disjoint range of nonces. The first
+//! worker that finds a hash meeting the difficulty stops all other workers.
+//! A running job can be cancelled at any time, for example when a valid
+//! block for the same height arrives from another peer.
+use carina_core_protocol::payloads::block::Block;
+use difficulty::meets_target;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::u64;
+
+/// Number of hashes a worker calculates before updating the counter
+const HASH_BATCH: usize = 1024;
+/// Interval in seconds in which the progress of a job is logged
+const PROGRESS_INTERVAL: u64 = 10;
+
+/// Progress of a mining job
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MiningStats {
+    /// height of the block that is mined
+    pub height: u64,
+    /// number of hashes tried so far
+    pub hashes: u64,
+    /// time since the job was started
+    pub elapsed: Duration,
+    /// true as long as the workers are searching
+    pub running: bool,
+}
+
+impl MiningStats {
+    /// Hashes per second
+    pub fn hash_rate(&self) -> f64 {
+        let seconds = self.elapsed.as_secs() as f64 + f64::from(self.elapsed.subsec_nanos()) / 1_000_000_000.0;
+
+        if seconds > 0.0 {
+            self.hashes as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// State of a single mining job shared with its threads
+#[derive(Debug)]
+struct Job {
+    /// height of the block that is mined
+    height: u64,
+    /// tells the workers to stop
+    stop: Arc<AtomicBool>,
+    /// set if the job was cancelled from the outside
+    cancelled: Arc<AtomicBool>,
+    /// number of hashes tried by all workers
+    hashes: Arc<AtomicUsize>,
+    /// time the job was started
+    started: Instant,
+}
+
+/// Miner running one job at a time
+#[derive(Debug)]
+pub struct Miner {
+    /// number of worker threads per job
+    threads: usize,
+    /// current or last job
+    job: Option<Job>,
+}
+
+impl Miner {
+    /// Creates a new miner using the given number of worker threads
+    ///
+    /// At least one thread is used.
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            job: None,
+        }
+    }
+
+    /// Starts searching a nonce for the given block
+    ///
+    /// A running job is cancelled first. The search starts with the nonce of
+    /// the block. As soon as a worker finds a valid nonce, `on_found` is called
+    /// with the mined block. It is not called if the job is cancelled.
+    pub fn start<F>(&mut self, block: Block, on_found: F)
+    where
+        F: FnOnce(Block) + Send + 'static,
+    {
+        self.cancel();
+
+        let job = Job {
+            height: block.header.index,
+            stop: Arc::new(AtomicBool::new(false)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            hashes: Arc::new(AtomicUsize::new(0)),
+            started: Instant::now(),
+        };
+
+        let (sender, receiver) = channel();
+        let range = (u64::MAX - block.header.nonce) / self.threads as u64;
+        for i in 0..self.threads as u64 {
+            let mut block = block.clone();
+            let start = block.header.nonce + i * range;
+            let end = start + range;
+            let stop = Arc::clone(&job.stop);
+            let hashes = Arc::clone(&job.hashes);
+            let sender = sender.clone();
+
+            block.header.nonce = start;
+            thread::spawn(move || {
+                let mut counter = 0;
+                while !stop.load(Ordering::Relaxed) && block.header.nonce < end {
+                    let found = meets_target(&block.hash(), block.header.difficulty);
+
+                    counter += 1;
+                    if counter == HASH_BATCH {
+                        hashes.fetch_add(counter, Ordering::Relaxed);
+                        counter = 0;
+                    }
+
+                    if found {
+                        stop.store(true, Ordering::SeqCst);
+                        // the receiver only goes away after all workers ended
+                        let _ = sender.send(block);
+                        break;
+                    }
+                    block.header.nonce += 1;
+                }
+                hashes.fetch_add(counter, Ordering::Relaxed);
+            });
+        }
+        // only the workers hold a sender, so the coordinator notices when
+        // all of them ended without a result
+        drop(sender);
+
+        let height = job.height;
+        let stop = Arc::clone(&job.stop);
+        let cancelled = Arc::clone(&job.cancelled);
+        let hashes = Arc::clone(&job.hashes);
+        let started = job.started;
+        info!("[MINER] Mining block {} with {} threads", height, self.threads);
+
+        thread::spawn(move || {
+            let result = loop {
+                match receiver.recv_timeout(Duration::from_secs(PROGRESS_INTERVAL)) {
+                    Ok(block)                          => break Some(block),
+                    Err(RecvTimeoutError::Disconnected) => break None,
+                    Err(RecvTimeoutError::Timeout)      => {
+                        let stats = MiningStats {
+                            height,
+                            hashes: hashes.load(Ordering::Relaxed) as u64,
+                            elapsed: started.elapsed(),
+                            running: true,
+                        };
+                        info!("[MINER] Block {}: {} hashes, {:.0} H/s", height, stats.hashes, stats.hash_rate());
+                    }
+                }
+            };
+            stop.store(true, Ordering::SeqCst);
+
+            match result {
+                Some(ref block) if !cancelled.load(Ordering::SeqCst) => {
+                    info!("[MINER] Found nonce {} for block {} after {:?}", block.header.nonce, height, started.elapsed());
+                },
+                _                                                      => {
+                    info!("[MINER] Stopped mining block {}", height);
+                    return;
+                }
+            };
+
+            if let Some(block) = result {
+                on_found(block);
+            }
+        });
+
+        self.job = Some(job);
+    }
+
+    /// Cancels the running job
+    pub fn cancel(&mut self) {
+        if let Some(ref job) = self.job {
+            if !job.stop.load(Ordering::SeqCst) {
+                job.cancelled.store(true, Ordering::SeqCst);
+                job.stop.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Cancels the running job if it mines a block at or below the given
+    /// height
+    ///
+    /// # Return
+    /// - `bool` -> true if a job was cancelled
+    pub fn cancel_height(&mut self, height: u64) -> bool {
+        let running = match self.job {
+            Some(ref job) => job.height <= height && !job.stop.load(Ordering::SeqCst),
+            None          => false,
+        };
+
+        if running {
+            self.cancel();
+        }
+        running
+    }
+
+    /// Number of worker threads per job
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Sets the number of worker threads, at least one thread is used
+    ///
+    /// A running job keeps its threads, the next job uses the new number.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Progress of the current or last job
+    pub fn stats(&self) -> Option<MiningStats> {
+        self.job.as_ref().map(|job| MiningStats {
+            height: job.height,
+            hashes: job.hashes.load(Ordering::Relaxed) as u64,
+            elapsed: job.started.elapsed(),
+            running: !job.stop.load(Ordering::SeqCst),
+        })
+    }
+}
+
+impl Drop for Miner {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_mining() {
+        let mut block = Block::new(0, "0".repeat(64), Vec::new());
+        block.header.difficulty = 8;
+
+        let (sender, receiver) = channel();
+        let mut miner = Miner::new(2);
+        miner.start(block, move |block| sender.send(block).unwrap());
+
+        let block = receiver.recv_timeout(Duration::from_secs(30)).unwrap();
+        assert!(meets_target(&block.hash(), 8));
+        assert!(!miner.stats().unwrap().running);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut block = Block::new(3, "0".repeat(64), Vec::new());
+        block.header.difficulty = 255;
+
+        let (sender, receiver) = channel();
+        let mut miner = Miner::new(2);
+        miner.start(block, move |block| sender.send(block).unwrap());
+        thread::sleep(Duration::from_millis(100));
+
+        assert!(!miner.cancel_height(2));
+        assert!(miner.cancel_height(3));
+        assert!(receiver.recv_timeout(Duration::from_secs(1)).is_err());
+
+        let stats = miner.stats().unwrap();
+        assert_eq!(3, stats.height);
+        assert!(!stats.running);
+        assert!(stats.hashes > 0);
+    }
+}
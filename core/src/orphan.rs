@@ -0,0 +1,220 @@
+//! Holds blocks whose parent is not known yet
+//!
+//! Blocks sent over UDP can arrive in any order. Instead of dropping a
+//! block whose parent is missing, it waits in the pool until the parent is
+//! added to the chain. The pool is bounded and blocks expire after some
+//! time, so it cannot grow without limit.
+use carina_core_protocol::payloads::block::Block;
+use difficulty::meets_target;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Default number of blocks the pool holds
+pub const MAX_ORPHANS: usize = 128;
+/// Default seconds after that a block is removed from the pool
+pub const ORPHAN_EXPIRY: u64 = 600;
+
+/// Block waiting for its parent
+#[derive(Clone, Debug)]
+struct Orphan {
+    /// the block itself
+    block: Block,
+    /// time the block was added
+    received: Instant,
+    /// order in which the blocks were added
+    sequence: u64,
+}
+
+/// Bounded pool of orphan blocks
+#[derive(Debug)]
+pub struct OrphanPool {
+    /// maximum number of blocks
+    capacity: usize,
+    /// time a block stays in the pool
+    expiry: Duration,
+    /// blocks by their hash
+    blocks: HashMap<String, Orphan>,
+    /// hashes of the blocks by the hash of their parent
+    children: HashMap<String, Vec<String>>,
+    /// sequence number of the next block
+    next_sequence: u64,
+}
+
+impl OrphanPool {
+    /// Creates a new pool
+    pub fn new(capacity: usize, expiry: Duration) -> Self {
+        Self {
+            capacity,
+            expiry,
+            blocks: HashMap::new(),
+            children: HashMap::new(),
+            next_sequence: 0,
+        }
+    }
+
+    /// Number of blocks in the pool
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// true if there are no blocks in the pool
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// true if the pool contains the block with the given hash
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    /// Adds a block to the pool
+    ///
+    /// Blocks that are not consistent or do not meet their own difficulty
+    /// are not accepted. If the pool is full, the oldest block is dropped.
+    ///
+    /// # Return
+    /// - `bool` -> true if the block was added
+    pub fn add(&mut self, block: Block) -> bool {
+        self.expire();
+
+        let hash = block.hash();
+        if self.capacity == 0
+            || self.blocks.contains_key(&hash)
+            || !block.is_consistent()
+            || !meets_target(&hash, block.header.difficulty) {
+            return false;
+        }
+
+        if self.blocks.len() >= self.capacity {
+            let oldest = self.blocks
+                .iter()
+                .min_by_key(|(_, orphan)| orphan.sequence)
+                .map(|(hash, _)| hash.clone());
+            if let Some(oldest) = oldest {
+                self.remove(&oldest);
+            }
+        }
+
+        self.children
+            .entry(block.header.prev.clone())
+            .or_insert_with(Vec::new)
+            .push(hash.clone());
+        self.blocks.insert(hash, Orphan { block, received: Instant::now(), sequence: self.next_sequence });
+        self.next_sequence += 1;
+        true
+    }
+
+    /// Removes and returns all blocks whose parent has the given hash
+    pub fn take_children(&mut self, parent: &str) -> Vec<Block> {
+        let hashes = self.children.remove(parent).unwrap_or_default();
+        hashes
+            .into_iter()
+            .filter_map(|hash| self.blocks.remove(&hash))
+            .map(|orphan| orphan.block)
+            .collect()
+    }
+
+    /// Removes all blocks that are in the pool for too long
+    pub fn expire(&mut self) {
+        let expiry = self.expiry;
+        let expired: Vec<String> = self.blocks
+            .iter()
+            .filter(|(_, orphan)| orphan.received.elapsed() >= expiry)
+            .map(|(hash, _)| hash.clone())
+            .collect();
+
+        for hash in expired {
+            debug!("[ORPHAN] Block {} expired", hash);
+            self.remove(&hash);
+        }
+    }
+
+    /// Removes a single block
+    fn remove(&mut self, hash: &str) {
+        let orphan = match self.blocks.remove(hash) {
+            Some(orphan) => orphan,
+            None         => return,
+        };
+
+        let prev = orphan.block.header.prev;
+        let empty = match self.children.get_mut(&prev) {
+            Some(children) => {
+                children.retain(|child| child != hash);
+                children.is_empty()
+            },
+            None           => false,
+        };
+        if empty {
+            self.children.remove(&prev);
+        }
+    }
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        OrphanPool::new(MAX_ORPHANS, Duration::from_secs(ORPHAN_EXPIRY))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mine(index: u64, prev: String) -> Block {
+        let mut block = Block::new(index, prev, Vec::new());
+        block.header.difficulty = 4;
+        while !meets_target(&block.hash(), block.header.difficulty) {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    #[test]
+    fn test_take_children() {
+        let mut pool = OrphanPool::default();
+        let first = mine(5, "a".repeat(64));
+        let second = mine(6, first.hash());
+
+        assert!(pool.add(second.clone()));
+        assert!(pool.add(first.clone()));
+        assert!(!pool.add(first.clone()));
+        assert_eq!(2, pool.len());
+
+        assert_eq!(vec![first.clone()], pool.take_children(&"a".repeat(64)));
+        assert_eq!(vec![second], pool.take_children(&first.hash()));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut pool = OrphanPool::new(2, Duration::from_secs(ORPHAN_EXPIRY));
+        let blocks: Vec<Block> = (0..3).map(|i| mine(i + 1, format!("{}", i).repeat(64))).collect();
+
+        for block in &blocks {
+            assert!(pool.add(block.clone()));
+        }
+
+        assert_eq!(2, pool.len());
+        assert!(!pool.contains(&blocks[0].hash()));
+        assert!(pool.contains(&blocks[2].hash()));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut pool = OrphanPool::new(2, Duration::from_secs(0));
+        let block = mine(1, "a".repeat(64));
+
+        assert!(pool.add(block.clone()));
+        pool.expire();
+        assert!(!pool.contains(&block.hash()));
+    }
+
+    #[test]
+    fn test_inconsistent_block() {
+        let mut pool = OrphanPool::default();
+        let mut block = mine(1, "a".repeat(64));
+        block.header.merkle_root = "b".repeat(64);
+
+        assert!(!pool.add(block));
+    }
+}
@@ -0,0 +1,112 @@
+//! Generates proofs that content is part of a stored block
+use carina_core_protocol::merkle::merkle_proof;
+use carina_core_protocol::payloads::block::{Block, GetProofAckPayload};
+use failure::Error;
+use storage::BlockStorage;
+
+/// Creates a merkle proof that the content with the given unique key is
+/// part of a block of the chain
+///
+/// If the hash of the block containing the content is known, only that
+/// block is read. Otherwise the chain is scanned from the latest block
+/// down to the genesis block.
+///
+/// # Params
+/// - `storage` -> storage containing all blocks
+/// - `unique_key` -> unique key of the content
+/// - `block_hash` -> optional hash of the block containing the content
+///
+/// # Return
+/// - `Result<Option<GetProofAckPayload>, Error>` -> the proof, `None` if no block contains the content
+pub fn get_proof(storage: &BlockStorage, unique_key: &str, block_hash: Option<&str>) -> Result<Option<GetProofAckPayload>, Error> {
+    if let Some(block_hash) = block_hash {
+        return match storage.get_by_hash(block_hash)? {
+            Some(bytes) => proof_in_block(Block::from_bytes(&bytes)?, unique_key),
+            None        => Ok(None),
+        };
+    }
+
+    let tip = match storage.tip() {
+        Some((height, _)) => height,
+        None              => return Ok(None),
+    };
+
+    for height in (0..tip + 1).rev() {
+        let block = match storage.get_by_height(height)? {
+            Some(bytes) => Block::from_bytes(&bytes)?,
+            None        => return Err(format_err!("Block {} is missing", height)),
+        };
+
+        if let Some(proof) = proof_in_block(block, unique_key)? {
+            return Ok(Some(proof));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Creates the proof for the content with the given unique key, `None` if the block doesn´t contain it
+fn proof_in_block(block: Block, unique_key: &str) -> Result<Option<GetProofAckPayload>, Error> {
+    let position = match block.entries.binary_search_by(|entry| entry.unique_key.as_str().cmp(unique_key)) {
+        Ok(position) => position,
+        Err(_)       => return Ok(None),
+    };
+
+    match merkle_proof(&block.entries, position) {
+        Some(proof) => Ok(Some(GetProofAckPayload {
+            found: true,
+            entry: block.entries[position].clone(),
+            header: block.header,
+            proof,
+        })),
+        None        => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carina_core_protocol::Payload;
+    use carina_core_protocol::payloads::block::NewBlockContentPayload;
+    use carina_core_protocol::verify_proof;
+    use storage::MemoryStorage;
+
+    #[test]
+    fn test_get_proof() {
+        let mut storage = MemoryStorage::new();
+        let mut prev = "0".repeat(64);
+        let mut hashes = Vec::new();
+
+        for i in 0..3 {
+            let entries = (0..5)
+                .map(|j| NewBlockContentPayload {
+                    unique_key: format!("key_{}_{}", i, j),
+                    content: format!("content {} {}", i, j),
+                })
+                .collect();
+            let block = Block::new(i, prev.clone(), entries);
+            prev = block.hash();
+            storage.append(&prev, &block.to_bytes()).unwrap();
+            hashes.push(prev.clone());
+        }
+
+        let proof = get_proof(&storage, "key_1_3", Some(&hashes[1])).unwrap().unwrap();
+        assert!(proof.found);
+        assert_eq!(1, proof.header.index);
+        assert_eq!(String::from("content 1 3"), proof.entry.content);
+        assert!(verify_proof(&proof.header, &proof.entry, &proof.proof));
+
+        assert_eq!(None, get_proof(&storage, "unknown", Some(&hashes[1])).unwrap());
+        assert_eq!(None, get_proof(&storage, "key_1_3", Some(&hashes[2])).unwrap());
+        assert_eq!(None, get_proof(&storage, "key_1_3", Some(&"0".repeat(64))).unwrap());
+
+        // without the block hash the chain is scanned
+        let proof = get_proof(&storage, "key_0_2", None).unwrap().unwrap();
+        assert_eq!(hashes[0], proof.header.hash());
+        assert_eq!(String::from("content 0 2"), proof.entry.content);
+        assert!(verify_proof(&proof.header, &proof.entry, &proof.proof));
+
+        assert_eq!(None, get_proof(&storage, "unknown", None).unwrap());
+        assert_eq!(None, get_proof(&MemoryStorage::new(), "key_0_2", None).unwrap());
+    }
+}
@@ -0,0 +1,379 @@
+//! Validation rounds for mined blocks
+//!
+//! After a block was mined, every peer sends a signed vote about the block.
+//! A round ends as soon as a quorum of peers accepted the block, as soon as
+//! the quorum can no longer be reached or when it times out. The votes of a
+//! finalized round are stored next to the block as finality certificate.
+//!
+//! With only a few voters a single peer could finalize its own blocks, so
+//! no round is started if fewer voters than configured are known.
+use carina_core_protocol::payloads::block::{BlockVotePayload, FinalityCertificate};
+use carina_core_protocol::Payload;
+use failure::Error;
+use sodiumoxide::crypto::sign;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use storage::BlockStorage;
+
+/// Number of voters that must be known, if nothing else is configured
+pub const DEFAULT_MIN_VOTERS: usize = 3;
+
+/// Share of all voters that must accept a block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quorum {
+    /// numerator of the share
+    pub numerator: u64,
+    /// denominator of the share
+    pub denominator: u64,
+}
+
+impl Quorum {
+    /// Creates a new quorum, the share must be greater than 0 and at most 1
+    pub fn new(numerator: u64, denominator: u64) -> Result<Self, Error> {
+        if numerator == 0 || denominator == 0 || numerator > denominator {
+            return Err(format_err!("Invalid quorum {}/{}", numerator, denominator));
+        }
+
+        Ok(Self { numerator, denominator })
+    }
+
+    /// Parses a quorum in the form `2/3`
+    pub fn from_str(value: &str) -> Result<Self, Error> {
+        let parts: Vec<&str> = value.split('/').map(|part| part.trim()).collect();
+        if parts.len() != 2 {
+            return Err(format_err!("Invalid quorum {}", value));
+        }
+
+        Quorum::new(parts[0].parse()?, parts[1].parse()?)
+    }
+
+    /// Number of votes needed with the given number of voters
+    pub fn required(&self, voters: usize) -> usize {
+        let voters = voters as u64;
+        let required = (voters * self.numerator + self.denominator - 1) / self.denominator;
+        required.max(1) as usize
+    }
+}
+
+impl Default for Quorum {
+    fn default() -> Self {
+        Self {
+            numerator: 2,
+            denominator: 3,
+        }
+    }
+}
+
+/// Result of a finished round
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoundResult {
+    /// A quorum accepted the block, the certificate was stored
+    Finalized(FinalityCertificate),
+    /// So many peers rejected the block that the quorum can not be reached
+    Rejected(FinalityCertificate),
+    /// Not enough votes arrived in time
+    TimedOut(FinalityCertificate),
+}
+
+/// Votes collected for a single block
+#[derive(Debug)]
+struct Round {
+    /// number of peers that can vote, including this peer
+    voters: usize,
+    /// signing keys of the peers that already voted
+    signers: Vec<String>,
+    /// time the round was started
+    started: Instant,
+    /// votes collected so far
+    certificate: FinalityCertificate,
+}
+
+/// Manages all running validation rounds
+pub struct VoteRounds {
+    /// storage the certificates are written to
+    storage: Arc<Mutex<BlockStorage>>,
+    /// share of voters needed
+    quorum: Quorum,
+    /// time after that a round is given up
+    timeout: Duration,
+    /// number of voters needed to start a round
+    min_voters: usize,
+    /// running rounds by block hash
+    rounds: HashMap<String, Round>,
+}
+
+impl VoteRounds {
+    /// Creates a new instance
+    ///
+    /// # Params
+    /// - `storage` -> storage the certificates are written to
+    /// - `quorum` -> share of voters needed
+    /// - `timeout` -> time after that a round is given up
+    /// - `min_voters` -> number of voters needed to start a round, including this peer
+    pub fn new(storage: Arc<Mutex<BlockStorage>>, quorum: Quorum, timeout: Duration, min_voters: usize) -> Self {
+        Self {
+            storage,
+            quorum,
+            timeout,
+            min_voters,
+            rounds: HashMap::new(),
+        }
+    }
+
+    /// Time after that a round is given up
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Replaces the quorum, the timeout and the minimum number of voters
+    ///
+    /// Used for all votes from now on, including running rounds.
+    pub fn set_quorum(&mut self, quorum: Quorum, timeout: Duration, min_voters: usize) {
+        self.quorum = quorum;
+        self.timeout = timeout;
+        self.min_voters = min_voters;
+    }
+
+    /// Starts a new round with the vote of this peer
+    ///
+    /// Returns an error if fewer voters than the minimum are known, the block
+    /// can´t become final then.
+    ///
+    /// # Params
+    /// - `source` -> address of this peer
+    /// - `sign_key` -> signing key of this peer
+    /// - `vote` -> vote of this peer
+    /// - `voters` -> number of peers that can vote, including this peer
+    ///
+    /// # Return
+    /// - `Result<Option<RoundResult>, Error>` -> result if the vote already finished the round
+    pub fn start(&mut self, source: &str, sign_key: &sign::PublicKey, vote: BlockVotePayload, voters: usize) -> Result<Option<RoundResult>, Error> {
+        if voters < self.min_voters {
+            return Err(format_err!(
+                "Only {} of at least {} voters are known, finality is disabled",
+                voters,
+                self.min_voters
+            ));
+        }
+
+        let certificate = FinalityCertificate {
+            index: vote.index,
+            hash: vote.hash.clone(),
+            votes: Vec::new(),
+        };
+
+        self.rounds.insert(vote.hash.clone(), Round {
+            voters: voters.max(1),
+            signers: Vec::new(),
+            started: Instant::now(),
+            certificate,
+        });
+        self.add_vote(source, Some(sign_key), vote)
+    }
+
+    /// Adds the vote of the given peer
+    ///
+    /// Returns an error if there is no round for the block, the signing key
+    /// of the peer is unknown or the vote is not signed with it. A second
+    /// vote with the same key is ignored.
+    ///
+    /// # Params
+    /// - `source` -> address of the voting peer
+    /// - `sign_key` -> known signing key of the voting peer, `None` if the peer has none
+    /// - `vote` -> vote of the peer
+    ///
+    /// # Return
+    /// - `Result<Option<RoundResult>, Error>` -> result if the vote finished the round
+    pub fn add_vote(&mut self, source: &str, sign_key: Option<&sign::PublicKey>, vote: BlockVotePayload) -> Result<Option<RoundResult>, Error> {
+        match sign_key {
+            Some(sign_key) if vote.is_signed_by(sign_key) => (),
+            Some(_)                                       => return Err(format_err!("Vote from {} is not signed with its key", source)),
+            None                                          => return Err(format_err!("Signing key of {} is unknown", source)),
+        };
+
+        let (accepted, rejected, required, voters) = {
+            let round = match self.rounds.get_mut(&vote.hash) {
+                Some(round) => round,
+                None        => return Err(format_err!("No validation round for block {}", vote.hash)),
+            };
+
+            if vote.index != round.certificate.index {
+                return Err(format_err!("Vote from {} has the wrong index {}", source, vote.index));
+            }
+            if round.signers.contains(&vote.public_key) {
+                debug!("[QUORUM] Ignoring second vote from {}", source);
+                return Ok(None);
+            }
+
+            round.signers.push(vote.public_key.clone());
+            round.certificate.votes.push(vote.clone());
+
+            let accepted = round.certificate.votes.iter().filter(|vote| vote.accepted).count();
+            let rejected = round.certificate.votes.len() - accepted;
+            (accepted, rejected, self.quorum.required(round.voters), round.voters)
+        };
+
+        if accepted >= required {
+            let certificate = self.finish(&vote.hash);
+            match self.storage.lock() {
+                Ok(mut storage) => storage.put_certificate(&certificate.hash, &certificate.clone().to_bytes())?,
+                Err(e)          => return Err(format_err!("Error locking storage. {}", e)),
+            };
+
+            info!("[QUORUM] Block {} finalized with {} of {} votes", certificate.hash, accepted, voters);
+            Ok(Some(RoundResult::Finalized(certificate)))
+        } else if rejected > voters - required {
+            let certificate = self.finish(&vote.hash);
+            info!("[QUORUM] Block {} rejected by {} of {} peers", certificate.hash, rejected, voters);
+            Ok(Some(RoundResult::Rejected(certificate)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Ends all rounds that took longer than the timeout
+    pub fn expire(&mut self) -> Vec<RoundResult> {
+        let timeout = self.timeout;
+        let expired: Vec<String> = self.rounds
+            .iter()
+            .filter(|(_, round)| round.started.elapsed() >= timeout)
+            .map(|(hash, _)| hash.clone())
+            .collect();
+
+        expired
+            .into_iter()
+            .map(|hash| {
+                let certificate = self.finish(&hash);
+                info!("[QUORUM] Round for block {} timed out with {} votes", hash, certificate.votes.len());
+                RoundResult::TimedOut(certificate)
+            })
+            .collect()
+    }
+
+    /// Removes the round and returns its votes
+    fn finish(&mut self, hash: &str) -> FinalityCertificate {
+        match self.rounds.remove(hash) {
+            Some(round) => round.certificate,
+            None        => FinalityCertificate::new(),
+        }
+    }
+}
+
+impl Debug for VoteRounds {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "VoteRounds: {{ quorum: {:?}, timeout: {:?}, rounds: {:?} }}", self.quorum, self.timeout, self.rounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carina_core_protocol::Nacl;
+    use sodiumoxide::crypto::box_;
+    use std::thread;
+    use storage::MemoryStorage;
+
+    fn nacl() -> Nacl {
+        Nacl::new(box_::gen_keypair().1)
+    }
+
+    fn vote(nacl: &Nacl, hash: &str, accepted: bool) -> BlockVotePayload {
+        BlockVotePayload::signed(nacl, 1, hash.to_string(), accepted, String::new())
+    }
+
+    fn rounds(timeout: Duration) -> (Arc<Mutex<BlockStorage>>, VoteRounds) {
+        let storage: Arc<Mutex<BlockStorage>> = Arc::new(Mutex::new(MemoryStorage::new()));
+        let rounds = VoteRounds::new(Arc::clone(&storage), Quorum::default(), timeout, DEFAULT_MIN_VOTERS);
+        (storage, rounds)
+    }
+
+    #[test]
+    fn test_quorum() {
+        assert_eq!(Quorum::new(2, 3).unwrap(), Quorum::from_str("2/3").unwrap());
+        assert!(Quorum::from_str("4/3").is_err());
+        assert!(Quorum::from_str("0/3").is_err());
+        assert!(Quorum::from_str("2").is_err());
+
+        let quorum = Quorum::default();
+        assert_eq!(1, quorum.required(1));
+        assert_eq!(2, quorum.required(3));
+        assert_eq!(3, quorum.required(4));
+        assert_eq!(7, quorum.required(10));
+    }
+
+    #[test]
+    fn test_finalized() {
+        let hash = "a".repeat(64);
+        let (storage, mut rounds) = rounds(Duration::from_secs(60));
+        let peers: Vec<Nacl> = (0..5).map(|_| nacl()).collect();
+        let keys: Vec<sign::PublicKey> = peers.iter().map(|peer| peer.sign_public_key()).collect();
+
+        assert_eq!(None, rounds.start("own", &keys[0], vote(&peers[0], &hash, true), 4).unwrap());
+        assert_eq!(None, rounds.add_vote("peer_1", Some(&keys[1]), vote(&peers[1], &hash, false)).unwrap());
+        assert_eq!(None, rounds.add_vote("own", Some(&keys[0]), vote(&peers[0], &hash, true)).unwrap());
+        assert!(rounds.add_vote("peer_2", Some(&keys[2]), vote(&peers[2], &"b".repeat(64), true)).is_err());
+
+        let mut forged = vote(&peers[2], &hash, true);
+        forged.index = 2;
+        assert!(rounds.add_vote("peer_2", Some(&keys[2]), forged).is_err());
+
+        // throwaway keys and peers without a known key don´t count
+        assert!(rounds.add_vote("peer_2", Some(&keys[2]), vote(&nacl(), &hash, true)).is_err());
+        assert!(rounds.add_vote("peer_2", None, vote(&peers[2], &hash, true)).is_err());
+
+        assert_eq!(None, rounds.add_vote("peer_3", Some(&keys[3]), vote(&peers[3], &hash, true)).unwrap());
+        match rounds.add_vote("peer_4", Some(&keys[4]), vote(&peers[4], &hash, true)).unwrap() {
+            Some(RoundResult::Finalized(certificate)) => {
+                assert_eq!(4, certificate.votes.len());
+                assert_eq!(3, certificate.accepted(&keys));
+
+                let stored = storage.lock().unwrap().get_certificate(&hash).unwrap().unwrap();
+                assert_eq!(certificate, FinalityCertificate::from_bytes(&stored).unwrap());
+            },
+            result => panic!("Unexpected result {:?}", result),
+        };
+    }
+
+    #[test]
+    fn test_rejected_and_timeout() {
+        let hash = "a".repeat(64);
+        let (_, mut rounds) = rounds(Duration::from_millis(10));
+        let (own, peer_1, peer_2) = (nacl(), nacl(), nacl());
+
+        rounds.start("own", &own.sign_public_key(), vote(&own, &hash, true), 3).unwrap();
+        assert_eq!(None, rounds.add_vote("peer_1", Some(&peer_1.sign_public_key()), vote(&peer_1, &hash, false)).unwrap());
+        match rounds.add_vote("peer_2", Some(&peer_2.sign_public_key()), vote(&peer_2, &hash, false)).unwrap() {
+            Some(RoundResult::Rejected(certificate)) => assert_eq!(3, certificate.votes.len()),
+            result                                   => panic!("Unexpected result {:?}", result),
+        };
+
+        rounds.start("own", &own.sign_public_key(), vote(&own, &hash, true), 3).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        match rounds.expire().pop() {
+            Some(RoundResult::TimedOut(certificate)) => assert_eq!(1, certificate.votes.len()),
+            result                                   => panic!("Unexpected result {:?}", result),
+        };
+        assert!(rounds.expire().is_empty());
+    }
+
+    #[test]
+    fn test_min_voters() {
+        let hash = "a".repeat(64);
+        let (_, mut rounds) = rounds(Duration::from_secs(60));
+        let own = nacl();
+
+        // alone the own vote would finalize the block
+        assert!(rounds.start("own", &own.sign_public_key(), vote(&own, &hash, true), 1).is_err());
+        assert!(rounds.start("own", &own.sign_public_key(), vote(&own, &hash, true), DEFAULT_MIN_VOTERS - 1).is_err());
+        assert!(rounds.add_vote("own", Some(&own.sign_public_key()), vote(&own, &hash, true)).is_err());
+
+        rounds.set_quorum(Quorum::default(), Duration::from_secs(60), 1);
+        match rounds.start("own", &own.sign_public_key(), vote(&own, &hash, true), 1).unwrap() {
+            Some(RoundResult::Finalized(certificate)) => assert_eq!(1, certificate.votes.len()),
+            result                                    => panic!("Unexpected result {:?}", result),
+        };
+    }
+}
@@ -0,0 +1,312 @@
+//! Reloads the configuration of a running node
+//!
+//! The config file and the peer file are watched for changes. A changed
+//! configuration is parsed and validated like at startup. Settings that can
+//! be changed while running are applied, after that the handlers of
+//! `Events::Reload` are notified with the new configuration. Settings like
+//! the `uri` or the `secret_key` need a restart, if one of them changed the
+//! whole configuration is rejected.
+use carina_config::CarinaConfig;
+use carina_core_protocol::payloads::EmptyPayload;
+use carina_core_protocol::{Events, Payload};
+use config::{Config, Peer};
+use failure::Error;
+use log;
+use std::collections::HashMap;
+use std::fs::{metadata, File};
+use std::io::Read;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+/// Seconds between two checks for changed files
+const RELOAD_CHECK: u64 = 2;
+
+/// Applies a reloaded configuration to the running one
+///
+/// Nothing is applied if a setting changed that needs a restart.
+///
+/// # Return
+/// - `Result<Vec<&'static str>, Error>` -> names of the changed settings
+pub fn apply_config(current: &mut Config, new: Config) -> Result<Vec<&'static str>, Error> {
+    let mut restart = Vec::new();
+    if current.uri != new.uri {
+        restart.push("uri");
+    }
+    if current.nacl.public_key() != new.nacl.public_key() {
+        restart.push("secret_key");
+    }
+    let client_key = |config: &Config| config.client_nacl.as_ref().map(|nacl| nacl.public_key());
+    if client_key(current) != client_key(&new) {
+        restart.push("client_secret_key");
+    }
+    if current.socket != new.socket {
+        restart.push("socket");
+    }
+    if current.storage != new.storage {
+        restart.push("storage");
+    }
+    if current.storage_backend != new.storage_backend {
+        restart.push("storage_backend");
+    }
+    if current.nonce_file != new.nonce_file {
+        restart.push("nonce_file");
+    }
+    if current.http_api != new.http_api {
+        restart.push("http_api");
+    }
+    if current.metrics_address != new.metrics_address {
+        restart.push("metrics");
+    }
+    // all peers must agree on the difficulty
+    if current.retarget != new.retarget {
+        restart.push("retarget_interval/target_block_time");
+    }
+    if current.max_time_drift != new.max_time_drift {
+        restart.push("max_time_drift");
+    }
+    if !restart.is_empty() {
+        return Err(format_err!("{} can´t be changed while the node is running, restart it to apply the configuration", restart.join(", ")));
+    }
+
+    let mut changed = Vec::new();
+    if current.log_level != new.log_level {
+        log::set_max_level(new.log_level.unwrap_or(current.default_log_level));
+        current.log_level = new.log_level;
+        changed.push("log_level");
+    }
+    if current.miner_threads != new.miner_threads {
+        current.miner_threads = new.miner_threads;
+        changed.push("miner_threads");
+    }
+    if current.quorum != new.quorum {
+        current.quorum = new.quorum;
+        changed.push("quorum");
+    }
+    if current.quorum_timeout != new.quorum_timeout {
+        current.quorum_timeout = new.quorum_timeout;
+        changed.push("quorum_timeout");
+    }
+    if current.min_voters != new.min_voters {
+        current.min_voters = new.min_voters;
+        changed.push("min_voters");
+    }
+    if current.trust_policy != new.trust_policy {
+        current.trust_policy = new.trust_policy;
+        changed.push("trust_policy");
+    }
+    if current.known_peers != new.known_peers {
+        current.known_peers = new.known_peers.clone();
+        changed.push("known_peers");
+    }
+    if current.clients != new.clients {
+        current.clients = new.clients.clone();
+        changed.push("clients");
+    }
+    if current.rekey_interval != new.rekey_interval {
+        current.rekey_interval = new.rekey_interval;
+        changed.push("rekey_interval");
+    }
+    if apply_peers(current, &new) {
+        changed.push("peers");
+    }
+    current.peer_path = new.peer_path;
+
+    Ok(changed)
+}
+
+/// Replaces the peers from the peer file, learned peers are kept
+///
+/// # Return
+/// - `bool` -> true if a peer was added, removed or changed
+fn apply_peers(current: &mut Config, new: &Config) -> bool {
+    let configured = |config: &Config| -> HashMap<String, Peer> {
+        config.peers
+            .iter()
+            .filter(|(address, _)| !config.learned_peers.contains(*address))
+            .map(|(address, peer)| (address.clone(), peer.clone()))
+            .collect()
+    };
+    let old = configured(current);
+    let new = configured(new);
+    if old == new {
+        return false;
+    }
+
+    for (address, peer) in &old {
+        if new.get(address) != Some(peer) {
+            current.forget(peer);
+            current.peers.remove(address);
+        }
+    }
+    for (address, peer) in new {
+        if let Some(learned) = current.peers.get(&address) {
+            if learned.public_key != peer.public_key {
+                current.forget(learned);
+            }
+        }
+        current.learned_peers.remove(&address);
+        current.peers.insert(address, peer);
+    }
+    true
+}
+
+/// Reads the config file and applies it
+fn reload(carina_config: &Arc<Mutex<CarinaConfig>>, socket: &UdpSocket, path: &str) -> Result<(), Error> {
+    let mut content = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+    let new = Config::from_str(&content)?;
+
+    let (mut config, listeners) = {
+        let mut carina_config = match carina_config.lock() {
+            Ok(val) => val,
+            Err(e)  => return Err(format_err!("Error locking carina_config. {}", e)),
+        };
+
+        let changed = apply_config(&mut carina_config.config, new)?;
+        if changed.is_empty() {
+            return Ok(());
+        }
+        info!("[THREAD_RELOAD] Applied new configuration. Changed: {}", changed.join(", "));
+
+        let listeners = carina_config.events.get(&Events::Reload).cloned().unwrap_or_default();
+        (carina_config.config.clone(), listeners)
+    };
+
+    // the handlers run without holding the lock, same as for received events
+    for listener in listeners {
+        match listener.lock() {
+            Ok(mut listener) => {
+                if let Err(e) = listener.execute(socket.try_clone()?, String::new(), &mut config, &EmptyPayload::new().to_bytes()) {
+                    error!("[THREAD_RELOAD] Error notifying listener. {}", e);
+                }
+            },
+            Err(e)           => error!("[THREAD_RELOAD] Error locking listener. {}", e),
+        };
+    }
+    Ok(())
+}
+
+/// Last modification time of the given file
+fn modified(path: &str) -> Option<SystemTime> {
+    metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Starts a thread that reloads the configuration as soon as the config
+/// file at `path` or the peer file changes
+pub fn watch_config(carina_config: Arc<Mutex<CarinaConfig>>, socket: UdpSocket, path: String) -> JoinHandle<()> {
+    debug!("[THREAD_RELOAD] Watching {}", path);
+    thread::spawn(move || {
+        let mut last = None;
+        loop {
+            let peer_path = match carina_config.lock() {
+                Ok(val) => val.config.peer_path.clone(),
+                Err(e)  => {
+                    error!("[THREAD_RELOAD] Error locking carina_config. {}", e);
+                    return;
+                }
+            };
+
+            let current = (modified(&path), modified(&peer_path));
+            if last.is_some() && last != Some(current) {
+                match reload(&carina_config, &socket, &path) {
+                    Ok(_)  => (),
+                    Err(e) => error!("[THREAD_RELOAD] Rejected new configuration. {}", e),
+                };
+            }
+            last = Some(current);
+
+            thread::sleep(Duration::from_secs(RELOAD_CHECK));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::LevelFilter;
+    use quorum::Quorum;
+    use sodiumoxide::crypto::box_;
+
+    fn peer(address: &str) -> Peer {
+        Peer {
+            address: address.to_string(),
+            public_key: box_::gen_keypair().0,
+            sign_key: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_live_settings() {
+        let mut current = Config::default();
+        let mut new = current.clone();
+        new.quorum = Quorum::new(3, 4).unwrap();
+        new.miner_threads = 4;
+
+        assert_eq!(vec!["miner_threads", "quorum"], apply_config(&mut current, new.clone()).unwrap());
+        assert_eq!(new.quorum, current.quorum);
+        assert_eq!(4, current.miner_threads);
+        assert!(apply_config(&mut current, new).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reject_restart_settings() {
+        let mut current = Config::default();
+        let mut new = current.clone();
+        new.uri = "0.0.0.0:45010".to_string();
+        new.miner_threads = 4;
+
+        assert!(apply_config(&mut current, new.clone()).is_err());
+        assert_eq!(1, current.miner_threads);
+
+        new.uri = current.uri.clone();
+        new.client_nacl = Some(Default::default());
+        assert!(apply_config(&mut current, new.clone()).is_err());
+
+        new.client_nacl = None;
+        new.nacl = Default::default();
+        assert!(apply_config(&mut current, new).is_err());
+    }
+
+    #[test]
+    fn test_reset_log_level() {
+        let mut current = Config::default();
+        current.default_log_level = LevelFilter::Info;
+        let mut new = current.clone();
+
+        new.log_level = Some(LevelFilter::Warn);
+        assert_eq!(vec!["log_level"], apply_config(&mut current, new.clone()).unwrap());
+        assert_eq!(LevelFilter::Warn, log::max_level());
+
+        new.log_level = None;
+        assert_eq!(vec!["log_level"], apply_config(&mut current, new).unwrap());
+        assert_eq!(LevelFilter::Info, log::max_level());
+    }
+
+    #[test]
+    fn test_apply_peers() {
+        let mut current = Config::default();
+        let kept = peer("127.0.0.1:45002");
+        let removed = peer("127.0.0.1:45003");
+        let learned = peer("127.0.0.1:45010");
+        current.peers.insert(kept.address.clone(), kept.clone());
+        current.peers.insert(removed.address.clone(), removed.clone());
+        current.peers.insert(learned.address.clone(), learned.clone());
+        current.learned_peers.insert(learned.address.clone());
+
+        let mut new = current.clone();
+        new.peers.remove(&removed.address);
+        new.peers.remove(&learned.address);
+        new.learned_peers.clear();
+        let added = peer("127.0.0.1:45004");
+        new.peers.insert(added.address.clone(), added.clone());
+
+        assert_eq!(vec!["peers"], apply_config(&mut current, new).unwrap());
+        assert_eq!(Some(&kept), current.peers.get(&kept.address));
+        assert_eq!(Some(&added), current.peers.get(&added.address));
+        assert_eq!(Some(&learned), current.peers.get(&learned.address));
+        assert!(!current.peers.contains_key(&removed.address));
+    }
+}
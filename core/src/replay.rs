@@ -0,0 +1,177 @@
+//! Rejects messages that were already received
+use carina_core_protocol::{Replay, ReplayWindow};
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, PublicKey};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Number of windows that are kept for senders that are neither peers nor
+/// clients of the configuration
+pub const MAX_UNKNOWN_WINDOWS: usize = 1024;
+
+/// Keeps a `ReplayWindow` for every peer
+///
+/// The windows are kept by the public key of the peer, so a peer that
+/// changes its address keeps its window and another node can´t move it.
+///
+/// The windows of configured or learned peers and configured clients are
+/// kept until the peer is removed. Everybody can introduce themselves with
+/// a new key, so for all other senders only the `MAX_UNKNOWN_WINDOWS` most
+/// recently used windows are kept.
+///
+/// Clones share their state, so the number of rejected messages can be
+/// read from every clone of the config.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayProtection {
+    /// windows of all senders
+    windows: Arc<Mutex<Windows>>,
+    /// number of rejected messages
+    rejected: Arc<AtomicUsize>,
+}
+
+/// Windows by the public key of their sender
+#[derive(Debug, Default)]
+struct Windows {
+    /// windows of peers and clients of the configuration
+    known: HashMap<[u8; 32], ReplayWindow>,
+    /// windows of all other senders and the time they were last used
+    unknown: HashMap<[u8; 32], (ReplayWindow, u64)>,
+    /// incremented for every use of a window
+    clock: u64,
+}
+
+impl Windows {
+    /// Gets the window of the given sender, creates it if necessary
+    fn get(&mut self, peer: [u8; 32], known: bool) -> &mut ReplayWindow {
+        self.clock += 1;
+
+        if known || self.known.contains_key(&peer) {
+            if let Some((window, _)) = self.unknown.remove(&peer) {
+                self.known.insert(peer, window);
+            }
+            return self.known.entry(peer).or_insert_with(ReplayWindow::new);
+        }
+
+        if !self.unknown.contains_key(&peer) && self.unknown.len() >= MAX_UNKNOWN_WINDOWS {
+            self.drop_least_recently_used();
+        }
+        let clock = self.clock;
+        let entry = self.unknown.entry(peer).or_insert_with(|| (ReplayWindow::new(), clock));
+        entry.1 = clock;
+        &mut entry.0
+    }
+
+    /// Moves the window of a removed peer to the windows of unknown senders
+    fn unpin(&mut self, peer: [u8; 32]) {
+        if let Some(window) = self.known.remove(&peer) {
+            if self.unknown.len() >= MAX_UNKNOWN_WINDOWS {
+                self.drop_least_recently_used();
+            }
+            self.unknown.insert(peer, (window, self.clock));
+        }
+    }
+
+    /// Drops the window of the unknown sender that was not used for the longest time
+    fn drop_least_recently_used(&mut self) {
+        let oldest = self.unknown
+            .iter()
+            .min_by_key(|(_, (_, used))| *used)
+            .map(|(peer, _)| *peer);
+
+        if let Some(peer) = oldest {
+            self.unknown.remove(&peer);
+        }
+    }
+}
+
+impl ReplayProtection {
+    /// Creates a new instance without any known nonces
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks the nonce of a decrypted message from the given peer
+    ///
+    /// # Params
+    /// - `peer` -> public key the message was sealed with
+    /// - `nonce` -> nonce of the message
+    /// - `proven` -> true if the message was opened with a session key
+    /// - `known` -> true if the sender is a peer or client of the configuration
+    pub fn check(&self, peer: &PublicKey, nonce: &Nonce, proven: bool, known: bool) -> Result<(), Replay> {
+        let result = match self.windows.lock() {
+            Ok(mut windows) => windows.get(peer.0, known).check(nonce, proven),
+            // without the windows it´s not possible to tell, better drop it
+            Err(_)          => Err(Replay::Duplicate),
+        };
+
+        if result.is_err() {
+            self.rejected.fetch_add(1, Ordering::SeqCst);
+        }
+        result
+    }
+
+    /// Keeps the window of a removed peer only as long as the windows of
+    /// unknown senders
+    pub fn unpin(&self, peer: &PublicKey) {
+        if let Ok(mut windows) = self.windows.lock() {
+            windows.unpin(peer.0);
+        }
+    }
+
+    /// Number of messages that were rejected
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sodiumoxide::crypto::box_;
+
+    #[test]
+    fn test_rejected_per_peer() {
+        let replay = ReplayProtection::new();
+        let nonce = Nonce([7; 24]);
+        let (peer, _) = box_::gen_keypair();
+        let (other, _) = box_::gen_keypair();
+
+        assert_eq!(Ok(()), replay.check(&peer, &nonce, false, true));
+        assert_eq!(Ok(()), replay.check(&other, &nonce, false, false));
+        assert_eq!(Err(Replay::Duplicate), replay.clone().check(&peer, &nonce, true, true));
+        assert_eq!(1, replay.rejected());
+    }
+
+    #[test]
+    fn test_unknown_windows_are_limited() {
+        let replay = ReplayProtection::new();
+        let nonce = Nonce([7; 24]);
+        let (peer, _) = box_::gen_keypair();
+        let (first, _) = box_::gen_keypair();
+
+        assert_eq!(Ok(()), replay.check(&peer, &nonce, false, true));
+        assert_eq!(Ok(()), replay.check(&first, &nonce, false, false));
+        for _ in 0..MAX_UNKNOWN_WINDOWS {
+            let (unknown, _) = box_::gen_keypair();
+            assert_eq!(Ok(()), replay.check(&unknown, &nonce, false, false));
+        }
+
+        {
+            let windows = replay.windows.lock().unwrap();
+            assert_eq!(1, windows.known.len());
+            assert_eq!(MAX_UNKNOWN_WINDOWS, windows.unknown.len());
+            assert!(!windows.unknown.contains_key(&first.0));
+        }
+
+        // the window of a configured peer is never dropped
+        assert_eq!(Err(Replay::Duplicate), replay.check(&peer, &nonce, false, false));
+
+        // a removed peer is dropped like every unknown sender
+        replay.unpin(&peer);
+        for _ in 0..MAX_UNKNOWN_WINDOWS {
+            let (unknown, _) = box_::gen_keypair();
+            assert_eq!(Ok(()), replay.check(&unknown, &nonce, false, false));
+        }
+        assert_eq!(Ok(()), replay.check(&peer, &nonce, false, false));
+    }
+}
@@ -0,0 +1,489 @@
+//! Forward secret sessions with the peers
+//!
+//! Messages are sealed with the long-term keys from the peers file until a
+//! session is established. A session key is derived from two ephemeral
+//! keys, that are exchanged with `Handshake` and `HandshakeAck`. Both events
+//! are sealed, so the ephemeral keys are authenticated by the long-term keys.
+//! After the handshake the ephemeral secret keys are dropped, so a leaked
+//! long-term key does not reveal recorded messages.
+//!
+//! ```
+//! // Initiator                                  Responder
+//! //     |  Handshake(id, ephemeral key)            |
+//! //     |----------------------------------------->| next session
+//! //     |  HandshakeAck(id, ephemeral key)         |
+//! //     |<-----------------------------------------|
+//! //     | current session                          |
+//! //     |  first message sealed with the session   |
+//! //     |----------------------------------------->| next becomes current
+//! ```
+//!
+//! The responder keeps sealing with its old key until the first message
+//! sealed with the new session arrives, so nothing is sealed with a key the
+//! initiator doesn´t know yet.
+//!
+//! If both peers start a handshake at the same time, only the handshake of
+//! the peer with the lower public key is used. The other peer drops its
+//! own handshake and answers.
+use carina_config::CarinaConfig;
+use carina_core_protocol::payloads::HandshakePayload;
+use carina_core_protocol::{Events, MessageBuilder, Payload};
+use config::{Config, Peer};
+use failure::Error;
+use protocol_builder_parser::Parser;
+use sodiumoxide::crypto::box_;
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{PrecomputedKey, SecretKey};
+use sodiumoxide::randombytes::randombytes;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Seconds until an unanswered handshake is started again
+pub const HANDSHAKE_TIMEOUT: u64 = 10;
+/// Seconds the previous session key is still accepted after a rekey
+///
+/// Messages that were sealed before the rekey may still be on their way.
+pub const SESSION_GRACE: u64 = 60;
+/// Seconds between two checks for peers that need a new session
+const REKEY_CHECK: u64 = 5;
+
+/// Key derived by a handshake
+struct Session {
+    /// id chosen by the initiator
+    id: u64,
+    /// key shared between the two ephemeral keys
+    key: PrecomputedKey,
+    /// time the session was established
+    established: Instant,
+}
+
+/// Handshake that was sent but not answered yet
+struct Pending {
+    /// id of the handshake
+    id: u64,
+    /// ephemeral secret key, dropped as soon as the answer arrives
+    secret_key: SecretKey,
+    /// time the handshake was sent
+    started: Instant,
+}
+
+/// All sessions with a single peer
+#[derive(Default)]
+struct PeerSessions {
+    /// session used for sealing
+    current: Option<Session>,
+    /// session accepted as responder, used once the initiator uses it
+    next: Option<Session>,
+    /// session replaced by the current one and the time it was replaced
+    previous: Option<(Session, Instant)>,
+    /// handshake started by us
+    pending: Option<Pending>,
+}
+
+impl PeerSessions {
+    /// Makes the given session the current one
+    fn rotate(&mut self, session: Session) {
+        if let Some(current) = self.current.take() {
+            self.previous = Some((current, Instant::now()));
+        }
+        self.current = Some(session);
+    }
+
+    /// Drops the previous session after the grace period
+    fn expire(&mut self) {
+        let expired = match self.previous {
+            Some((_, replaced)) => replaced.elapsed() >= Duration::from_secs(SESSION_GRACE),
+            None                => false,
+        };
+        if expired {
+            self.previous = None;
+        }
+    }
+}
+
+/// Session keys of all peers by their address
+///
+/// Clones share their state, so a handshake completed by the udp thread
+/// is used by every clone of the config.
+#[derive(Clone, Default)]
+pub struct Sessions {
+    /// sessions by the address of the peer
+    peers: Arc<Mutex<HashMap<String, PeerSessions>>>,
+}
+
+impl Sessions {
+    /// Creates an instance without any session
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new handshake with the given peer
+    ///
+    /// The returned payload must be sent to the peer as `Handshake`.
+    pub fn initiate(&self, peer: &str) -> Result<HandshakePayload, Error> {
+        let (public_key, secret_key) = box_::gen_keypair();
+        let id = random_id();
+
+        let mut peers = self.lock()?;
+        peers.entry(peer.to_string()).or_insert_with(PeerSessions::default).pending = Some(Pending {
+            id,
+            secret_key,
+            started: Instant::now(),
+        });
+
+        Ok(HandshakePayload {
+            session_id: id,
+            public_key,
+        })
+    }
+
+    /// Answers a handshake of the given peer
+    ///
+    /// If a handshake with the peer was started by us as well, the one of
+    /// the peer with the lower public key is used.
+    ///
+    /// # Params
+    /// - `peer` -> address of the peer
+    /// - `handshake` -> handshake of the peer
+    /// - `lower` -> true if our public key is lower than the one of the peer
+    ///
+    /// # Return
+    /// - `Result<Option<HandshakePayload>, Error>` -> payload that must be
+    ///   sent to the peer as `HandshakeAck`, `None` if our handshake is used
+    pub fn accept(&self, peer: &str, handshake: &HandshakePayload, lower: bool) -> Result<Option<HandshakePayload>, Error> {
+        let mut peers = self.lock()?;
+        let sessions = peers.entry(peer.to_string()).or_insert_with(PeerSessions::default);
+
+        if sessions.pending.is_some() {
+            if lower {
+                return Ok(None);
+            }
+            // the ephemeral secret key is dropped, the answer to our
+            // handshake is rejected by `complete`
+            sessions.pending = None;
+        }
+
+        let (public_key, secret_key) = box_::gen_keypair();
+        sessions.next = Some(Session {
+            id: handshake.session_id,
+            key: box_::precompute(&handshake.public_key, &secret_key),
+            established: Instant::now(),
+        });
+
+        Ok(Some(HandshakePayload {
+            session_id: handshake.session_id,
+            public_key,
+        }))
+    }
+
+    /// Completes a handshake started with `initiate`
+    ///
+    /// Fails if no handshake with the id of the answer was started.
+    pub fn complete(&self, peer: &str, ack: &HandshakePayload) -> Result<(), Error> {
+        let mut peers = self.lock()?;
+        let sessions = match peers.get_mut(peer) {
+            Some(sessions) => sessions,
+            None           => return Err(format_err!("No handshake with {} was started", peer)),
+        };
+
+        let matches = match sessions.pending {
+            Some(ref pending) => pending.id == ack.session_id,
+            None              => false,
+        };
+        if !matches {
+            return Err(format_err!("Unknown handshake {} from {}", ack.session_id, peer));
+        }
+
+        // unwrap ok, checked above. The ephemeral secret key is dropped here
+        let pending = sessions.pending.take().unwrap();
+        sessions.rotate(Session {
+            id: pending.id,
+            key: box_::precompute(&ack.public_key, &pending.secret_key),
+            established: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Key for sealing messages to the given peer, if a session exists
+    pub fn key(&self, peer: &str) -> Option<PrecomputedKey> {
+        match self.peers.lock() {
+            Ok(peers) => peers
+                .get(peer)
+                .and_then(|sessions| sessions.current.as_ref())
+                .map(|session| session.key.clone()),
+            Err(_)    => None,
+        }
+    }
+
+    /// All session keys a message from the given peer may be sealed with
+    ///
+    /// The current key comes first.
+    pub fn keys(&self, peer: &str) -> Vec<PrecomputedKey> {
+        let mut peers = match self.peers.lock() {
+            Ok(peers) => peers,
+            Err(_)    => return Vec::new(),
+        };
+
+        match peers.get_mut(peer) {
+            Some(sessions) => {
+                sessions.expire();
+                sessions.current.iter()
+                    .chain(sessions.next.iter())
+                    .chain(sessions.previous.iter().map(|(session, _)| session))
+                    .map(|session| session.key.clone())
+                    .collect()
+            },
+            None           => Vec::new(),
+        }
+    }
+
+    /// Marks the given key as used by the peer
+    ///
+    /// If it belongs to the session accepted last, that session becomes
+    /// the current one.
+    pub fn confirm(&self, peer: &str, key: &PrecomputedKey) {
+        if let Ok(mut peers) = self.peers.lock() {
+            if let Some(sessions) = peers.get_mut(peer) {
+                let confirmed = match sessions.next {
+                    Some(ref next) => next.key == *key,
+                    None           => false,
+                };
+
+                if confirmed {
+                    // unwrap ok, checked above
+                    let next = sessions.next.take().unwrap();
+                    debug!("[SESSION] Session {} with {} confirmed", next.id, peer);
+                    sessions.rotate(next);
+                }
+            }
+        }
+    }
+
+    /// true if a handshake with the peer should be started
+    ///
+    /// That is the case if there is no session yet or the current session
+    /// is older than `rekey_interval` seconds, and no handshake was started
+    /// or accepted during the last `HANDSHAKE_TIMEOUT` seconds.
+    pub fn needs_handshake(&self, peer: &str, rekey_interval: u64) -> bool {
+        let peers = match self.peers.lock() {
+            Ok(peers) => peers,
+            Err(_)    => return false,
+        };
+
+        match peers.get(peer) {
+            Some(sessions) => {
+                let timeout = Duration::from_secs(HANDSHAKE_TIMEOUT);
+                let started = match sessions.pending {
+                    Some(ref pending) => pending.started.elapsed() < timeout,
+                    None              => false,
+                };
+                // the peer uses the accepted session as soon as it gets
+                // the answer
+                let accepted = match sessions.next {
+                    Some(ref next) => next.established.elapsed() < timeout,
+                    None           => false,
+                };
+                let waiting = started || accepted;
+                let fresh = match sessions.current {
+                    Some(ref session) => session.established.elapsed() < Duration::from_secs(rekey_interval),
+                    None              => false,
+                };
+                !waiting && !fresh
+            },
+            None           => true,
+        }
+    }
+
+    /// Forgets all sessions with the given peer
+    pub fn remove(&self, peer: &str) {
+        if let Ok(mut peers) = self.peers.lock() {
+            peers.remove(peer);
+        }
+    }
+
+    /// Locks the sessions
+    fn lock(&self) -> Result<MutexGuard<HashMap<String, PeerSessions>>, Error> {
+        self.peers.lock().map_err(|e| format_err!("Error locking sessions. {}", e))
+    }
+}
+
+// the keys are secret, so only the peers with a session are shown
+impl fmt::Debug for Sessions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.peers.lock() {
+            Ok(peers) => write!(f, "Sessions {{ peers: {:?} }}", peers.keys().collect::<Vec<_>>()),
+            Err(_)    => write!(f, "Sessions {{ .. }}"),
+        }
+    }
+}
+
+/// Handles a decrypted `Handshake` or `HandshakeAck` from the given peer
+///
+/// A handshake is answered with a `HandshakeAck`, an answer completes the
+/// handshake started by us.
+pub(crate) fn handle(socket: &UdpSocket, peer: &Peer, config: &mut Config, event: Events, buffer: &[u8]) -> Result<(), Error> {
+    let payload = HandshakePayload::parse(Parser::parse_payload(buffer))?;
+
+    match event {
+        Events::Handshake    => {
+            let lower = config.nacl.public_key().0 < peer.public_key.0;
+            let ack = match config.sessions.accept(&peer.address, &payload, lower)? {
+                Some(ack) => ack,
+                None      => {
+                    debug!("[SESSION] Ignoring session {} of {}, our handshake is used", payload.session_id, peer.address);
+                    return Ok(());
+                }
+            };
+            // the peer doesn´t know the new session yet
+            let key = config.keys.get(&config.nacl, &peer.public_key);
+            let message = MessageBuilder::new()
+                .set_event_code(Events::as_val(Events::HandshakeAck))
+                .set_payload(ack)
+                .build_precomputed(&mut config.nacl, &key);
+
+            config.transport.send_reliable(socket, Events::HandshakeAck, &message, &peer.address)?;
+            debug!("[SESSION] Accepted session {} with {}", payload.session_id, peer.address);
+        },
+        Events::HandshakeAck => {
+            config.sessions.complete(&peer.address, &payload)?;
+            debug!("[SESSION] Established session {} with {}", payload.session_id, peer.address);
+        },
+        _                    => return Err(format_err!("Not a handshake event")),
+    }
+    Ok(())
+}
+
+/// Sends a `Handshake` to the given peer
+fn send_handshake(socket: &UdpSocket, peer: &Peer, config: &mut Config) -> Result<(), Error> {
+    let handshake = config.sessions.initiate(&peer.address)?;
+    let key = config.keys.get(&config.nacl, &peer.public_key);
+    let message = MessageBuilder::new()
+        .set_event_code(Events::as_val(Events::Handshake))
+        .set_payload(handshake)
+        .build_precomputed(&mut config.nacl, &key);
+
+    config.transport.send_reliable(socket, Events::Handshake, &message, &peer.address)
+}
+
+/// Starts a thread that establishes and renews the sessions with all peers
+pub(crate) fn start(carina_config: Arc<Mutex<CarinaConfig>>, socket: UdpSocket) -> JoinHandle<()> {
+    debug!("[THREAD_SESSION] Starting session thread");
+    thread::spawn(move || loop {
+        // peers may be learned while running
+        let mut config = match carina_config.lock() {
+            Ok(val) => val.config.clone(),
+            Err(e)  => {
+                error!("[THREAD_SESSION] Error locking carina_config. {}", e);
+                return;
+            }
+        };
+
+        for (_, peer) in config.peers.clone() {
+            if config.sessions.needs_handshake(&peer.address, config.rekey_interval) {
+                match send_handshake(&socket, &peer, &mut config) {
+                    Ok(_)  => debug!("[THREAD_SESSION] Sent handshake to {}", peer.address),
+                    Err(e) => error!("[THREAD_SESSION] Error sending handshake to {}. {}", peer.address, e),
+                };
+            }
+        }
+        thread::sleep(Duration::from_secs(REKEY_CHECK));
+    })
+}
+
+/// Random id for a new handshake
+fn random_id() -> u64 {
+    randombytes(8)
+        .iter()
+        .fold(0, |id, byte| (id << 8) | u64::from(*byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a complete handshake between the given sessions
+    fn handshake(initiator: &Sessions, responder: &Sessions) {
+        let handshake = initiator.initiate("responder").unwrap();
+        let ack = responder.accept("initiator", &handshake, false).unwrap().unwrap();
+        initiator.complete("responder", &ack).unwrap();
+
+        let key = initiator.key("responder").unwrap();
+        responder.confirm("initiator", &key);
+    }
+
+    #[test]
+    fn test_handshake() {
+        let initiator = Sessions::new();
+        let responder = Sessions::new();
+        assert!(initiator.needs_handshake("responder", 3600));
+
+        let handshake = initiator.initiate("responder").unwrap();
+        assert!(!initiator.needs_handshake("responder", 3600));
+
+        let ack = responder.accept("initiator", &handshake, false).unwrap().unwrap();
+        // the responder waits until the initiator uses the session
+        assert_eq!(None, responder.key("initiator"));
+        assert!(!responder.needs_handshake("initiator", 3600));
+
+        initiator.complete("responder", &ack).unwrap();
+        let key = initiator.key("responder").unwrap();
+        assert_eq!(vec![key.clone()], responder.keys("initiator"));
+
+        responder.confirm("initiator", &key);
+        assert_eq!(Some(key), responder.key("initiator"));
+        assert!(!initiator.needs_handshake("responder", 3600));
+    }
+
+    #[test]
+    fn test_rekey_keeps_previous() {
+        let initiator = Sessions::new();
+        let responder = Sessions::new();
+
+        handshake(&initiator, &responder);
+        let old = initiator.key("responder").unwrap();
+        assert!(initiator.needs_handshake("responder", 0));
+
+        handshake(&initiator, &responder);
+        let new = initiator.key("responder").unwrap();
+        assert!(old != new);
+        assert_eq!(vec![new.clone(), old.clone()], initiator.keys("responder"));
+        assert_eq!(vec![new, old], responder.keys("initiator"));
+    }
+
+    #[test]
+    fn test_unknown_ack() {
+        let initiator = Sessions::new();
+        let responder = Sessions::new();
+
+        let mut handshake = initiator.initiate("responder").unwrap();
+        handshake.session_id = handshake.session_id.wrapping_add(1);
+        let ack = responder.accept("initiator", &handshake, false).unwrap().unwrap();
+
+        assert!(initiator.complete("responder", &ack).is_err());
+        assert!(initiator.complete("other", &ack).is_err());
+        assert_eq!(None, initiator.key("responder"));
+    }
+
+    #[test]
+    fn test_simultaneous_handshake() {
+        let lower = Sessions::new();
+        let higher = Sessions::new();
+
+        let lower_handshake = lower.initiate("higher").unwrap();
+        let higher_handshake = higher.initiate("lower").unwrap();
+
+        // only the handshake of the lower key is answered
+        assert_eq!(None, lower.accept("higher", &higher_handshake, true).unwrap());
+        let ack = higher.accept("lower", &lower_handshake, false).unwrap().unwrap();
+        assert!(!higher.needs_handshake("lower", 3600));
+
+        lower.complete("higher", &ack).unwrap();
+        let key = lower.key("higher").unwrap();
+        higher.confirm("lower", &key);
+        assert_eq!(Some(key), higher.key("lower"));
+    }
+}
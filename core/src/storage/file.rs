@@ -0,0 +1,673 @@
+use failure::Error;
+use sodiumoxide::crypto::hash::sha256;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use storage::BlockStorage;
+
+/// Magic bytes every record starts with
+const RECORD_MAGIC: [u8; 4] = [0x43, 0x52, 0x4E, 0x41];
+/// Magic (4) + hash length (1) + data length (4)
+const RECORD_HEADER_SIZE: u64 = 9;
+/// Truncated sha256 of the hash and the data
+const RECORD_CHECKSUM_SIZE: u64 = 4;
+/// A new segment is started as soon as the current segment exceeds this size
+const MAX_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+/// Directory inside the storage containing the finality certificates
+const CERTIFICATE_DIR: &str = "certificates";
+
+/// Position of a single block on disk
+#[derive(Clone, Debug)]
+struct Location {
+    /// hash of the block
+    hash: String,
+    /// number of the segment file
+    segment: u32,
+    /// offset of the block data inside the segment
+    offset: u64,
+    /// length of the block data
+    length: u32,
+}
+
+/// Append-only block store backed by segment files
+///
+/// Every block is written as a single record to the current segment.
+///
+/// ```
+/// // 00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15
+/// //+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// //| Magic                 | Hash length           |
+/// //+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--|
+/// //| Data length                                   |
+/// //+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--|
+/// ////                Hash                         //
+/// //+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--|
+/// ////                Data                         //
+/// //+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--|
+/// //| Checksum                                      |
+/// //+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+///
+/// Finality certificates are stored as one file per block in the
+/// `certificates` directory.
+///
+/// The height of a block is the position of its record in the store.
+/// The height and hash index is rebuilt from the segments when the store
+/// is opened. A record that was only partly written, for example because
+/// the process crashed, is cut off the last segment. Stores opened with
+/// `open_read_only` are never changed, a partly written record is only
+/// reported.
+#[derive(Debug)]
+pub struct FileStorage {
+    /// directory containing the segment files
+    path: PathBuf,
+    /// height -> location
+    heights: Vec<Location>,
+    /// hash -> height
+    hashes: HashMap<String, u64>,
+    /// number of the segment new blocks are appended to
+    current_segment: u32,
+    /// size of the current segment
+    current_size: u64,
+    /// true if the store must not be changed
+    read_only: bool,
+    /// segment and offset of a partly written record, only set for read
+    /// only stores
+    incomplete_tail: Option<(u32, u64)>,
+}
+
+impl FileStorage {
+    /// Opens the store at the given directory
+    ///
+    /// The directory is created if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            fs::create_dir_all(&path)?;
+        }
+
+        let mut storage = Self::empty(path, false);
+        storage.recover()?;
+        Ok(storage)
+    }
+
+    /// Opens an existing store without changing it
+    ///
+    /// A partly written record at the end is not removed, see
+    /// `incomplete_tail`. All changes fail.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        if !path.is_dir() {
+            return Err(format_err!("Storage {} does not exist", path.display()));
+        }
+
+        let mut storage = Self::empty(path, true);
+        storage.recover()?;
+        Ok(storage)
+    }
+
+    /// Segment and offset of a partly written record at the end of the
+    /// store, only found by read only stores
+    ///
+    /// The record may still be written by a running node.
+    pub fn incomplete_tail(&self) -> Option<(u32, u64)> {
+        self.incomplete_tail
+    }
+
+    /// Creates a store without any blocks
+    fn empty(path: PathBuf, read_only: bool) -> Self {
+        Self {
+            path,
+            heights: Vec::new(),
+            hashes: HashMap::new(),
+            current_segment: 0,
+            current_size: 0,
+            read_only,
+            incomplete_tail: None,
+        }
+    }
+
+    /// Fails if the store was opened read only
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            Err(format_err!("Storage {} is opened read only", self.path.display()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reads the data of the given location
+    fn read(&self, location: &Location) -> Result<Vec<u8>, Error> {
+        let mut file = File::open(self.segment_path(location.segment))?;
+        file.seek(SeekFrom::Start(location.offset))?;
+
+        let mut data = vec![0; location.length as usize];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Rebuilds the index from all segments
+    ///
+    /// Only the last segment may end with an incomplete record, meaning
+    /// fewer bytes are left than its header declares. Such a record is
+    /// removed from the file, unless the store is read only.
+    /// A damaged record is never removed and fails the recovery.
+    fn recover(&mut self) -> Result<(), Error> {
+        let segments = self.segments()?;
+
+        for (position, segment) in segments.iter().enumerate() {
+            let is_last = position == segments.len() - 1;
+            let mut content = Vec::new();
+            File::open(self.segment_path(*segment))?.read_to_end(&mut content)?;
+
+            let mut offset = 0;
+            while (offset as usize) < content.len() {
+                match decode_record(&content[offset as usize..]) {
+                    Record::Valid(hash, length) => {
+                        if self.hashes.contains_key(&hash) {
+                            return Err(format_err!("Block {} is stored twice", hash));
+                        }
+
+                        let record_size = RECORD_HEADER_SIZE + hash.len() as u64 + length as u64 + RECORD_CHECKSUM_SIZE;
+                        self.hashes.insert(hash.clone(), self.heights.len() as u64);
+                        self.heights.push(Location {
+                            hash: hash.clone(),
+                            segment: *segment,
+                            offset: offset + RECORD_HEADER_SIZE + hash.len() as u64,
+                            length,
+                        });
+                        offset += record_size;
+                    },
+                    Record::Incomplete if is_last && self.read_only => {
+                        self.incomplete_tail = Some((*segment, offset));
+                        break;
+                    },
+                    Record::Incomplete if is_last => {
+                        warn!("[STORAGE] Removing incomplete block at offset {} of segment {}", offset, segment);
+                        let file = OpenOptions::new().write(true).open(self.segment_path(*segment))?;
+                        file.set_len(offset)?;
+                        file.sync_all()?;
+                        break;
+                    },
+                    Record::Incomplete => return Err(format_err!("Segment {} ends with an incomplete block at offset {}", segment, offset)),
+                    Record::Damaged    => return Err(format_err!("Segment {} is corrupt at offset {}", segment, offset)),
+                }
+            }
+
+            self.current_segment = *segment;
+            self.current_size = offset;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the numbers of all existing segments in ascending order
+    fn segments(&self) -> Result<Vec<u32>, Error> {
+        let mut segments = Vec::new();
+
+        for entry in fs::read_dir(&self.path)? {
+            let name = entry?.file_name();
+            let name = match name.to_str() {
+                Some(name) => name,
+                None       => continue,
+            };
+
+            if name.starts_with("segment_") && name.ends_with(".dat") {
+                match name[8..name.len() - 4].parse::<u32>() {
+                    Ok(number) => segments.push(number),
+                    Err(_)     => warn!("[STORAGE] Ignoring unknown file {}", name),
+                }
+            }
+        }
+
+        segments.sort();
+        Ok(segments)
+    }
+
+    /// Path to the segment with the given number
+    fn segment_path(&self, segment: u32) -> PathBuf {
+        self.path.join(format!("segment_{:08}.dat", segment))
+    }
+
+    /// Path to the certificate of the given block
+    ///
+    /// Fails if the hash could escape the certificate directory.
+    fn certificate_path(&self, hash: &str) -> Result<PathBuf, Error> {
+        if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format_err!("Invalid block hash {}", hash));
+        }
+        Ok(self.path.join(CERTIFICATE_DIR).join(format!("{}.cert", hash)))
+    }
+}
+
+impl BlockStorage for FileStorage {
+    fn append(&mut self, hash: &str, block: &[u8]) -> Result<u64, Error> {
+        self.check_writable()?;
+        if hash.is_empty() || hash.len() > 255 {
+            return Err(format_err!("Invalid block hash length {}", hash.len()));
+        }
+
+        if block.len() as u64 > u64::from(u32::max_value()) {
+            return Err(format_err!("Block {} is too large with {} bytes", hash, block.len()));
+        }
+
+        if self.hashes.contains_key(hash) {
+            return Err(format_err!("Block {} is already stored", hash));
+        }
+
+        let (segment, offset) = if self.current_size >= MAX_SEGMENT_SIZE {
+            (self.current_segment + 1, 0)
+        } else {
+            (self.current_segment, self.current_size)
+        };
+
+        let record = encode_record(hash, block);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(self.segment_path(segment))?;
+        if let Err(e) = write_record(&mut file, offset, &record) {
+            // cut the partly written record, so the next block is not
+            // appended after it
+            if let Err(e) = file.set_len(offset).and_then(|_| file.sync_all()) {
+                error!("[STORAGE] Error removing incomplete block from segment {}. {}", segment, e);
+            }
+            return Err(e);
+        }
+
+        let height = self.heights.len() as u64;
+        self.heights.push(Location {
+            hash: hash.to_string(),
+            segment,
+            offset: offset + RECORD_HEADER_SIZE + hash.len() as u64,
+            length: block.len() as u32,
+        });
+        self.hashes.insert(hash.to_string(), height);
+        self.current_segment = segment;
+        self.current_size = offset + record.len() as u64;
+        Ok(height)
+    }
+
+    fn get_by_height(&self, height: u64) -> Result<Option<Vec<u8>>, Error> {
+        match self.heights.get(height as usize) {
+            Some(location) => Ok(Some(self.read(location)?)),
+            None           => Ok(None),
+        }
+    }
+
+    fn get_by_hash(&self, hash: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self.hashes.get(hash) {
+            Some(height) => self.get_by_height(*height),
+            None         => Ok(None),
+        }
+    }
+
+    fn get_hash(&self, height: u64) -> Option<String> {
+        self.heights.get(height as usize).map(|location| location.hash.clone())
+    }
+
+    fn get_height(&self, hash: &str) -> Option<u64> {
+        self.hashes.get(hash).cloned()
+    }
+
+    fn truncate(&mut self, height: u64) -> Result<(), Error> {
+        self.check_writable()?;
+        let location = match self.heights.get(height as usize) {
+            Some(location) => location.clone(),
+            None           => return Ok(()),
+        };
+        let record_start = location.offset - RECORD_HEADER_SIZE - location.hash.len() as u64;
+
+        for segment in self.segments()? {
+            if segment > location.segment {
+                fs::remove_file(self.segment_path(segment))?;
+            }
+        }
+
+        let file = OpenOptions::new().write(true).open(self.segment_path(location.segment))?;
+        file.set_len(record_start)?;
+        file.sync_all()?;
+
+        for removed in self.heights.drain(height as usize..) {
+            self.hashes.remove(&removed.hash);
+        }
+        self.current_segment = location.segment;
+        self.current_size = record_start;
+        Ok(())
+    }
+
+    fn tip(&self) -> Option<(u64, String)> {
+        match self.heights.last() {
+            Some(location) => Some((self.heights.len() as u64 - 1, location.hash.clone())),
+            None           => None,
+        }
+    }
+
+    fn put_certificate(&mut self, hash: &str, certificate: &[u8]) -> Result<(), Error> {
+        self.check_writable()?;
+        let path = self.certificate_path(hash)?;
+        fs::create_dir_all(self.path.join(CERTIFICATE_DIR))?;
+
+        // write to a temporary file first, so a crash never leaves a
+        // partly written certificate behind
+        let temp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(certificate)?;
+            file.sync_all()?;
+        }
+        fs::rename(temp_path, path)?;
+        Ok(())
+    }
+
+    fn get_certificate(&self, hash: &str) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.certificate_path(hash)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut certificate = Vec::new();
+        File::open(path)?.read_to_end(&mut certificate)?;
+        Ok(Some(certificate))
+    }
+}
+
+/// Writes the record at the given offset of the segment
+///
+/// The offset is the end of the last complete record, so leftovers of an
+/// earlier failed write are overwritten.
+fn write_record(file: &mut File, offset: u64, record: &[u8]) -> Result<(), Error> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(record)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Creates a new record containing the hash and the block
+///
+/// The block must not be longer than `u32::MAX` bytes.
+fn encode_record(hash: &str, block: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(
+        RECORD_HEADER_SIZE as usize + hash.len() + block.len() + RECORD_CHECKSUM_SIZE as usize
+    );
+    record.extend(RECORD_MAGIC.iter());
+    record.push(hash.len() as u8);
+    record.extend(u32_to_bytes(block.len() as u32).iter());
+    record.extend(hash.as_bytes());
+    record.extend(block);
+
+    let checksum = checksum(hash.as_bytes(), block);
+    record.extend(checksum.iter());
+    record
+}
+
+/// Result of reading a record
+#[derive(Debug, PartialEq)]
+enum Record {
+    /// hash and length of the data of a valid record
+    Valid(String, u32),
+    /// fewer bytes are left than the record header declares
+    Incomplete,
+    /// the record is damaged
+    Damaged,
+}
+
+/// Tries to read the record at the beginning of the given bytes
+fn decode_record(bytes: &[u8]) -> Record {
+    if (bytes.len() as u64) < RECORD_HEADER_SIZE {
+        let magic = bytes.len().min(RECORD_MAGIC.len());
+        return if bytes[..magic] == RECORD_MAGIC[..magic] {
+            Record::Incomplete
+        } else {
+            Record::Damaged
+        };
+    }
+
+    if bytes[0..4] != RECORD_MAGIC {
+        return Record::Damaged;
+    }
+
+    let hash_length = bytes[4] as usize;
+    let length = bytes_to_u32(&bytes[5..9]);
+    let hash_start = RECORD_HEADER_SIZE as usize;
+    let data_start = hash_start + hash_length;
+    let data_end = data_start as u64 + u64::from(length);
+
+    if (bytes.len() as u64) < data_end + RECORD_CHECKSUM_SIZE {
+        return Record::Incomplete;
+    }
+
+    let data_end = data_end as usize;
+    let checksum = checksum(&bytes[hash_start..data_start], &bytes[data_start..data_end]);
+    if bytes[data_end..data_end + RECORD_CHECKSUM_SIZE as usize] != checksum {
+        return Record::Damaged;
+    }
+
+    match String::from_utf8(bytes[hash_start..data_start].to_vec()) {
+        Ok(hash) => Record::Valid(hash, length),
+        Err(_)   => Record::Damaged,
+    }
+}
+
+/// First four bytes of the sha256 over hash and data
+fn checksum(hash: &[u8], data: &[u8]) -> [u8; 4] {
+    let mut content = Vec::with_capacity(hash.len() + data.len());
+    content.extend(hash);
+    content.extend(data);
+
+    let digest = sha256::hash(&content);
+    [digest.0[0], digest.0[1], digest.0[2], digest.0[3]]
+}
+
+fn u32_to_bytes(value: u32) -> [u8; 4] {
+    [
+        (value & 0xFF) as u8,
+        ((value >> 8) & 0xFF) as u8,
+        ((value >> 16) & 0xFF) as u8,
+        ((value >> 24) & 0xFF) as u8,
+    ]
+}
+
+fn bytes_to_u32(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0])
+        | (u32::from(bytes[1]) << 8)
+        | (u32::from(bytes[2]) << 16)
+        | (u32::from(bytes[3]) << 24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+        env::temp_dir().join(format!("carina_storage_{}_{}_{}", name, process::id(), nanos))
+    }
+
+    #[test]
+    fn test_append_and_get() {
+        let path = temp_dir("append");
+        let mut storage = FileStorage::open(&path).unwrap();
+        assert_eq!(None, storage.tip());
+
+        assert_eq!(0, storage.append("hash_0", b"block_0").unwrap());
+        assert_eq!(1, storage.append("hash_1", b"block_1").unwrap());
+
+        assert_eq!(Some(b"block_0".to_vec()), storage.get_by_height(0).unwrap());
+        assert_eq!(Some(b"block_1".to_vec()), storage.get_by_hash("hash_1").unwrap());
+        assert_eq!(None, storage.get_by_height(2).unwrap());
+        assert_eq!(None, storage.get_by_hash("unknown").unwrap());
+        assert_eq!(Some(String::from("hash_0")), storage.get_hash(0));
+        assert_eq!(Some((1, String::from("hash_1"))), storage.tip());
+        assert!(storage.append("hash_1", b"block_1").is_err());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_reopen() {
+        let path = temp_dir("reopen");
+        {
+            let mut storage = FileStorage::open(&path).unwrap();
+            storage.append("hash_0", b"block_0").unwrap();
+            storage.append("hash_1", &vec![1; 1000]).unwrap();
+        }
+
+        let mut storage = FileStorage::open(&path).unwrap();
+        assert_eq!(Some((1, String::from("hash_1"))), storage.tip());
+        assert_eq!(Some(vec![1; 1000]), storage.get_by_hash("hash_1").unwrap());
+        assert_eq!(2, storage.append("hash_2", b"block_2").unwrap());
+        assert_eq!(Some(b"block_2".to_vec()), storage.get_by_height(2).unwrap());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_truncate() {
+        let path = temp_dir("truncate_height");
+        {
+            let mut storage = FileStorage::open(&path).unwrap();
+            storage.append("hash_0", b"block_0").unwrap();
+            storage.append("hash_1", b"block_1").unwrap();
+            storage.append("hash_2", b"block_2").unwrap();
+
+            storage.truncate(1).unwrap();
+            assert_eq!(Some((0, String::from("hash_0"))), storage.tip());
+            assert_eq!(None, storage.get_height("hash_2"));
+            assert_eq!(1, storage.append("hash_3", b"block_3").unwrap());
+        }
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(Some((1, String::from("hash_3"))), storage.tip());
+        assert_eq!(Some(b"block_3".to_vec()), storage.get_by_height(1).unwrap());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_truncate_incomplete_tail() {
+        let path = temp_dir("truncate");
+        {
+            let mut storage = FileStorage::open(&path).unwrap();
+            storage.append("hash_0", b"block_0").unwrap();
+            storage.append("hash_1", b"block_1").unwrap();
+        }
+
+        let segment = path.join("segment_00000000.dat");
+        let complete = fs::metadata(&segment).unwrap().len();
+        {
+            let record = encode_record("hash_2", b"block_2");
+            let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+            file.write_all(&record[..record.len() - 3]).unwrap();
+        }
+
+        let mut storage = FileStorage::open_read_only(&path).unwrap();
+        assert_eq!(Some((1, String::from("hash_1"))), storage.tip());
+        assert_eq!(Some((0, complete)), storage.incomplete_tail());
+        assert!(complete < fs::metadata(&segment).unwrap().len());
+        assert!(storage.append("hash_2", b"block_2").is_err());
+
+        storage = FileStorage::open(&path).unwrap();
+        assert_eq!(Some((1, String::from("hash_1"))), storage.tip());
+        assert_eq!(None, storage.incomplete_tail());
+        assert_eq!(complete, fs::metadata(&segment).unwrap().len());
+        assert_eq!(2, storage.append("hash_2", b"block_2").unwrap());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_record_is_kept() {
+        let path = temp_dir("corrupt");
+        {
+            let mut storage = FileStorage::open(&path).unwrap();
+            storage.append("hash_0", b"block_0").unwrap();
+            storage.append("hash_1", b"block_1").unwrap();
+            storage.append("hash_2", b"block_2").unwrap();
+        }
+
+        // damage the data of the second block
+        let segment = path.join("segment_00000000.dat");
+        let length = fs::metadata(&segment).unwrap().len();
+        {
+            let record = encode_record("hash_0", b"block_0");
+            let mut file = OpenOptions::new().write(true).open(&segment).unwrap();
+            file.seek(SeekFrom::Start(record.len() as u64 + RECORD_HEADER_SIZE + 6)).unwrap();
+            file.write_all(b"x").unwrap();
+        }
+
+        assert!(FileStorage::open(&path).is_err());
+        assert!(FileStorage::open_read_only(&path).is_err());
+        assert_eq!(length, fs::metadata(&segment).unwrap().len());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_record() {
+        let record = encode_record("hash_0", b"block_0");
+        assert_eq!(Record::Valid(String::from("hash_0"), 7), decode_record(&record));
+        assert_eq!(Record::Incomplete, decode_record(&record[..2]));
+        assert_eq!(Record::Incomplete, decode_record(&record[..record.len() - 1]));
+        assert_eq!(Record::Damaged, decode_record(&[0, 0]));
+
+        let mut damaged = record.clone();
+        damaged[0] = 0;
+        assert_eq!(Record::Damaged, decode_record(&damaged));
+
+        let mut damaged = record.clone();
+        let last = damaged.len() - 1;
+        damaged[last] ^= 0xFF;
+        assert_eq!(Record::Damaged, decode_record(&damaged));
+
+        // a length near u32::MAX must not overflow
+        let mut huge = record.clone();
+        huge[5..9].copy_from_slice(&u32_to_bytes(u32::max_value()));
+        assert_eq!(Record::Incomplete, decode_record(&huge));
+    }
+
+    #[test]
+    fn test_append_after_failed_write() {
+        let path = temp_dir("failed_write");
+        let segment = path.join("segment_00000000.dat");
+        {
+            let mut storage = FileStorage::open(&path).unwrap();
+            storage.append("hash_0", b"block_0").unwrap();
+
+            // leftover of a write that failed halfway
+            let record = encode_record("hash_1", b"block_1");
+            let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+            file.write_all(&record[..record.len() - 3]).unwrap();
+
+            assert_eq!(1, storage.append("hash_2", &vec![2; 100]).unwrap());
+        }
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(Some((1, String::from("hash_2"))), storage.tip());
+        assert_eq!(Some(vec![2; 100]), storage.get_by_height(1).unwrap());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_certificate() {
+        let path = temp_dir("certificate");
+        let hash = "ab".repeat(32);
+        {
+            let mut storage = FileStorage::open(&path).unwrap();
+            assert_eq!(None, storage.get_certificate(&hash).unwrap());
+            storage.put_certificate(&hash, b"certificate").unwrap();
+            assert!(storage.put_certificate("../escape", b"certificate").is_err());
+        }
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(Some(b"certificate".to_vec()), storage.get_certificate(&hash).unwrap());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+use failure::Error;
+use std::collections::HashMap;
+use storage::BlockStorage;
+
+/// Block storage that only lives in memory
+///
+/// Everything is lost when the instance is dropped.
+/// Useful for tests and short living nodes.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    /// height -> (hash, block)
+    blocks: Vec<(String, Vec<u8>)>,
+    /// hash -> height
+    hashes: HashMap<String, u64>,
+    /// hash -> finality certificate
+    certificates: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryStorage {
+    /// Creates a new empty storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BlockStorage for MemoryStorage {
+    fn append(&mut self, hash: &str, block: &[u8]) -> Result<u64, Error> {
+        if self.hashes.contains_key(hash) {
+            return Err(format_err!("Block {} is already stored", hash));
+        }
+
+        let height = self.blocks.len() as u64;
+        self.blocks.push((hash.to_string(), block.to_vec()));
+        self.hashes.insert(hash.to_string(), height);
+        Ok(height)
+    }
+
+    fn get_by_height(&self, height: u64) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.blocks.get(height as usize).map(|(_, block)| block.clone()))
+    }
+
+    fn get_by_hash(&self, hash: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self.hashes.get(hash) {
+            Some(height) => self.get_by_height(*height),
+            None         => Ok(None),
+        }
+    }
+
+    fn get_hash(&self, height: u64) -> Option<String> {
+        self.blocks.get(height as usize).map(|(hash, _)| hash.clone())
+    }
+
+    fn get_height(&self, hash: &str) -> Option<u64> {
+        self.hashes.get(hash).cloned()
+    }
+
+    fn truncate(&mut self, height: u64) -> Result<(), Error> {
+        if height < self.blocks.len() as u64 {
+            for (hash, _) in self.blocks.drain(height as usize..) {
+                self.hashes.remove(&hash);
+            }
+        }
+        Ok(())
+    }
+
+    fn tip(&self) -> Option<(u64, String)> {
+        match self.blocks.last() {
+            Some((hash, _)) => Some((self.blocks.len() as u64 - 1, hash.clone())),
+            None            => None,
+        }
+    }
+
+    fn put_certificate(&mut self, hash: &str, certificate: &[u8]) -> Result<(), Error> {
+        self.certificates.insert(hash.to_string(), certificate.to_vec());
+        Ok(())
+    }
+
+    fn get_certificate(&self, hash: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.certificates.get(hash).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_get() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(None, storage.tip());
+
+        assert_eq!(0, storage.append("hash_0", b"block_0").unwrap());
+        assert_eq!(1, storage.append("hash_1", b"block_1").unwrap());
+        assert!(storage.append("hash_1", b"block_1").is_err());
+
+        assert_eq!(Some(b"block_0".to_vec()), storage.get_by_height(0).unwrap());
+        assert_eq!(Some(b"block_1".to_vec()), storage.get_by_hash("hash_1").unwrap());
+        assert_eq!(None, storage.get_by_height(2).unwrap());
+        assert_eq!(Some((1, String::from("hash_1"))), storage.tip());
+
+        storage.truncate(1).unwrap();
+        assert_eq!(Some((0, String::from("hash_0"))), storage.tip());
+        assert_eq!(None, storage.get_height("hash_1"));
+        assert_eq!(1, storage.append("hash_1", b"block_1").unwrap());
+    }
+
+    #[test]
+    fn test_certificate() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(None, storage.get_certificate("hash_0").unwrap());
+
+        storage.put_certificate("hash_0", b"certificate").unwrap();
+        assert_eq!(Some(b"certificate".to_vec()), storage.get_certificate("hash_0").unwrap());
+    }
+}
@@ -0,0 +1,63 @@
+//! Persistence of blocks
+mod file;
+mod memory;
+
+pub use self::file::FileStorage;
+pub use self::memory::MemoryStorage;
+
+use config::{Config, StorageBackend};
+use failure::Error;
+use std::sync::{Arc, Mutex};
+
+/// Trait that every block storage backend must implement
+///
+/// Blocks are stored in the order they are appended.
+/// The position of a block is its height, starting with 0.
+pub trait BlockStorage: Send {
+    /// Appends a new block
+    ///
+    /// # Params
+    /// - `hash` -> hash of the block
+    /// - `block` -> serialized block
+    ///
+    /// # Return
+    /// - `Result<u64, Error>` -> height of the new block or error
+    fn append(&mut self, hash: &str, block: &[u8]) -> Result<u64, Error>;
+
+    /// Gets the block at the given height
+    fn get_by_height(&self, height: u64) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Gets the block with the given hash
+    fn get_by_hash(&self, hash: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Gets the hash of the block at the given height
+    fn get_hash(&self, height: u64) -> Option<String>;
+
+    /// Gets the height of the block with the given hash
+    fn get_height(&self, hash: &str) -> Option<u64>;
+
+    /// Removes all blocks starting with the given height
+    ///
+    /// Used when the chain is reorganised.
+    fn truncate(&mut self, height: u64) -> Result<(), Error>;
+
+    /// Height and hash of the latest block, `None` if there is no block
+    fn tip(&self) -> Option<(u64, String)>;
+
+    /// Stores the finality certificate of the block with the given hash
+    ///
+    /// An existing certificate is replaced. Certificates are kept when the
+    /// chain is truncated, as the block may become part of the chain again.
+    fn put_certificate(&mut self, hash: &str, certificate: &[u8]) -> Result<(), Error>;
+
+    /// Gets the finality certificate of the block with the given hash
+    fn get_certificate(&self, hash: &str) -> Result<Option<Vec<u8>>, Error>;
+}
+
+/// Opens the storage backend that is configured
+pub fn open_storage(config: &Config) -> Result<Arc<Mutex<BlockStorage>>, Error> {
+    match config.storage_backend {
+        StorageBackend::File   => Ok(Arc::new(Mutex::new(FileStorage::open(&config.storage)?))),
+        StorageBackend::Memory => Ok(Arc::new(Mutex::new(MemoryStorage::new()))),
+    }
+}
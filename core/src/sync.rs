@@ -0,0 +1,816 @@
+//! Height based synchronisation of the chain with other peers
+//!
+//! A node asks all peers for their tip. Afterwards the headers of the
+//! missing heights are requested in ranges and the block bodies one by
+//! one, spread over all peers that have them. Every response is validated
+//! as soon as it arrives. Requests that are not answered in time are sent
+//! to another peer.
+//!
+//! Everything that is already stored is never requested again, so a sync
+//! that was interrupted continues at the tip of the local chain.
+use carina_core_protocol::payloads::block::{
+    Block, BlockHeader, GetBlockAckPayload, GetBlockPayload, GetHeadersPayload,
+    HeadersPayload, TipPayload, MAX_HEADERS,
+};
+use carina_core_protocol::{Events, MessageBuilder, Payload};
+use chain::ChainManager;
+use config::Config;
+use difficulty::{meets_target, Retarget};
+use failure::Error;
+use std::collections::{BTreeMap, HashMap};
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use storage::BlockStorage;
+use validation::read_header;
+
+/// Maximum number of header ranges requested at the same time
+const MAX_HEADER_REQUESTS: usize = 4;
+/// Maximum number of blocks requested from a single peer at the same time
+const MAX_BLOCKS_PER_PEER: usize = 8;
+/// Seconds after that a request is sent to another peer
+const REQUEST_TIMEOUT: u64 = 10;
+/// Maximum number of heights the headers run ahead of the local chain
+const MAX_HEADERS_AHEAD: u64 = MAX_HEADERS * MAX_HEADER_REQUESTS as u64;
+
+/// Request the sync wants to send
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyncRequest {
+    /// Requests a range of headers
+    GetHeaders {
+        /// address of the peer
+        peer: String,
+        /// requested range
+        payload: GetHeadersPayload,
+    },
+    /// Requests a single block
+    GetBlock {
+        /// address of the peer
+        peer: String,
+        /// requested block
+        payload: GetBlockPayload,
+    },
+}
+
+/// Request that was not answered yet
+#[derive(Clone, Debug)]
+struct Request {
+    /// peer the request was sent to
+    peer: String,
+    /// first requested height
+    height: u64,
+    /// number of requested items
+    count: u64,
+    /// time the request was sent
+    sent: Instant,
+}
+
+/// Keeps track of a running synchronisation
+#[derive(Debug)]
+pub struct SyncManager {
+    /// chain the blocks are added to
+    chain: Arc<Mutex<ChainManager>>,
+    /// latest tip of every peer
+    tips: HashMap<String, TipPayload>,
+    /// height of the next header that is connected to the chain
+    next_header: u64,
+    /// header ranges that are requested, by their first height
+    pending_headers: HashMap<u64, Request>,
+    /// header ranges that are received but not connected yet
+    received_headers: BTreeMap<u64, (String, Vec<BlockHeader>)>,
+    /// connected headers of blocks that are not part of the chain yet
+    headers: BTreeMap<u64, BlockHeader>,
+    /// blocks that are requested, by their hash
+    pending_blocks: HashMap<String, Request>,
+    /// received blocks that wait for their predecessor
+    bodies: BTreeMap<u64, Block>,
+    /// used for spreading requests over all peers
+    next_peer: usize,
+}
+
+impl SyncManager {
+    /// Creates a new instance that syncs into the given chain
+    pub fn new(chain: Arc<Mutex<ChainManager>>) -> Self {
+        Self {
+            chain,
+            tips: HashMap::new(),
+            next_header: 0,
+            pending_headers: HashMap::new(),
+            received_headers: BTreeMap::new(),
+            headers: BTreeMap::new(),
+            pending_blocks: HashMap::new(),
+            bodies: BTreeMap::new(),
+            next_peer: 0,
+        }
+    }
+
+    /// Highest height any peer reported
+    pub fn best_height(&self) -> Option<u64> {
+        self.tips.values().map(|tip| tip.height).max()
+    }
+
+    /// true if the local chain is as high as the chain of every peer and
+    /// nothing is requested anymore
+    pub fn is_synced(&self) -> Result<bool, Error> {
+        let local = self.local_tip()?.map(|(height, _)| height);
+        let behind = match (self.best_height(), local) {
+            (Some(best), Some(local)) => best > local,
+            (Some(_), None)           => true,
+            (None, _)                 => false,
+        };
+
+        Ok(!behind && self.headers.is_empty() && self.pending_headers.is_empty())
+    }
+
+    /// Handles the tip of a peer
+    ///
+    /// # Return
+    /// - `Result<Vec<SyncRequest>, Error>` -> requests that should be sent
+    pub fn on_tip(&mut self, peer: &str, tip: TipPayload) -> Result<Vec<SyncRequest>, Error> {
+        if tip.hash.is_empty() {
+            self.tips.remove(peer);
+            return Ok(Vec::new());
+        }
+
+        if self.tips.is_empty() && self.headers.is_empty() && self.pending_headers.is_empty() {
+            self.next_header = self.local_tip()?.map(|(height, _)| height + 1).unwrap_or(0);
+        }
+
+        debug!("[SYNC] Peer {} is at height {}", peer, tip.height);
+        self.tips.insert(peer.to_string(), tip);
+        self.schedule()
+    }
+
+    /// Handles a block whose parent is not known
+    ///
+    /// The peer that sent the block must know its ancestors, so their
+    /// headers are requested from it. The block itself waits in the orphan
+    /// pool of the chain until its parent is added.
+    ///
+    /// # Return
+    /// - `Result<Vec<SyncRequest>, Error>` -> requests that should be sent
+    pub fn on_orphan(&mut self, peer: &str, block: &Block) -> Result<Vec<SyncRequest>, Error> {
+        if block.header.index == 0 {
+            return Ok(Vec::new());
+        }
+
+        let height = block.header.index - 1;
+        match self.tips.get(peer) {
+            Some(tip) if tip.height >= height => self.schedule(),
+            _                                 => self.on_tip(peer, TipPayload { height, hash: block.header.prev.clone() }),
+        }
+    }
+
+    /// Handles a range of headers
+    ///
+    /// # Return
+    /// - `Result<Vec<SyncRequest>, Error>` -> requests that should be sent
+    pub fn on_headers(&mut self, peer: &str, payload: HeadersPayload) -> Result<Vec<SyncRequest>, Error> {
+        let request = match self.pending_headers.remove(&payload.start) {
+            Some(request) => request,
+            None          => return Ok(Vec::new()),
+        };
+        if request.peer != peer {
+            self.pending_headers.insert(payload.start, request);
+            return Ok(Vec::new());
+        }
+
+        if payload.headers.is_empty() {
+            debug!("[SYNC] Peer {} has no headers starting at {}", peer, payload.start);
+            self.tips.remove(peer);
+        } else if payload.headers.len() as u64 > request.count || !valid_range(payload.start, &payload.headers) {
+            error!("[SYNC] Peer {} sent invalid headers starting at {}", peer, payload.start);
+            self.tips.remove(peer);
+        } else {
+            self.received_headers.insert(payload.start, (peer.to_string(), payload.headers));
+            self.connect_headers()?;
+        }
+
+        self.schedule()
+    }
+
+    /// Handles a requested block
+    ///
+    /// # Return
+    /// - `Result<Vec<SyncRequest>, Error>` -> requests that should be sent
+    pub fn on_block(&mut self, peer: &str, payload: GetBlockAckPayload) -> Result<Vec<SyncRequest>, Error> {
+        let request = match self.pending_blocks.remove(&payload.hash) {
+            Some(request) => request,
+            None          => return Ok(Vec::new()),
+        };
+        if request.peer != peer {
+            self.pending_blocks.insert(payload.hash, request);
+            return Ok(Vec::new());
+        }
+
+        let expected = self.headers.get(&request.height).cloned();
+        let block = payload.block;
+        let valid = payload.found
+            && Some(&block.header) == expected.as_ref()
+            && block.hash() == payload.hash
+            && block.is_consistent();
+
+        if valid {
+            self.bodies.insert(request.height, block);
+            self.apply()?;
+        } else {
+            error!("[SYNC] Peer {} did not send a valid block {}", peer, payload.hash);
+            self.tips.remove(peer);
+        }
+
+        self.schedule()
+    }
+
+    /// Sends requests that timed out to other peers
+    ///
+    /// Should be called regularly while syncing.
+    ///
+    /// # Return
+    /// - `Result<Vec<SyncRequest>, Error>` -> requests that should be sent
+    pub fn tick(&mut self) -> Result<Vec<SyncRequest>, Error> {
+        let timeout = Duration::from_secs(REQUEST_TIMEOUT);
+
+        let expired: Vec<u64> = self.pending_headers
+            .iter()
+            .filter(|(_, request)| request.sent.elapsed() >= timeout)
+            .map(|(start, _)| *start)
+            .collect();
+        for start in expired {
+            if let Some(request) = self.pending_headers.remove(&start) {
+                info!("[SYNC] Peer {} did not send headers starting at {} in time", request.peer, start);
+            }
+        }
+
+        let expired: Vec<String> = self.pending_blocks
+            .iter()
+            .filter(|(_, request)| request.sent.elapsed() >= timeout)
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        for hash in expired {
+            if let Some(request) = self.pending_blocks.remove(&hash) {
+                info!("[SYNC] Peer {} did not send block {} in time", request.peer, hash);
+            }
+        }
+
+        self.schedule()
+    }
+
+    /// Creates the requests for everything that is missing
+    fn schedule(&mut self) -> Result<Vec<SyncRequest>, Error> {
+        let mut requests = Vec::new();
+        let target = match self.best_height() {
+            Some(target) => target,
+            None         => return Ok(requests),
+        };
+        // the tip of a peer is not verified, only request headers up to a
+        // limit above the local chain
+        let local = self.local_tip()?.map(|(height, _)| height).unwrap_or(0);
+        let target = target.min(local.saturating_add(MAX_HEADERS_AHEAD));
+
+        let mut height = self.next_header;
+        while height <= target && self.pending_headers.len() < MAX_HEADER_REQUESTS {
+            if self.headers.contains_key(&height) {
+                height += 1;
+                continue;
+            }
+            if let Some(end) = self.covered_until(height) {
+                height = end;
+                continue;
+            }
+
+            let peer = match self.pick_peer(height, false) {
+                Some(peer) => peer,
+                None       => break,
+            };
+            let count = self.uncovered_count(height, target);
+
+            self.pending_headers.insert(height, Request { peer: peer.clone(), height, count, sent: Instant::now() });
+            requests.push(SyncRequest::GetHeaders {
+                peer,
+                payload: GetHeadersPayload { start: height, count },
+            });
+            height += count;
+        }
+
+        let missing: Vec<(u64, String)> = self.headers
+            .iter()
+            .filter(|(height, _)| !self.bodies.contains_key(*height))
+            .map(|(height, header)| (*height, header.hash()))
+            .filter(|(_, hash)| !self.pending_blocks.contains_key(hash))
+            .collect();
+        for (height, hash) in missing {
+            let peer = match self.pick_peer(height, true) {
+                Some(peer) => peer,
+                None       => break,
+            };
+
+            self.pending_blocks.insert(hash.clone(), Request { peer: peer.clone(), height, count: 1, sent: Instant::now() });
+            requests.push(SyncRequest::GetBlock {
+                peer,
+                payload: GetBlockPayload { hash },
+            });
+        }
+
+        Ok(requests)
+    }
+
+    /// End of the requested or received header range containing `height`
+    fn covered_until(&self, height: u64) -> Option<u64> {
+        let pending = self.pending_headers
+            .values()
+            .map(|request| (request.height, request.height + request.count));
+        let received = self.received_headers
+            .iter()
+            .map(|(start, (_, headers))| (*start, *start + headers.len() as u64));
+
+        pending
+            .chain(received)
+            .filter(|(start, end)| *start <= height && height < *end)
+            .map(|(_, end)| end)
+            .max()
+    }
+
+    /// Number of headers starting with `height` that are not requested yet
+    fn uncovered_count(&self, height: u64, target: u64) -> u64 {
+        let mut count = 1;
+        while count < MAX_HEADERS && height + count <= target {
+            let next = height + count;
+            if self.headers.contains_key(&next) || self.covered_until(next).is_some() {
+                break;
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Picks the next peer that has the given height
+    ///
+    /// With `blocks` set, only peers with free capacity for blocks are used.
+    fn pick_peer(&mut self, height: u64, blocks: bool) -> Option<String> {
+        let mut candidates: Vec<&String> = self.tips
+            .iter()
+            .filter(|(_, tip)| tip.height >= height)
+            .map(|(peer, _)| peer)
+            .filter(|peer| {
+                !blocks || self.pending_blocks.values().filter(|request| request.peer == **peer).count() < MAX_BLOCKS_PER_PEER
+            })
+            .collect();
+
+        if candidates.is_empty() {
+            return None;
+        }
+
+        candidates.sort();
+        self.next_peer = self.next_peer.wrapping_add(1);
+        Some(candidates[self.next_peer % candidates.len()].clone())
+    }
+
+    /// Connects received header ranges to the chain
+    fn connect_headers(&mut self) -> Result<(), Error> {
+        loop {
+            let next = self.next_header;
+            let stale: Vec<u64> = self.received_headers
+                .iter()
+                .filter(|(start, (_, headers))| **start + headers.len() as u64 <= next)
+                .map(|(start, _)| *start)
+                .collect();
+            for start in stale {
+                self.received_headers.remove(&start);
+            }
+
+            let start = match self.received_headers
+                .range(..next + 1)
+                .next_back()
+                .filter(|(start, (_, headers))| **start + headers.len() as u64 > next)
+                .map(|(start, _)| *start) {
+                Some(start) => start,
+                None        => return Ok(()),
+            };
+            let (peer, headers) = match self.received_headers.remove(&start) {
+                Some(range) => range,
+                None        => return Ok(()),
+            };
+
+            for header in headers.into_iter().skip((next - start) as usize) {
+                let height = header.index;
+                let prev = if height == 0 {
+                    Some("0".repeat(64))
+                } else {
+                    match self.headers.get(&(height - 1)) {
+                        Some(prev) => Some(prev.hash()),
+                        None       => self.local_hash(height - 1)?,
+                    }
+                };
+
+                if prev.as_ref() != Some(&header.prev) {
+                    let local = self.local_tip()?.map(|(tip, _)| tip);
+                    let below_tip = height > 0
+                        && !self.headers.contains_key(&(height - 1))
+                        && Some(height - 1) <= local;
+
+                    if below_tip {
+                        // the chain of the peer forks below our tip, go back
+                        // until both chains match
+                        let back = height.saturating_sub(MAX_HEADERS);
+                        info!("[SYNC] Chain of {} forks below height {}, going back to {}", peer, height, back);
+                        self.reset(back);
+                    } else {
+                        error!("[SYNC] Headers of {} do not connect at height {}", peer, height);
+                        self.tips.remove(&peer);
+                    }
+                    break;
+                }
+
+                let difficulty = self.expected_difficulty(height)?;
+                if header.difficulty != difficulty {
+                    error!("[SYNC] Header {} of {} has difficulty {} instead of {}", height, peer, header.difficulty, difficulty);
+                    self.tips.remove(&peer);
+                    break;
+                }
+
+                let hash = header.hash();
+                if self.local_hash(height)? != Some(hash) {
+                    self.headers.insert(height, header);
+                }
+                self.next_header = height + 1;
+            }
+        }
+    }
+
+    /// Difficulty the header at the given height must have
+    ///
+    /// The previous headers are taken from the connected headers or the
+    /// local chain.
+    fn expected_difficulty(&self, height: u64) -> Result<u32, Error> {
+        let retarget = self.retarget()?;
+        let prev = match height {
+            0      => None,
+            height => Some(self.header(height - 1)?),
+        };
+        retarget.required_difficulty(prev.as_ref(), |height| self.header(height))
+    }
+
+    /// Connected header or header of the local block at the given height
+    fn header(&self, height: u64) -> Result<BlockHeader, Error> {
+        if let Some(header) = self.headers.get(&height) {
+            return Ok(header.clone());
+        }
+
+        let storage = self.storage()?;
+        let storage = match storage.lock() {
+            Ok(storage) => storage,
+            Err(e)      => return Err(format_err!("Error locking storage. {}", e)),
+        };
+        read_header(&*storage, height)
+    }
+
+    /// Adds all blocks to the chain whose predecessor is known
+    fn apply(&mut self) -> Result<(), Error> {
+        loop {
+            let height = match self.headers.keys().next() {
+                Some(height) => *height,
+                None         => return Ok(()),
+            };
+            let block = match self.bodies.remove(&height) {
+                Some(block) => block,
+                None        => return Ok(()),
+            };
+            self.headers.remove(&height);
+
+            let result = match self.chain.lock() {
+                Ok(mut chain) => chain.add_block(block),
+                Err(e)        => return Err(format_err!("Error locking chain. {}", e)),
+            };
+
+            match result {
+                Ok(update) => debug!("[SYNC] Added block {}. {:?}", height, update),
+                Err(e)     => {
+                    error!("[SYNC] Block {} is invalid. {}", height, e);
+                    self.reset(height);
+                    return Ok(());
+                }
+            };
+        }
+    }
+
+    /// Forgets everything starting with the given height
+    fn reset(&mut self, height: u64) {
+        self.next_header = height;
+        let _ = self.headers.split_off(&height);
+        let _ = self.bodies.split_off(&height);
+        self.received_headers.clear();
+        self.pending_headers.clear();
+        self.pending_blocks.retain(|_, request| request.height < height);
+    }
+
+    /// Tip of the local chain
+    fn local_tip(&self) -> Result<Option<(u64, String)>, Error> {
+        let storage = self.storage()?;
+        let storage = match storage.lock() {
+            Ok(storage) => storage,
+            Err(e)      => return Err(format_err!("Error locking storage. {}", e)),
+        };
+        Ok(storage.tip())
+    }
+
+    /// Hash of the local block at the given height
+    fn local_hash(&self, height: u64) -> Result<Option<String>, Error> {
+        let storage = self.storage()?;
+        let storage = match storage.lock() {
+            Ok(storage) => storage,
+            Err(e)      => return Err(format_err!("Error locking storage. {}", e)),
+        };
+        Ok(storage.get_hash(height))
+    }
+
+    /// Storage of the chain
+    fn storage(&self) -> Result<Arc<Mutex<BlockStorage>>, Error> {
+        match self.chain.lock() {
+            Ok(chain) => Ok(chain.storage()),
+            Err(e)    => Err(format_err!("Error locking chain. {}", e)),
+        }
+    }
+
+    /// Rules for adjusting the difficulty of the chain
+    fn retarget(&self) -> Result<Retarget, Error> {
+        match self.chain.lock() {
+            Ok(chain) => Ok(chain.retarget()),
+            Err(e)    => Err(format_err!("Error locking chain. {}", e)),
+        }
+    }
+}
+
+/// Checks that the headers follow each other and meet their difficulty
+fn valid_range(start: u64, headers: &[BlockHeader]) -> bool {
+    for (i, header) in headers.iter().enumerate() {
+        if header.index != start + i as u64 || !meets_target(&header.hash(), header.difficulty) {
+            return false;
+        }
+        if i > 0 && header.prev != headers[i - 1].hash() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Creates the answer for `GetTip`
+pub fn get_tip(storage: &BlockStorage) -> TipPayload {
+    match storage.tip() {
+        Some((height, hash)) => TipPayload { height, hash },
+        None                 => TipPayload::new(),
+    }
+}
+
+/// Creates the answer for `GetHeaders`
+pub fn get_headers(storage: &BlockStorage, request: &GetHeadersPayload) -> Result<HeadersPayload, Error> {
+    let mut headers = Vec::new();
+    let count = request.count.min(MAX_HEADERS);
+
+    for height in request.start..request.start.saturating_add(count) {
+        match storage.get_by_height(height)? {
+            Some(bytes) => headers.push(Block::from_bytes(&bytes)?.header),
+            None        => break,
+        };
+    }
+
+    Ok(HeadersPayload {
+        start: request.start,
+        headers,
+    })
+}
+
+/// Creates the answer for `GetBlock`
+pub fn get_block(storage: &BlockStorage, request: &GetBlockPayload) -> Result<GetBlockAckPayload, Error> {
+    match storage.get_by_hash(&request.hash)? {
+        Some(bytes) => Ok(GetBlockAckPayload {
+            hash: request.hash.clone(),
+            found: true,
+            block: Block::from_bytes(&bytes)?,
+        }),
+        None        => Ok(GetBlockAckPayload::not_found(request.hash.clone())),
+    }
+}
+
+/// Sends the given requests to the peers
+pub fn send_requests(socket: &UdpSocket, config: &mut Config, requests: Vec<SyncRequest>) {
+    for request in requests {
+        let (peer, event) = match request {
+            SyncRequest::GetHeaders { ref peer, .. } => (peer.clone(), Events::GetHeaders),
+            SyncRequest::GetBlock { ref peer, .. }   => (peer.clone(), Events::GetBlock),
+        };
+
+        let key = match config.peers.get(&peer) {
+            Some(peer) => config.seal_key(peer),
+            None       => {
+                error!("[SYNC] Unknown peer {}", peer);
+                continue;
+            }
+        };
+
+        let message = match request {
+            SyncRequest::GetHeaders { payload, .. } => MessageBuilder::new()
+                .set_event_code(Events::as_val(event))
+                .set_payload(payload)
+                .build_precomputed(&mut config.nacl, &key),
+            SyncRequest::GetBlock { payload, .. }   => MessageBuilder::new()
+                .set_event_code(Events::as_val(event))
+                .set_payload(payload)
+                .build_precomputed(&mut config.nacl, &key),
+        };
+
+        match config.transport.send_to(socket, event, &message, &peer) {
+            Ok(_)  => debug!("[SYNC] Sent {:?} to {}", event, peer),
+            Err(e) => error!("[SYNC] Error sending {:?} to {}. {}", event, peer, e),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carina_core_protocol::payloads::block::NewBlockContentPayload;
+    use chain::ChainUpdate;
+    use validation::DEFAULT_MAX_TIME_DRIFT;
+    use storage::MemoryStorage;
+
+    fn mine(index: u64, prev: String) -> Block {
+        let entries = vec![NewBlockContentPayload {
+            unique_key: format!("key_{}", index),
+            content: String::from("content"),
+        }];
+        let mut block = Block::new(index, prev, entries);
+        while !meets_target(&block.hash(), block.header.difficulty) {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    fn chain(blocks: &[Block]) -> Arc<Mutex<ChainManager>> {
+        let storage: Arc<Mutex<BlockStorage>> = Arc::new(Mutex::new(MemoryStorage::new()));
+        let mut chain = ChainManager::new(storage, Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap();
+        for block in blocks {
+            chain.add_block(block.clone()).unwrap();
+        }
+        Arc::new(Mutex::new(chain))
+    }
+
+    /// Answers all requests with the given remote chain
+    fn answer(sync: &mut SyncManager, remote: &Arc<Mutex<ChainManager>>, requests: Vec<SyncRequest>) {
+        let storage = remote.lock().unwrap().storage();
+        let mut requests = requests;
+
+        while let Some(request) = requests.pop() {
+            let next = match request {
+                SyncRequest::GetHeaders { peer, payload } => {
+                    let headers = get_headers(&*storage.lock().unwrap(), &payload).unwrap();
+                    sync.on_headers(&peer, headers).unwrap()
+                },
+                SyncRequest::GetBlock { peer, payload } => {
+                    let block = get_block(&*storage.lock().unwrap(), &payload).unwrap();
+                    sync.on_block(&peer, block).unwrap()
+                }
+            };
+            requests.extend(next);
+        }
+    }
+
+    fn tip(chain: &Arc<Mutex<ChainManager>>) -> TipPayload {
+        let storage = chain.lock().unwrap().storage();
+        let storage = storage.lock().unwrap();
+        get_tip(&*storage)
+    }
+
+    #[test]
+    fn test_sync_empty_chain() {
+        let mut blocks = vec![mine(0, "0".repeat(64))];
+        for index in 1..4 {
+            let prev = blocks[index - 1].hash();
+            blocks.push(mine(index as u64, prev));
+        }
+        let remote = chain(&blocks);
+        let local = chain(&[]);
+
+        let mut sync = SyncManager::new(Arc::clone(&local));
+        let requests = sync.on_tip("peer", tip(&remote)).unwrap();
+        assert_eq!(
+            vec![SyncRequest::GetHeaders { peer: String::from("peer"), payload: GetHeadersPayload { start: 0, count: 4 } }],
+            requests
+        );
+
+        answer(&mut sync, &remote, requests);
+        assert!(sync.is_synced().unwrap());
+        assert_eq!(tip(&remote), tip(&local));
+    }
+
+    #[test]
+    fn test_sync_resumes_at_tip() {
+        let genesis = mine(0, "0".repeat(64));
+        let block_1 = mine(1, genesis.hash());
+        let block_2 = mine(2, block_1.hash());
+        let remote = chain(&[genesis.clone(), block_1.clone(), block_2]);
+        let local = chain(&[genesis, block_1]);
+
+        let mut sync = SyncManager::new(Arc::clone(&local));
+        let requests = sync.on_tip("peer", tip(&remote)).unwrap();
+        assert_eq!(
+            vec![SyncRequest::GetHeaders { peer: String::from("peer"), payload: GetHeadersPayload { start: 2, count: 1 } }],
+            requests
+        );
+
+        answer(&mut sync, &remote, requests);
+        assert_eq!(tip(&remote), tip(&local));
+    }
+
+    #[test]
+    fn test_sync_fork_below_tip() {
+        let genesis = mine(0, "0".repeat(64));
+        let local_1 = mine(1, genesis.hash());
+        let mut remote_1 = mine(1, genesis.hash());
+        remote_1.header.timestamp += 1;
+        while !meets_target(&remote_1.hash(), remote_1.header.difficulty) {
+            remote_1.header.nonce += 1;
+        }
+        let remote_2 = mine(2, remote_1.hash());
+
+        let remote = chain(&[genesis.clone(), remote_1, remote_2]);
+        let local = chain(&[genesis, local_1]);
+
+        let mut sync = SyncManager::new(Arc::clone(&local));
+        let requests = sync.on_tip("peer", tip(&remote)).unwrap();
+        answer(&mut sync, &remote, requests);
+
+        assert!(sync.is_synced().unwrap());
+        assert_eq!(tip(&remote), tip(&local));
+    }
+
+    #[test]
+    fn test_sync_orphan() {
+        let genesis = mine(0, "0".repeat(64));
+        let block_1 = mine(1, genesis.hash());
+        let block_2 = mine(2, block_1.hash());
+        let remote = chain(&[genesis.clone(), block_1.clone(), block_2.clone()]);
+        let local = chain(&[genesis]);
+
+        let mut sync = SyncManager::new(Arc::clone(&local));
+        assert_eq!(ChainUpdate::Orphan, local.lock().unwrap().add_block(block_2.clone()).unwrap());
+
+        let requests = sync.on_orphan("peer", &block_2).unwrap();
+        assert_eq!(
+            vec![SyncRequest::GetHeaders { peer: String::from("peer"), payload: GetHeadersPayload { start: 1, count: 1 } }],
+            requests
+        );
+
+        answer(&mut sync, &remote, requests);
+        assert_eq!(tip(&remote), tip(&local));
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        let genesis = mine(0, "0".repeat(64));
+        let remote = chain(&[genesis.clone()]);
+        let local = chain(&[]);
+
+        let mut sync = SyncManager::new(Arc::clone(&local));
+        sync.on_tip("peer", tip(&remote)).unwrap();
+
+        let mut header = genesis.header;
+        header.prev = "f".repeat(64);
+        let headers = HeadersPayload { start: 0, headers: vec![header] };
+
+        assert!(sync.on_headers("peer", headers).unwrap().is_empty());
+        assert_eq!(None, sync.best_height());
+    }
+
+    #[test]
+    fn test_wrong_difficulty() {
+        let mut genesis = mine(0, "0".repeat(64));
+        genesis.header.difficulty += 1;
+        while !meets_target(&genesis.hash(), genesis.header.difficulty) {
+            genesis.header.nonce += 1;
+        }
+        let local = chain(&[]);
+
+        let mut sync = SyncManager::new(Arc::clone(&local));
+        sync.on_tip("peer", TipPayload { height: 0, hash: genesis.hash() }).unwrap();
+
+        let headers = HeadersPayload { start: 0, headers: vec![genesis.header] };
+        assert!(sync.on_headers("peer", headers).unwrap().is_empty());
+        assert_eq!(None, sync.best_height());
+    }
+
+    #[test]
+    fn test_headers_ahead_limited() {
+        let local = chain(&[mine(0, "0".repeat(64))]);
+
+        let mut sync = SyncManager::new(Arc::clone(&local));
+        let requests = sync.on_tip("peer", TipPayload { height: u64::max_value(), hash: "a".repeat(64) }).unwrap();
+        assert_eq!(MAX_HEADER_REQUESTS, requests.len());
+
+        for request in requests {
+            match request {
+                SyncRequest::GetHeaders { payload, .. } => assert!(payload.start + payload.count <= MAX_HEADERS_AHEAD + 1),
+                request                                 => panic!("Unexpected request {:?}", request),
+            };
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Sends sealed messages to other peers
+use carina_core_protocol::{fragment, Events, FragmentKind};
+use failure::Error;
+use sodiumoxide::randombytes::randombytes_uniform;
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Milliseconds until a reliable message is sent the first time again
+const INITIAL_BACKOFF: u64 = 500;
+/// Milliseconds between two checks for messages that must be sent again
+const RETRANSMIT_CHECK: u64 = 100;
+/// Number of times a reliable message is sent again before giving up
+const MAX_RETRANSMITS: u32 = 5;
+/// Seconds a received reliable message is remembered, so retransmissions
+/// are not handed to the handlers twice
+///
+/// Must be longer than all retransmissions of a message take.
+const DELIVERED_EXPIRY: u64 = 60;
+
+/// Reliable message that was not acknowledged yet
+#[derive(Clone, Debug)]
+struct Pending {
+    /// datagrams of the message
+    datagrams: Vec<Vec<u8>>,
+    /// number of retransmissions so far
+    retransmits: u32,
+    /// time to wait before the next retransmission
+    backoff: Duration,
+    /// time of the last transmission
+    sent: Instant,
+}
+
+/// Handle for sending messages
+///
+/// Messages are split into fragments that fit into a single datagram. The
+/// receiver puts them back together before handing the message to the
+/// event handlers. Clones share their state, so every message gets its own
+/// id and acknowledgements reach the clone that sent the message.
+///
+/// Messages sent with `send_reliable` are sent again with exponential
+/// backoff until the receiver acknowledges them or `MAX_RETRANSMITS` is
+/// reached.
+#[derive(Clone, Debug)]
+pub struct Transport {
+    /// id of the next message
+    next_id: Arc<AtomicUsize>,
+    /// reliable messages that are not acknowledged by their address and id
+    pending: Arc<Mutex<HashMap<(String, u32), Pending>>>,
+    /// received reliable messages by their sender and id
+    delivered: Arc<Mutex<HashMap<(String, u32), Instant>>>,
+    /// number of sent messages by their event
+    sent: Arc<Mutex<HashMap<Events, u64>>>,
+}
+
+impl Transport {
+    /// Creates a new instance starting with a random message id
+    pub fn new() -> Self {
+        Self {
+            next_id: Arc::new(AtomicUsize::new(randombytes_uniform(u32::max_value()) as usize)),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            delivered: Arc::new(Mutex::new(HashMap::new())),
+            sent: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Sends a message built by `MessageBuilder` to the given address
+    ///
+    /// Fire and forget, lost messages are not sent again. `event` is the
+    /// event of the message, it is only used to count the sent messages.
+    pub fn send_to(&self, socket: &UdpSocket, event: Events, message: &[u8], address: &str) -> Result<(), Error> {
+        for datagram in fragment(FragmentKind::Data, self.next_id(), message)? {
+            socket.send_to(&datagram, address)?;
+        }
+        self.count(event);
+        Ok(())
+    }
+
+    /// Sends a message built by `MessageBuilder` to the given address and
+    /// sends it again until it is acknowledged
+    pub fn send_reliable(&self, socket: &UdpSocket, event: Events, message: &[u8], address: &str) -> Result<(), Error> {
+        let message_id = self.next_id();
+        let datagrams = fragment(FragmentKind::Reliable, message_id, message)?;
+
+        match self.pending.lock() {
+            Ok(mut pending) => pending.insert((address.to_string(), message_id), Pending {
+                datagrams: datagrams.clone(),
+                retransmits: 0,
+                backoff: Duration::from_millis(INITIAL_BACKOFF),
+                sent: Instant::now(),
+            }),
+            Err(e)          => return Err(format_err!("Error locking pending messages. {}", e)),
+        };
+
+        for datagram in datagrams {
+            socket.send_to(&datagram, address)?;
+        }
+        self.count(event);
+        Ok(())
+    }
+
+    /// Number of sent messages by their event
+    ///
+    /// Messages sent again are only counted once.
+    pub fn sent(&self) -> HashMap<Events, u64> {
+        match self.sent.lock() {
+            Ok(sent) => sent.clone(),
+            Err(_)   => HashMap::new(),
+        }
+    }
+
+    /// Number of reliable messages that are not acknowledged yet
+    pub fn pending(&self) -> usize {
+        match self.pending.lock() {
+            Ok(pending) => pending.len(),
+            Err(_)      => 0,
+        }
+    }
+
+    /// Marks a reliable message as acknowledged
+    ///
+    /// The ack is not authenticated, see `Fragment::ack`.
+    pub fn acknowledge(&self, address: &str, message_id: u32) {
+        if let Ok(mut pending) = self.pending.lock() {
+            if pending.remove(&(address.to_string(), message_id)).is_some() {
+                debug!("[TRANSPORT] Message {} was acknowledged by {}", message_id, address);
+            }
+        }
+    }
+
+    /// Remembers a received reliable message
+    ///
+    /// # Return
+    /// - `bool` -> true if the message was not received before
+    pub fn delivered(&self, source: &str, message_id: u32) -> bool {
+        let mut delivered = match self.delivered.lock() {
+            Ok(delivered) => delivered,
+            Err(_)        => return true,
+        };
+
+        let expiry = Duration::from_secs(DELIVERED_EXPIRY);
+        let expired: Vec<(String, u32)> = delivered
+            .iter()
+            .filter(|(_, received)| received.elapsed() >= expiry)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            delivered.remove(&key);
+        }
+
+        delivered.insert((source.to_string(), message_id), Instant::now()).is_none()
+    }
+
+    /// Sends all reliable messages again whose backoff is over
+    ///
+    /// Messages that reached the retransmission limit are dropped.
+    pub fn retransmit(&self, socket: &UdpSocket) {
+        let mut pending = match self.pending.lock() {
+            Ok(pending) => pending,
+            Err(e)      => {
+                error!("[TRANSPORT] Error locking pending messages. {}", e);
+                return;
+            }
+        };
+
+        let due: Vec<(String, u32)> = pending
+            .iter()
+            .filter(|(_, message)| message.sent.elapsed() >= message.backoff)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in due {
+            let give_up = match pending.get_mut(&key) {
+                Some(message) => {
+                    if message.retransmits < MAX_RETRANSMITS {
+                        message.retransmits += 1;
+                        message.backoff *= 2;
+                        message.sent = Instant::now();
+
+                        debug!("[TRANSPORT] Sending message {} to {} again", key.1, key.0);
+                        for datagram in &message.datagrams {
+                            if let Err(e) = socket.send_to(datagram, &key.0) {
+                                error!("[TRANSPORT] Error sending message {} to {}. {}", key.1, key.0, e);
+                            }
+                        }
+                        false
+                    } else {
+                        true
+                    }
+                },
+                None          => false,
+            };
+
+            if give_up {
+                error!("[TRANSPORT] Message {} was not acknowledged by {}", key.1, key.0);
+                pending.remove(&key);
+            }
+        }
+    }
+
+    /// Counts a sent message
+    fn count(&self, event: Events) {
+        if let Ok(mut sent) = self.sent.lock() {
+            *sent.entry(event).or_insert(0) += 1;
+        }
+    }
+
+    /// Id for the next message
+    fn next_id(&self) -> u32 {
+        self.next_id.fetch_add(1, Ordering::SeqCst) as u32
+    }
+}
+
+/// Starts a thread that sends unacknowledged messages again
+pub(crate) fn start(transport: Transport, socket: UdpSocket) -> JoinHandle<()> {
+    debug!("[THREAD_TRANSPORT] Starting retransmit thread");
+    thread::spawn(move || loop {
+        transport.retransmit(&socket);
+        thread::sleep(Duration::from_millis(RETRANSMIT_CHECK));
+    })
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carina_core_protocol::Fragment;
+
+    fn sockets() -> (UdpSocket, UdpSocket) {
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        (sender, receiver)
+    }
+
+    fn receive(socket: &UdpSocket) -> Fragment {
+        let mut buffer = [0; 65535];
+        let (bytes, _) = socket.recv_from(&mut buffer).unwrap();
+        Fragment::from_bytes(&buffer[..bytes]).unwrap()
+    }
+
+    #[test]
+    fn test_retransmit_until_acknowledged() {
+        let (sender, receiver) = sockets();
+        let address = receiver.local_addr().unwrap().to_string();
+        let transport = Transport::new();
+
+        transport.send_reliable(&sender, Events::Ping, &[1, 2, 3], &address).unwrap();
+        let first = receive(&receiver);
+        assert_eq!(FragmentKind::Reliable, first.kind);
+        assert_eq!(1, transport.pending());
+        assert_eq!(Some(&1), transport.sent().get(&Events::Ping));
+
+        // nothing is due yet
+        transport.retransmit(&sender);
+        assert_eq!(1, transport.pending());
+
+        {
+            let mut pending = transport.pending.lock().unwrap();
+            for message in pending.values_mut() {
+                message.backoff = Duration::from_secs(0);
+            }
+        }
+        transport.retransmit(&sender);
+        assert_eq!(first, receive(&receiver));
+
+        transport.acknowledge(&address, first.message_id);
+        assert_eq!(0, transport.pending());
+    }
+
+    #[test]
+    fn test_give_up() {
+        let (sender, receiver) = sockets();
+        let address = receiver.local_addr().unwrap().to_string();
+        let transport = Transport::new();
+
+        transport.send_reliable(&sender, Events::Ping, &[1], &address).unwrap();
+        for _ in 0..MAX_RETRANSMITS + 1 {
+            {
+                let mut pending = transport.pending.lock().unwrap();
+                for message in pending.values_mut() {
+                    message.backoff = Duration::from_secs(0);
+                }
+            }
+            transport.retransmit(&sender);
+        }
+
+        assert_eq!(0, transport.pending());
+    }
+
+    #[test]
+    fn test_delivered_once() {
+        let transport = Transport::new();
+
+        assert!(transport.delivered("peer", 1));
+        assert!(!transport.delivered("peer", 1));
+        assert!(transport.delivered("other", 1));
+    }
+}
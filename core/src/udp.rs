@@ -1,9 +1,75 @@
 use carina_config::CarinaConfig;
-use carina_core_protocol::{decrypt, Events};
+use carina_core_protocol::{decrypt_introduction, decrypt_precomputed, Events, Fragment, FragmentKind, Reassembler, Replay};
+use chain;
+use config::{Config, Peer};
+use discovery;
+use session;
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, PrecomputedKey, PublicKey};
 use std::net::UdpSocket;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Seconds an incomplete message is kept
+const REASSEMBLY_TIMEOUT: u64 = 30;
+/// Maximum number of bytes all incomplete messages may use
+const MAX_REASSEMBLY_BYTES: usize = 16 * 1024 * 1024;
+/// Maximum number of bytes the incomplete messages of a single sender may use
+const MAX_SOURCE_REASSEMBLY_BYTES: usize = 4 * 1024 * 1024;
+/// Maximum number of incomplete messages of a single sender
+const MAX_SOURCE_PARTIALS: usize = 64;
+
+/// true if the client with the given public key is accepted by the
+/// current configuration
+fn accepts_client(carina_config: &Arc<Mutex<CarinaConfig>>, public_key: &PublicKey) -> bool {
+    match carina_config.lock() {
+        Ok(carina_config) => carina_config.config.accepts_client(public_key),
+        Err(e)            => {
+            error!("[THREAD_UDP] Error locking carina_config. {}", e);
+            false
+        }
+    }
+}
+
+/// true if the given public key belongs to a peer or a client of the
+/// current configuration
+fn is_known(carina_config: &Arc<Mutex<CarinaConfig>>, public_key: &PublicKey) -> bool {
+    match carina_config.lock() {
+        Ok(carina_config) => carina_config.config.clients.contains(public_key)
+            || carina_config.config.peers.values().any(|peer| peer.public_key == *public_key),
+        Err(e)            => {
+            error!("[THREAD_UDP] Error locking carina_config. {}", e);
+            false
+        }
+    }
+}
+
+/// Acknowledges a decrypted message after its nonce was checked
+///
+/// Only messages that were authenticated and passed the replay check are
+/// acknowledged and marked as delivered. A copy that is sent again has the
+/// same nonce, so it is a duplicate, but it is acknowledged as well,
+/// because the first ack may have been lost.
+///
+/// # Return
+/// - `Result<bool, Replay>` -> false if the message was already delivered
+fn acknowledge(socket: &UdpSocket, config: &Config, reliable: bool, message_id: u32, source: &str, replay: Result<(), Replay>) -> Result<bool, Replay> {
+    if !reliable {
+        return replay.map(|_| true);
+    }
+
+    match replay {
+        Ok(_) | Err(Replay::Duplicate) => {
+            if let Err(e) = socket.send_to(&Fragment::ack(message_id).to_bytes(), source) {
+                error!("[THREAD_UDP] Error acknowledging message {} from {}. {}", message_id, source, e);
+            }
+        },
+        Err(_)                         => (),
+    };
+
+    replay.map(|_| config.transport.delivered(source, message_id))
+}
 
 pub fn start(
     carina_config: Arc<Mutex<CarinaConfig>>,
@@ -20,65 +86,209 @@ pub fn start(
             carina_config.config.clone()
         };
 
+        let mut reassembler = Reassembler::new(
+            Duration::from_secs(REASSEMBLY_TIMEOUT),
+            MAX_REASSEMBLY_BYTES,
+            MAX_SOURCE_REASSEMBLY_BYTES,
+            MAX_SOURCE_PARTIALS,
+        );
+
         debug!("[THREAD_UDP] Starting udp listener");
         loop {
             let mut buffer = [0; 65535];
 
             match socket.recv_from(&mut buffer) {
                 Ok((bytes, source)) => {
-                    let mut updated_buffer = Vec::new();
-                    for i in 0..bytes {
-                        updated_buffer.push(buffer[i])
-                    }
+                    reassembler.expire();
+                    let fragment = match Fragment::from_bytes(&buffer[..bytes]) {
+                        Ok(fragment) => fragment,
+                        Err(e)       => {
+                            info!("[THREAD_UDP] Dropping datagram from {}. {}", source, e);
+                            continue;
+                        }
+                    };
+                    let message_id = fragment.message_id;
+                    let reliable = fragment.kind == FragmentKind::Reliable;
+                    let source_addr = source.to_string();
+                    let updated_buffer = match fragment.kind {
+                        // acks are not sealed, see `Fragment::ack`
+                        FragmentKind::Ack                          => {
+                            config.transport.acknowledge(&source_addr, message_id);
+                            continue;
+                        },
+                        FragmentKind::Data | FragmentKind::Reliable => match reassembler.add(&source_addr, fragment) {
+                            Some(message) => message,
+                            None          => continue,
+                        },
+                    };
 
                     debug!(
                         "[THREAD_UDP] Received message from {}. Message: {:?}",
                         source, updated_buffer
                     );
-                    let parsed = match config.peers.get(&(source.to_string())) {
-                        Some(peer) => {
-                            let parsed = decrypt(
-                                &updated_buffer,
-                                &config.nacl,
-                                &peer.public_key,
-                            );
-
-                            if parsed.is_ok() {
-                                Some(parsed.unwrap())
-                            } else {
+                    // peers may be learned while running
+                    let peer = match carina_config.lock() {
+                        Ok(carina_config) => carina_config.config.peers.get(&source_addr).cloned(),
+                        Err(e)            => {
+                            error!("[THREAD_UDP] Error locking carina_config. {}", e);
+                            continue;
+                        }
+                    };
+
+                    // session keys first, the long-term key is used until a
+                    // session is established. Besides the message the public
+                    // key of the sender and the session key are kept
+                    let opened = match peer {
+                        Some(ref peer) => {
+                            let mut keys: Vec<(PrecomputedKey, bool)> = config.sessions
+                                .keys(&source_addr)
+                                .into_iter()
+                                .map(|key| (key, true))
+                                .collect();
+                            keys.push((config.keys.get(&config.nacl, &peer.public_key), false));
+                            keys.into_iter()
+                                .filter_map(|(key, session)| decrypt_precomputed(&updated_buffer, &key).ok().map(|parsed| (parsed, key, session)))
+                                .next()
+                                .map(|(parsed, key, session)| {
+                                    let nonce = Nonce::from_slice(&updated_buffer[0..24]).unwrap();
+                                    (parsed, nonce, peer.public_key, if session { Some(key) } else { None })
+                                })
+                        },
+                        None           => None,
+                    };
+
+                    // peers that don´t know each other yet may only register,
+                    // clients may only send the client events
+                    let mut introduced = None;
+                    let mut client = None;
+                    let opened = match opened {
+                        Some(opened) => Some(opened),
+                        None         => match decrypt_introduction(&updated_buffer, &config.nacl) {
+                            Ok((public_key, nonce, parsed)) => {
+                                let event = if parsed.len() >= 2 { Events::as_enum(parsed[1]) } else { Events::Invalid };
+                                if event == Events::Register {
+                                    introduced = Some(public_key);
+                                    Some((parsed, nonce, public_key, None))
+                                } else if Events::is_client(event) && accepts_client(&carina_config, &public_key) {
+                                    client = Some(Peer {
+                                        address: source_addr.clone(),
+                                        public_key,
+                                        sign_key: None,
+                                    });
+                                    Some((parsed, nonce, public_key, None))
+                                } else {
+                                    info!("[THREAD_UDP] Dropping introduced message from {}, that is not a register or from an unknown client", source);
+                                    config.metrics.unknown_source();
+                                    None
+                                }
+                            },
+                            Err(_) if peer.is_some()        => {
+                                info!("[THREAD_UDP] Error decrypting message from {}", source);
+                                config.metrics.decrypt_failure();
+                                None
+                            },
+                            Err(_)                          => {
+                                info!("[THREAD_UDP] Didn´t find peer");
+                                config.metrics.unknown_source();
                                 None
                             }
-                        }
-                        None => {
-                            info!("[THREAD_UDP] Didn´t find peer");
-                            None
-                        }
+                        },
+                    };
+
+                    let parsed = match opened {
+                        // only a session key proves that a new nonce prefix
+                        // doesn´t belong to a recorded message
+                        Some((parsed, nonce, sender, key)) => {
+                            let known = match peer {
+                                Some(ref peer) if peer.public_key == sender => true,
+                                _                                           => is_known(&carina_config, &sender),
+                            };
+                            let replay = config.replay.check(&sender, &nonce, key.is_some(), known);
+                            match acknowledge(&socket, &config, reliable, message_id, &source_addr, replay) {
+                                Ok(false) => {
+                                    debug!("[THREAD_UDP] Dropping duplicated message {} from {}", message_id, source);
+                                    None
+                                },
+                                Ok(true)  => {
+                                    if let Some(key) = key {
+                                        config.sessions.confirm(&source_addr, &key);
+                                    }
+                                    if peer.is_some() {
+                                        config.metrics.seen(&source_addr);
+                                    }
+                                    if parsed.len() >= 2 {
+                                        config.metrics.received(Events::as_enum(parsed[1]));
+                                    }
+                                    Some(parsed)
+                                },
+                                Err(e)    => {
+                                    info!("[THREAD_UDP] Dropping replayed message from {}. {}", source, e);
+                                    None
+                                }
+                            }
+                        },
+                        None                               => None,
                     };
 
                     match parsed {
+                        Some(ref buf) if Events::is_local(Events::as_enum(buf[1])) => {
+                            info!("[THREAD_UDP] Dropping local only event from {}", source);
+                        },
+                        Some(ref buf) if Events::is_handshake(Events::as_enum(buf[1])) => {
+                            // introductions are only accepted for register,
+                            // so the peer is known
+                            if let Some(ref peer) = peer {
+                                let mut config = config.clone();
+                                if let Err(e) = session::handle(&socket, peer, &mut config, Events::as_enum(buf[1]), &buf[2..]) {
+                                    error!("[THREAD_UDP] Error handling handshake from {}. {}", source, e);
+                                    config.metrics.handler_error(Events::as_enum(buf[1]));
+                                }
+                            }
+                        },
+                        Some(ref buf) if Events::is_discovery(Events::as_enum(buf[1])) => {
+                            if let Err(e) = discovery::handle(&socket, &carina_config, &source_addr, introduced, Events::as_enum(buf[1]), &buf[2..]) {
+                                info!("[THREAD_UDP] Error handling discovery event from {}. {}", source, e);
+                                config.metrics.handler_error(Events::as_enum(buf[1]));
+                            }
+                        },
                         Some(buf) => {
-                            let mut config = {
+                            // the handlers run without holding the lock, so
+                            // long running handlers don't block other events
+                            let (mut config, events, chain) = {
                                 let carina_config = carina_config.lock().unwrap();
-                                carina_config.config.clone()
+                                let events = carina_config.events
+                                    .get(&Events::as_enum(buf[1]))
+                                    .cloned()
+                                    .unwrap_or_default();
+                                (carina_config.config.clone(), events, carina_config.chain.clone())
                             };
+                            // the handlers answer clients like peers
+                            if let Some(client) = client {
+                                config.peers.insert(client.address.clone(), client);
+                            }
 
-                            let mut state = carina_config.lock().unwrap();
-                            match state.events.get_mut(&Events::as_enum(buf[1])) {
-                                Some(ref mut events) => {
-                                    for i in 0..events.len() {
-                                        match events[i].lock() {
-                                            Ok(mut event) => {
-                                                match event.execute(socket.try_clone().unwrap(), source.to_string(), &mut config, &buf[2..]) {
-                                                    Err(e) => error!("[THREAD_UDP] Error calling execute {:?}", e),
-                                                    _      => ()
-                                                }
+                            let event_code = Events::as_enum(buf[1]);
+                            for event in events {
+                                match event.lock() {
+                                    Ok(mut event) => {
+                                        let started = Instant::now();
+                                        match event.execute(socket.try_clone().unwrap(), source.to_string(), &mut config, &buf[2..]) {
+                                            Err(e) => {
+                                                error!("[THREAD_UDP] Error calling execute {:?}", e);
+                                                config.metrics.handler_error(event_code);
                                             },
-                                            Err(_)        => error!("[THREAD_UDP] Error locking mutex.")
-                                        };
-                                    }
-                                },
-                                None         => ()
-                            };
+                                            _      => ()
+                                        }
+                                        config.metrics.handler_latency(event_code, started.elapsed());
+                                    },
+                                    Err(_)        => error!("[THREAD_UDP] Error locking mutex.")
+                                };
+                            }
+
+                            // the handlers may have reorganised the chain
+                            if let Some(chain) = chain {
+                                chain::notify_reorgs(&chain, &socket, &config);
+                            }
                         }
                         None => (),
                     }
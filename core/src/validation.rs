@@ -0,0 +1,342 @@
+//! Rules every block must follow
+use carina_core_protocol::payloads::block::{Block, BlockHeader};
+use difficulty::{meets_target, Retarget};
+use failure::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+use storage::BlockStorage;
+
+/// Default number of seconds a block may be ahead of the local time
+pub const DEFAULT_MAX_TIME_DRIFT: u64 = 600;
+
+/// Reasons why a block is not valid
+#[derive(Clone, Debug, Fail, PartialEq)]
+pub enum InvalidBlock {
+    /// The block could not be read
+    #[fail(display = "block could not be parsed: {}", reason)]
+    Malformed {
+        /// error returned by the parser
+        reason: String,
+    },
+    /// The index does not follow the index of the previous block
+    #[fail(display = "expected index {} but got {}", expected, actual)]
+    Index {
+        /// index the block should have
+        expected: u64,
+        /// index of the block
+        actual: u64,
+    },
+    /// The block does not point to the previous block
+    #[fail(display = "prev {} does not match the previous hash {}", actual, expected)]
+    Prev {
+        /// hash of the previous block
+        expected: String,
+        /// prev of the block
+        actual: String,
+    },
+    /// The hash the block is known under is not the hash of the block
+    #[fail(display = "hash {} does not match the calculated hash {}", expected, actual)]
+    Hash {
+        /// hash the block is known under
+        expected: String,
+        /// hash calculated from the block
+        actual: String,
+    },
+    /// The difficulty is not the difficulty required at this height
+    #[fail(display = "expected difficulty {} but got {}", expected, actual)]
+    Difficulty {
+        /// difficulty the block should have
+        expected: u32,
+        /// difficulty of the block
+        actual: u32,
+    },
+    /// The hash does not meet the proof of work target
+    #[fail(display = "hash {} does not meet the proof of work target", hash)]
+    ProofOfWork {
+        /// hash of the block
+        hash: String,
+    },
+    /// The block was created before the previous block
+    #[fail(display = "timestamp {} is before the previous timestamp {}", actual, previous)]
+    Timestamp {
+        /// timestamp of the previous block
+        previous: u64,
+        /// timestamp of the block
+        actual: u64,
+    },
+    /// The block was created too far in the future
+    #[fail(display = "timestamp {} is after the latest allowed timestamp {}", actual, latest)]
+    Future {
+        /// latest timestamp a block may have
+        latest: u64,
+        /// timestamp of the block
+        actual: u64,
+    },
+    /// The merkle root does not match the entries
+    #[fail(display = "merkle root does not match the entries")]
+    MerkleRoot,
+}
+
+/// Error returned when verifying a chain
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[fail(display = "Block {} is invalid: {}", height, reason)]
+pub struct InvalidChain {
+    /// height of the first invalid block
+    pub height: u64,
+    /// why the block is invalid
+    pub reason: InvalidBlock,
+}
+
+/// Validates a block against its predecessor
+///
+/// # Params
+/// - `prev` -> header of the previous block, `None` for the first block
+/// - `difficulty` -> difficulty required at the height of the block
+/// - `latest` -> latest timestamp the block may have, see `latest_timestamp`
+/// - `hash` -> hash the block was announced or stored with
+/// - `block` -> block to validate
+pub fn validate_block(prev: Option<&BlockHeader>, difficulty: u32, latest: u64, hash: &str, block: &Block) -> Result<(), InvalidBlock> {
+    let header = &block.header;
+    let (index, prev_hash) = match prev {
+        Some(prev) => (prev.index + 1, prev.hash()),
+        None       => (0, "0".repeat(64)),
+    };
+
+    if header.index != index {
+        return Err(InvalidBlock::Index { expected: index, actual: header.index });
+    }
+
+    if header.prev != prev_hash {
+        return Err(InvalidBlock::Prev { expected: prev_hash, actual: header.prev.clone() });
+    }
+
+    let calculated = header.hash();
+    if calculated != hash {
+        return Err(InvalidBlock::Hash { expected: hash.to_string(), actual: calculated });
+    }
+
+    if header.difficulty != difficulty {
+        return Err(InvalidBlock::Difficulty { expected: difficulty, actual: header.difficulty });
+    }
+
+    if !meets_target(&calculated, header.difficulty) {
+        return Err(InvalidBlock::ProofOfWork { hash: calculated });
+    }
+
+    if let Some(prev) = prev {
+        if header.timestamp < prev.timestamp {
+            return Err(InvalidBlock::Timestamp { previous: prev.timestamp, actual: header.timestamp });
+        }
+    }
+    if header.timestamp > latest {
+        return Err(InvalidBlock::Future { latest, actual: header.timestamp });
+    }
+
+    if !block.is_consistent() {
+        return Err(InvalidBlock::MerkleRoot);
+    }
+
+    Ok(())
+}
+
+/// Walks the stored chain from the first block to the tip and validates
+/// every block
+///
+/// The difficulty of every block is checked against the given retarget
+/// configuration, the timestamps against the local time plus
+/// `max_time_drift` seconds.
+///
+/// # Return
+/// - `Result<u64, Error>` -> number of valid blocks, `InvalidChain` if a block is not valid
+pub fn verify_chain(storage: &BlockStorage, retarget: &Retarget, max_time_drift: u64) -> Result<u64, Error> {
+    let tip = match storage.tip() {
+        Some((height, _)) => height,
+        None              => return Ok(0),
+    };
+
+    let latest = latest_timestamp(max_time_drift);
+    let mut prev: Option<BlockHeader> = None;
+    for height in 0..tip + 1 {
+        let hash = match storage.get_hash(height) {
+            Some(hash) => hash,
+            None       => return Err(format_err!("Block {} is missing", height)),
+        };
+        let block = match storage.get_by_height(height)? {
+            Some(bytes) => match Block::from_bytes(&bytes) {
+                Ok(block) => block,
+                Err(e)    => return Err(InvalidChain {
+                    height,
+                    reason: InvalidBlock::Malformed { reason: e.to_string() },
+                }.into()),
+            },
+            None        => return Err(format_err!("Block {} is missing", height)),
+        };
+
+        let difficulty = retarget.required_difficulty(prev.as_ref(), |height| read_header(storage, height))?;
+
+        if let Err(reason) = validate_block(prev.as_ref(), difficulty, latest, &hash, &block) {
+            return Err(InvalidChain { height, reason }.into());
+        }
+        prev = Some(block.header);
+    }
+
+    Ok(tip + 1)
+}
+
+/// Latest timestamp a block may have, the local time plus the given drift
+/// in seconds
+pub fn latest_timestamp(max_time_drift: u64) -> u64 {
+    let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(now) => now.as_secs(),
+        Err(_)  => 0,
+    };
+    now.saturating_add(max_time_drift)
+}
+
+/// Reads the header of the block at the given height
+pub(crate) fn read_header(storage: &BlockStorage, height: u64) -> Result<BlockHeader, Error> {
+    match storage.get_by_height(height)? {
+        Some(bytes) => Ok(Block::from_bytes(&bytes)?.header),
+        None        => Err(format_err!("Block {} is missing", height)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carina_core_protocol::Payload;
+    use carina_core_protocol::payloads::block::{NewBlockContentPayload, DEFAULT_DIFFICULTY};
+    use storage::MemoryStorage;
+
+    fn mine(mut block: Block) -> Block {
+        while !meets_target(&block.hash(), block.header.difficulty) {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    fn chain(length: u64) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for index in 0..length {
+            let prev = match blocks.last() {
+                Some(block) => block.hash(),
+                None        => "0".repeat(64),
+            };
+            let entries = vec![NewBlockContentPayload {
+                unique_key: format!("key_{}", index),
+                content: format!("content {}", index),
+            }];
+            blocks.push(mine(Block::new(index, prev, entries)));
+        }
+        blocks
+    }
+
+    fn store(blocks: Vec<Block>) -> MemoryStorage {
+        let mut storage = MemoryStorage::new();
+        for block in blocks {
+            storage.append(&block.hash(), &block.to_bytes()).unwrap();
+        }
+        storage
+    }
+
+    #[test]
+    fn test_valid_chain() {
+        let storage = store(chain(3));
+        assert_eq!(3, verify_chain(&storage, &Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap());
+        assert_eq!(0, verify_chain(&MemoryStorage::new(), &Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap());
+    }
+
+    #[test]
+    fn test_changed_entries() {
+        let mut blocks = chain(3);
+        let hash = blocks[1].hash();
+        blocks[1].entries[0].content = String::from("changed");
+
+        let mut storage = MemoryStorage::new();
+        for (index, block) in blocks.into_iter().enumerate() {
+            let block_hash = if index == 1 { hash.clone() } else { block.hash() };
+            storage.append(&block_hash, &block.to_bytes()).unwrap();
+        }
+
+        let error = verify_chain(&storage, &Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap_err();
+        let error = error.downcast::<InvalidChain>().unwrap();
+        assert_eq!(1, error.height);
+        assert_eq!(InvalidBlock::MerkleRoot, error.reason);
+    }
+
+    #[test]
+    fn test_missing_block() {
+        let mut blocks = chain(3);
+        blocks.remove(1);
+
+        let error = verify_chain(&store(blocks), &Retarget::default(), DEFAULT_MAX_TIME_DRIFT).unwrap_err();
+        let error = error.downcast::<InvalidChain>().unwrap();
+        assert_eq!(1, error.height);
+        assert_eq!(InvalidBlock::Index { expected: 1, actual: 2 }, error.reason);
+    }
+
+    #[test]
+    fn test_validate_block() {
+        let latest = latest_timestamp(DEFAULT_MAX_TIME_DRIFT);
+        let blocks = chain(2);
+        let hash = blocks[1].hash();
+        assert_eq!(Ok(()), validate_block(Some(&blocks[0].header), DEFAULT_DIFFICULTY, latest, &hash, &blocks[1]));
+
+        let mut changed = blocks[1].clone();
+        changed.header.nonce += 1;
+        match validate_block(Some(&blocks[0].header), DEFAULT_DIFFICULTY, latest, &hash, &changed) {
+            Err(InvalidBlock::Hash { .. }) => (),
+            result                         => panic!("Unexpected result {:?}", result),
+        };
+
+        let mut early = blocks[1].clone();
+        early.header.timestamp = blocks[0].header.timestamp - 1;
+        let early = mine(early);
+        match validate_block(Some(&blocks[0].header), DEFAULT_DIFFICULTY, latest, &early.hash(), &early) {
+            Err(InvalidBlock::Timestamp { .. }) => (),
+            result                              => panic!("Unexpected result {:?}", result),
+        };
+
+        let mut future = blocks[1].clone();
+        future.header.timestamp = latest + 1;
+        let future = mine(future);
+        match validate_block(Some(&blocks[0].header), DEFAULT_DIFFICULTY, latest, &future.hash(), &future) {
+            Err(InvalidBlock::Future { .. }) => (),
+            result                           => panic!("Unexpected result {:?}", result),
+        };
+
+        let mut easy = blocks[1].clone();
+        easy.header.difficulty = DEFAULT_DIFFICULTY - 1;
+        let easy = mine(easy);
+        match validate_block(Some(&blocks[0].header), DEFAULT_DIFFICULTY, latest, &easy.hash(), &easy) {
+            Err(InvalidBlock::Difficulty { .. }) => (),
+            result                               => panic!("Unexpected result {:?}", result),
+        };
+    }
+
+    #[test]
+    fn test_retarget() {
+        let retarget = Retarget::new(2, 60).unwrap();
+        let genesis = mine(Block::new(0, "0".repeat(64), Vec::new()));
+
+        // the second block arrives twice as fast, so the third needs one more bit
+        let mut second = Block::new(1, genesis.hash(), Vec::new());
+        second.header.timestamp = genesis.header.timestamp + 30;
+        let second = mine(second);
+
+        let mut third = Block::new(2, second.hash(), Vec::new());
+        third.header.timestamp = second.header.timestamp;
+        let third = mine(third);
+
+        let storage = store(vec![genesis.clone(), second.clone(), third]);
+        let error = verify_chain(&storage, &retarget, DEFAULT_MAX_TIME_DRIFT).unwrap_err();
+        let error = error.downcast::<InvalidChain>().unwrap();
+        assert_eq!(2, error.height);
+        assert_eq!(InvalidBlock::Difficulty { expected: DEFAULT_DIFFICULTY + 1, actual: DEFAULT_DIFFICULTY }, error.reason);
+
+        let mut third = Block::new(2, second.hash(), Vec::new());
+        third.header.timestamp = second.header.timestamp;
+        third.header.difficulty = DEFAULT_DIFFICULTY + 1;
+        let storage = store(vec![genesis, second, mine(third)]);
+        assert_eq!(3, verify_chain(&storage, &retarget, DEFAULT_MAX_TIME_DRIFT).unwrap());
+    }
+}
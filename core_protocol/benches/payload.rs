@@ -3,13 +3,22 @@ extern crate criterion;
 extern crate carina_core_protocol;
 extern crate sodiumoxide;
 
-use carina_core_protocol::{MessageBuilder, Nacl, Payload};
+use carina_core_protocol::{decrypt, decrypt_precomputed, MessageBuilder, Nacl, Payload};
 use carina_core_protocol::payloads::EmptyPayload;
 use carina_core_protocol::payloads::block::*;
 use criterion::Criterion;
 use sodiumoxide::crypto::box_;
 
-criterion_group!(benches, bench_payload_empty, bench_block_data, bench_calc_block);
+criterion_group!(
+    benches,
+    bench_payload_empty,
+    bench_block_data,
+    bench_calc_block,
+    bench_seal,
+    bench_seal_precomputed,
+    bench_open,
+    bench_open_precomputed
+);
 criterion_main!(benches);
 
 fn bench_payload_empty(c: &mut Criterion) {
@@ -53,4 +62,63 @@ fn bench_calc_block(c: &mut Criterion) {
             .set_payload(payload)
             .build(&mut nacl, &theirpk);
     }));
-}
\ No newline at end of file
+}
+
+fn bench_seal(c: &mut Criterion) {
+    let (_, oursk) = box_::gen_keypair();
+    let (theirpk, _) = box_::gen_keypair();
+    let mut nacl = Nacl::new(oursk);
+    let block = Block::new(1, "0".repeat(64), Vec::new());
+
+    c.bench_function("bench_seal", move |b| b.iter(|| {
+        MessageBuilder::new()
+            .set_event_code(68)
+            .set_payload(BlockFoundPayload { block: block.clone() })
+            .build(&mut nacl, &theirpk);
+    }));
+}
+
+fn bench_seal_precomputed(c: &mut Criterion) {
+    let (_, oursk) = box_::gen_keypair();
+    let (theirpk, _) = box_::gen_keypair();
+    let mut nacl = Nacl::new(oursk);
+    let key = nacl.precompute(&theirpk);
+    let block = Block::new(1, "0".repeat(64), Vec::new());
+
+    c.bench_function("bench_seal_precomputed", move |b| b.iter(|| {
+        MessageBuilder::new()
+            .set_event_code(68)
+            .set_payload(BlockFoundPayload { block: block.clone() })
+            .build_precomputed(&mut nacl, &key);
+    }));
+}
+
+fn bench_open(c: &mut Criterion) {
+    let (ourpk, oursk) = box_::gen_keypair();
+    let (theirpk, theirsk) = box_::gen_keypair();
+    let mut ours = Nacl::new(oursk);
+    let theirs = Nacl::new(theirsk);
+    let message = MessageBuilder::new()
+        .set_event_code(68)
+        .set_payload(BlockFoundPayload { block: Block::new(1, "0".repeat(64), Vec::new()) })
+        .build(&mut ours, &theirpk);
+
+    c.bench_function("bench_open", move |b| b.iter(|| {
+        decrypt(&message, &theirs, &ourpk).unwrap();
+    }));
+}
+
+fn bench_open_precomputed(c: &mut Criterion) {
+    let (ourpk, oursk) = box_::gen_keypair();
+    let (theirpk, theirsk) = box_::gen_keypair();
+    let mut ours = Nacl::new(oursk);
+    let key = Nacl::new(theirsk).precompute(&ourpk);
+    let message = MessageBuilder::new()
+        .set_event_code(68)
+        .set_payload(BlockFoundPayload { block: Block::new(1, "0".repeat(64), Vec::new()) })
+        .build(&mut ours, &theirpk);
+
+    c.bench_function("bench_open_precomputed", move |b| b.iter(|| {
+        decrypt_precomputed(&message, &key).unwrap();
+    }));
+}
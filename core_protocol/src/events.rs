@@ -5,10 +5,50 @@ pub enum Events {
     Ping,
     /// Event: 1
     Pong,
+    /// Event: 2
+    Handshake,
+    /// Event: 3
+    HandshakeAck,
+    /// Event: 4
+    Register,
+    /// Event: 5
+    RegisterAck,
+    /// Event: 6
+    GetPeers,
+    /// Event: 7
+    GetPeersAck,
     /// Event: 64
     NewBlockContent,
     /// Event: 65
     CalcBlock,
+    /// Event: 66
+    GetProof,
+    /// Event: 67
+    GetProofAck,
+    /// Event: 68
+    BlockFound,
+    /// Event: 69
+    BlockVote,
+    /// Event: 70
+    GetTip,
+    /// Event: 71
+    GetTipAck,
+    /// Event: 72
+    GetHeaders,
+    /// Event: 73
+    GetHeadersAck,
+    /// Event: 74
+    GetBlock,
+    /// Event: 75
+    GetBlockAck,
+    /// Event: 192
+    ///
+    /// Only fired by the local node, see `Events::is_local`
+    Reorg,
+    /// Event: 193
+    ///
+    /// Only fired by the local node, after the configuration was reloaded
+    Reload,
     /// An invalid event
     Invalid
 }
@@ -19,8 +59,26 @@ impl Events {
         match event {
             Events::Ping            => 0,
             Events::Pong            => 1,
+            Events::Handshake       => 2,
+            Events::HandshakeAck    => 3,
+            Events::Register        => 4,
+            Events::RegisterAck     => 5,
+            Events::GetPeers        => 6,
+            Events::GetPeersAck     => 7,
             Events::NewBlockContent => 64,
-            Events::CalcBlock       => 64,
+            Events::CalcBlock       => 65,
+            Events::GetProof        => 66,
+            Events::GetProofAck     => 67,
+            Events::BlockFound      => 68,
+            Events::BlockVote       => 69,
+            Events::GetTip          => 70,
+            Events::GetTipAck       => 71,
+            Events::GetHeaders      => 72,
+            Events::GetHeadersAck   => 73,
+            Events::GetBlock        => 74,
+            Events::GetBlockAck     => 75,
+            Events::Reorg           => 192,
+            Events::Reload          => 193,
             _                       => 255
         }
     }
@@ -28,11 +86,76 @@ impl Events {
     /// Converts the given value to the enum value
     pub fn as_enum(value: u8) -> Events {
         match value {
-            0  => Events::Ping,
-            1  => Events::Pong,
-            64 => Events::NewBlockContent,
-            65 => Events::CalcBlock,
-            _  => Events::Invalid
+            0   => Events::Ping,
+            1   => Events::Pong,
+            2   => Events::Handshake,
+            3   => Events::HandshakeAck,
+            4   => Events::Register,
+            5   => Events::RegisterAck,
+            6   => Events::GetPeers,
+            7   => Events::GetPeersAck,
+            64  => Events::NewBlockContent,
+            65  => Events::CalcBlock,
+            66  => Events::GetProof,
+            67  => Events::GetProofAck,
+            68  => Events::BlockFound,
+            69  => Events::BlockVote,
+            70  => Events::GetTip,
+            71  => Events::GetTipAck,
+            72  => Events::GetHeaders,
+            73  => Events::GetHeadersAck,
+            74  => Events::GetBlock,
+            75  => Events::GetBlockAck,
+            192 => Events::Reorg,
+            193 => Events::Reload,
+            _   => Events::Invalid
+        }
+    }
+
+    /// Events with a value of 192 and above are only created by the local
+    /// node and must be ignored when they come from the network
+    pub fn is_local(event: Events) -> bool {
+        match event {
+            Events::Reorg  => true,
+            Events::Reload => true,
+            _              => false
+        }
+    }
+
+    /// Events that establish a session between two peers
+    ///
+    /// They are handled by the core and never reach the event handlers.
+    pub fn is_handshake(event: Events) -> bool {
+        match event {
+            Events::Handshake    => true,
+            Events::HandshakeAck => true,
+            _                    => false
+        }
+    }
+
+    /// Events for learning about other peers
+    ///
+    /// They are handled by the core and never reach the event handlers.
+    pub fn is_discovery(event: Events) -> bool {
+        match event {
+            Events::Register    => true,
+            Events::RegisterAck => true,
+            Events::GetPeers    => true,
+            Events::GetPeersAck => true,
+            _                   => false
+        }
+    }
+
+    /// Events a client that is not a peer may send
+    ///
+    /// Clients introduce themselves with their public key and bind an
+    /// ephemeral port, the answer is sent back to that port.
+    pub fn is_client(event: Events) -> bool {
+        match event {
+            Events::Ping            => true,
+            Events::NewBlockContent => true,
+            Events::GetProof        => true,
+            _                       => false
         }
     }
 }
\ No newline at end of file
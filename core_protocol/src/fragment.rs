@@ -0,0 +1,536 @@
+//! Splits sealed messages into datagrams and puts them back together
+//!
+//! Every sealed message is sent as one or more fragments, even if it would
+//! fit into a single datagram. That way the receiver never has to guess
+//! whether a datagram is a fragment or a complete message.
+//!
+//! Messages sent with the kind `Reliable` must be acknowledged by the
+//! receiver with a single `Ack` fragment that carries the message id and no
+//! data.
+//!
+//! ```
+//! //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+//! // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+//! // | Magic                 | Kind                  | Message id (unsigned)                         |
+//! // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+//! // | Index (unsigned)                              | Count (unsigned)                              |
+//! // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+//! // //                                                                                             //
+//! // // Data                                                                                        //
+//! // //                                                                                             //
+//! // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+//! ```
+use failure::Error;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// First byte of every fragment
+pub const FRAGMENT_MAGIC: u8 = 0xCA;
+/// Number of bytes the fragment header uses
+pub const FRAGMENT_HEADER: usize = 10;
+/// Maximum size of a single datagram
+///
+/// Small enough to pass the usual MTU of 1500 bytes without being split by
+/// IP.
+pub const MAX_DATAGRAM: usize = 1200;
+/// Maximum number of data bytes in a single fragment
+pub const MAX_FRAGMENT_DATA: usize = MAX_DATAGRAM - FRAGMENT_HEADER;
+
+/// Kind of a fragment
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FragmentKind {
+    /// Part of a sealed message
+    Data,
+    /// Part of a sealed message that must be acknowledged
+    Reliable,
+    /// Acknowledges a complete reliable message
+    Ack,
+}
+
+impl FragmentKind {
+    /// Value of the kind on the wire
+    pub fn as_val(self) -> u8 {
+        match self {
+            FragmentKind::Data     => 0,
+            FragmentKind::Reliable => 1,
+            FragmentKind::Ack      => 2,
+        }
+    }
+
+    /// Kind for the given value
+    pub fn from_val(value: u8) -> Result<Self, Error> {
+        match value {
+            0 => Ok(FragmentKind::Data),
+            1 => Ok(FragmentKind::Reliable),
+            2 => Ok(FragmentKind::Ack),
+            _ => Err(format_err!("Unknown fragment kind {}", value)),
+        }
+    }
+}
+
+/// Single datagram of a message
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fragment {
+    /// kind of the fragment
+    pub kind: FragmentKind,
+    /// id of the message, chosen by the sender
+    pub message_id: u32,
+    /// position of the fragment in the message
+    pub index: u16,
+    /// number of fragments of the message
+    pub count: u16,
+    /// part of the message
+    pub data: Vec<u8>,
+}
+
+impl Fragment {
+    /// Parses a received datagram
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < FRAGMENT_HEADER || bytes[0] != FRAGMENT_MAGIC {
+            return Err(format_err!("Datagram is not a fragment"));
+        }
+
+        let fragment = Self {
+            kind: FragmentKind::from_val(bytes[1])?,
+            message_id: read_u32(&bytes[2..6]),
+            index: read_u16(&bytes[6..8]),
+            count: read_u16(&bytes[8..10]),
+            data: bytes[FRAGMENT_HEADER..].to_vec(),
+        };
+
+        if fragment.count == 0 || fragment.index >= fragment.count {
+            return Err(format_err!("Fragment {} of {} is out of range", fragment.index, fragment.count));
+        }
+        if fragment.data.len() > MAX_FRAGMENT_DATA {
+            return Err(format_err!("Fragment is too large"));
+        }
+        Ok(fragment)
+    }
+
+    /// Creates the acknowledgement for a reliable message
+    ///
+    /// Acks are not sealed, so they are not authenticated. Anybody who
+    /// sees the message id can forge one. A forged ack only stops sending
+    /// the message again, which an attacker on the path can achieve by
+    /// dropping the datagrams as well.
+    pub fn ack(message_id: u32) -> Self {
+        Self {
+            kind: FragmentKind::Ack,
+            message_id,
+            index: 0,
+            count: 1,
+            data: Vec::new(),
+        }
+    }
+
+    /// Creates the datagram of the fragment
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FRAGMENT_HEADER + self.data.len());
+        bytes.push(FRAGMENT_MAGIC);
+        bytes.push(self.kind.as_val());
+        write_le(&mut bytes, u64::from(self.message_id), 4);
+        write_le(&mut bytes, u64::from(self.index), 2);
+        write_le(&mut bytes, u64::from(self.count), 2);
+        bytes.extend(self.data.iter());
+        bytes
+    }
+}
+
+/// Splits a sealed message into datagrams
+///
+/// # Return
+/// - `Result<Vec<Vec<u8>>, Error>` -> datagrams in order or an error if the
+///   message needs more fragments than the header can count
+pub fn fragment(kind: FragmentKind, message_id: u32, message: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let chunks: Vec<&[u8]> = if message.is_empty() {
+        vec![message]
+    } else {
+        message.chunks(MAX_FRAGMENT_DATA).collect()
+    };
+
+    if chunks.len() > usize::from(u16::max_value()) {
+        return Err(format_err!("Message with {} bytes is too large", message.len()));
+    }
+
+    let count = chunks.len() as u16;
+    Ok(chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| Fragment {
+            kind,
+            message_id,
+            index: index as u16,
+            count,
+            data: data.to_vec(),
+        }.to_bytes())
+        .collect())
+}
+
+/// Message that is not complete yet
+#[derive(Debug)]
+struct Partial {
+    /// received fragments by their index
+    fragments: Vec<Option<Vec<u8>>>,
+    /// number of received fragments
+    received: usize,
+    /// bytes reserved for the message
+    reserved: usize,
+    /// time the first fragment was received
+    started: Instant,
+    /// order in which the messages were started
+    sequence: u64,
+}
+
+/// Bytes and messages an incomplete sender reserves
+#[derive(Debug, Default)]
+struct Usage {
+    /// bytes reserved by the incomplete messages of the sender
+    reserved: usize,
+    /// number of incomplete messages of the sender
+    partials: usize,
+}
+
+/// Collects fragments until their message is complete
+///
+/// Incomplete messages are dropped after a timeout. The memory used by
+/// incomplete messages is capped in total and for every sender, as is the
+/// number of incomplete messages of a sender. If a new message does not
+/// fit, the oldest incomplete messages of the sender are dropped. If all
+/// senders together use too much memory, the sender that uses the most
+/// loses its oldest messages, so a single sender can´t push out the
+/// messages of everyone else.
+#[derive(Debug)]
+pub struct Reassembler {
+    /// time a message may take to arrive completely
+    timeout: Duration,
+    /// maximum number of bytes reserved for incomplete messages
+    max_bytes: usize,
+    /// maximum number of bytes reserved for the incomplete messages of a single sender
+    max_source_bytes: usize,
+    /// maximum number of incomplete messages of a single sender
+    max_source_partials: usize,
+    /// bytes reserved by all incomplete messages
+    reserved: usize,
+    /// incomplete messages by their sender and id
+    partials: HashMap<(String, u32), Partial>,
+    /// reserved bytes and incomplete messages by their sender
+    usage: HashMap<String, Usage>,
+    /// sequence number of the next incomplete message
+    sequence: u64,
+}
+
+impl Reassembler {
+    /// Creates a new instance
+    ///
+    /// # Params
+    /// - `timeout` -> time a message may take to arrive completely
+    /// - `max_bytes` -> maximum number of bytes all incomplete messages may use
+    /// - `max_source_bytes` -> maximum number of bytes the incomplete messages of a single sender may use
+    /// - `max_source_partials` -> maximum number of incomplete messages of a single sender
+    pub fn new(timeout: Duration, max_bytes: usize, max_source_bytes: usize, max_source_partials: usize) -> Self {
+        Self {
+            timeout,
+            max_bytes,
+            max_source_bytes,
+            max_source_partials,
+            reserved: 0,
+            partials: HashMap::new(),
+            usage: HashMap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Number of incomplete messages
+    pub fn pending(&self) -> usize {
+        self.partials.len()
+    }
+
+    /// Adds a fragment
+    ///
+    /// # Return
+    /// - `Option<Vec<u8>>` -> the message, if this was the last missing
+    ///   fragment
+    pub fn add(&mut self, source: &str, fragment: Fragment) -> Option<Vec<u8>> {
+        if fragment.count == 1 {
+            return Some(fragment.data);
+        }
+
+        let key = (source.to_string(), fragment.message_id);
+        if !self.partials.contains_key(&key) {
+            let reserved = usize::from(fragment.count) * MAX_FRAGMENT_DATA;
+            if reserved > self.max_bytes || reserved > self.max_source_bytes || self.max_source_partials == 0 {
+                return None;
+            }
+            while self.exceeds_source_limits(source, reserved) {
+                self.drop_oldest(source);
+            }
+            while self.reserved + reserved > self.max_bytes {
+                match self.largest_source() {
+                    Some(largest) => self.drop_oldest(&largest),
+                    // nothing left to drop, the counter can´t be off
+                    None          => self.reserved = 0,
+                };
+            }
+
+            self.reserved += reserved;
+            {
+                let usage = self.usage.entry(source.to_string()).or_insert_with(Usage::default);
+                usage.reserved += reserved;
+                usage.partials += 1;
+            }
+            self.partials.insert(key.clone(), Partial {
+                fragments: vec![None; usize::from(fragment.count)],
+                received: 0,
+                reserved,
+                started: Instant::now(),
+                sequence: self.sequence,
+            });
+            self.sequence += 1;
+        }
+
+        let complete = match self.partials.get_mut(&key) {
+            Some(partial) => {
+                let index = usize::from(fragment.index);
+                if partial.fragments.len() != usize::from(fragment.count) {
+                    return None;
+                }
+                if partial.fragments[index].is_none() {
+                    partial.fragments[index] = Some(fragment.data);
+                    partial.received += 1;
+                }
+                partial.received == partial.fragments.len()
+            },
+            None          => return None,
+        };
+
+        if !complete {
+            return None;
+        }
+
+        self.remove(&key).map(|partial| {
+            partial.fragments
+                .into_iter()
+                .flat_map(|data| data.unwrap_or_default())
+                .collect()
+        })
+    }
+
+    /// Drops all messages that did not arrive in time
+    pub fn expire(&mut self) {
+        let timeout = self.timeout;
+        let expired: Vec<(String, u32)> = self.partials
+            .iter()
+            .filter(|(_, partial)| partial.started.elapsed() >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in expired {
+            self.remove(&key);
+        }
+    }
+
+    /// true if a new message with the given size would exceed the limits of the sender
+    fn exceeds_source_limits(&self, source: &str, reserved: usize) -> bool {
+        match self.usage.get(source) {
+            Some(usage) => usage.reserved + reserved > self.max_source_bytes || usage.partials >= self.max_source_partials,
+            None        => false,
+        }
+    }
+
+    /// Sender that reserves the most bytes
+    fn largest_source(&self) -> Option<String> {
+        self.usage
+            .iter()
+            .max_by_key(|(_, usage)| usage.reserved)
+            .map(|(source, _)| source.clone())
+    }
+
+    /// Drops the oldest incomplete message of the given sender
+    fn drop_oldest(&mut self, source: &str) {
+        let oldest = self.partials
+            .iter()
+            .filter(|((sender, _), _)| sender == source)
+            .min_by_key(|(_, partial)| partial.sequence)
+            .map(|(key, _)| key.clone());
+
+        match oldest {
+            Some(key) => {
+                self.remove(&key);
+            },
+            // nothing left to drop, the usage can´t be off
+            None      => {
+                if let Some(usage) = self.usage.remove(source) {
+                    self.reserved -= usage.reserved;
+                }
+            },
+        };
+    }
+
+    /// Removes an incomplete message and releases its memory
+    fn remove(&mut self, key: &(String, u32)) -> Option<Partial> {
+        let partial = self.partials.remove(key)?;
+        self.reserved -= partial.reserved;
+
+        let empty = match self.usage.get_mut(&key.0) {
+            Some(usage) => {
+                usage.reserved -= partial.reserved;
+                usage.partials -= 1;
+                usage.partials == 0
+            },
+            None        => false,
+        };
+        if empty {
+            self.usage.remove(&key.0);
+        }
+        Some(partial)
+    }
+}
+
+/// Appends the lowest `len` bytes of the value in little endian order
+fn write_le(bytes: &mut Vec<u8>, value: u64, len: usize) {
+    for i in 0..len {
+        bytes.push(((value >> (i * 8)) & 0xFF) as u8);
+    }
+}
+
+/// Reads a little endian u16
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from(bytes[0]) | u16::from(bytes[1]) << 8
+}
+
+/// Reads a little endian u32
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .enumerate()
+        .fold(0, |value, (i, byte)| value | u32::from(*byte) << (i * 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(datagrams: Vec<Vec<u8>>) -> Vec<Fragment> {
+        datagrams
+            .iter()
+            .map(|datagram| Fragment::from_bytes(datagram).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_fragment_and_reassemble() {
+        let message: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let mut fragments = parse(fragment(FragmentKind::Data, 7, &message).unwrap());
+        assert_eq!(5, fragments.len());
+        assert!(fragments.iter().all(|fragment| fragment.to_bytes().len() <= MAX_DATAGRAM));
+
+        let mut reassembler = Reassembler::new(Duration::from_secs(10), 1 << 20, 1 << 20, 8);
+        fragments.reverse();
+        let last = fragments.pop().unwrap();
+        for fragment in fragments.clone() {
+            assert_eq!(None, reassembler.add("peer", fragment));
+        }
+        // duplicates don´t complete the message
+        assert_eq!(None, reassembler.add("peer", fragments[0].clone()));
+        assert_eq!(Some(message), reassembler.add("peer", last));
+        assert_eq!(0, reassembler.pending());
+    }
+
+    #[test]
+    fn test_single_fragment() {
+        let fragments = parse(fragment(FragmentKind::Reliable, 1, &[1, 2, 3]).unwrap());
+        let mut reassembler = Reassembler::new(Duration::from_secs(10), 0, 0, 0);
+
+        assert_eq!(Some(vec![1, 2, 3]), reassembler.add("peer", fragments[0].clone()));
+    }
+
+    #[test]
+    fn test_invalid_fragment() {
+        assert!(Fragment::from_bytes(&[FRAGMENT_MAGIC, 0, 0, 0]).is_err());
+        assert!(Fragment::from_bytes(&[0; FRAGMENT_HEADER]).is_err());
+        assert!(Fragment::from_bytes(&[FRAGMENT_MAGIC, 0, 0, 0, 0, 0, 2, 0, 2, 0]).is_err());
+        assert!(Fragment::from_bytes(&[FRAGMENT_MAGIC, 9, 0, 0, 0, 0, 0, 0, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn test_ack() {
+        let ack = Fragment::ack(42);
+        assert_eq!(ack, Fragment::from_bytes(&ack.to_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_memory_cap() {
+        let message = vec![0; MAX_FRAGMENT_DATA * 2];
+        let first = parse(fragment(FragmentKind::Data, 1, &message).unwrap());
+        let second = parse(fragment(FragmentKind::Data, 2, &message).unwrap());
+        let mut reassembler = Reassembler::new(Duration::from_secs(10), MAX_FRAGMENT_DATA * 3, MAX_FRAGMENT_DATA * 3, 8);
+
+        assert_eq!(None, reassembler.add("peer", first[0].clone()));
+        assert_eq!(None, reassembler.add("peer", second[0].clone()));
+        assert_eq!(1, reassembler.pending());
+
+        // the first message was dropped to make room for the second
+        assert_eq!(Some(message), reassembler.add("peer", second[1].clone()));
+        assert_eq!(None, reassembler.add("peer", first[1].clone()));
+        assert_eq!(1, reassembler.pending());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let fragments = parse(fragment(FragmentKind::Data, 1, &vec![0; MAX_FRAGMENT_DATA * 2]).unwrap());
+        let mut reassembler = Reassembler::new(Duration::from_secs(0), 1 << 20, 1 << 20, 8);
+
+        assert_eq!(None, reassembler.add("peer", fragments[0].clone()));
+        reassembler.expire();
+        assert_eq!(0, reassembler.pending());
+        assert_eq!(None, reassembler.add("peer", fragments[1].clone()));
+    }
+
+    #[test]
+    fn test_source_limits() {
+        let message = vec![0; MAX_FRAGMENT_DATA * 2];
+        let mut reassembler = Reassembler::new(Duration::from_secs(10), MAX_FRAGMENT_DATA * 8, MAX_FRAGMENT_DATA * 4, 2);
+
+        // the other peer starts a message before the flood
+        let other = parse(fragment(FragmentKind::Data, 1, &message).unwrap());
+        assert_eq!(None, reassembler.add("other", other[0].clone()));
+
+        // the flooding peer never completes its messages
+        for message_id in 0..100 {
+            let flood = parse(fragment(FragmentKind::Data, message_id, &message).unwrap());
+            assert_eq!(None, reassembler.add("flood", flood[0].clone()));
+        }
+        assert_eq!(3, reassembler.pending());
+
+        // only the messages of the flooding peer were dropped
+        assert_eq!(Some(message.clone()), reassembler.add("other", other[1].clone()));
+        assert_eq!(2, reassembler.pending());
+
+        // the oldest messages of the flooding peer were dropped first
+        let flood = parse(fragment(FragmentKind::Data, 99, &message).unwrap());
+        assert_eq!(Some(message.clone()), reassembler.add("flood", flood[1].clone()));
+        let flood = parse(fragment(FragmentKind::Data, 0, &message).unwrap());
+        assert_eq!(None, reassembler.add("flood", flood[1].clone()));
+    }
+
+    #[test]
+    fn test_memory_cap_drops_largest_source() {
+        let message = vec![0; MAX_FRAGMENT_DATA * 2];
+        let mut reassembler = Reassembler::new(Duration::from_secs(10), MAX_FRAGMENT_DATA * 6, MAX_FRAGMENT_DATA * 4, 2);
+
+        let other = parse(fragment(FragmentKind::Data, 1, &message).unwrap());
+        assert_eq!(None, reassembler.add("other", other[0].clone()));
+        for message_id in 0..2 {
+            let flood = parse(fragment(FragmentKind::Data, message_id, &message).unwrap());
+            assert_eq!(None, reassembler.add("flood", flood[0].clone()));
+        }
+
+        // a third sender needs room, the sender with the most bytes loses a message
+        let third = parse(fragment(FragmentKind::Data, 1, &message).unwrap());
+        assert_eq!(None, reassembler.add("third", third[0].clone()));
+        assert_eq!(3, reassembler.pending());
+
+        assert_eq!(Some(message.clone()), reassembler.add("other", other[1].clone()));
+        assert_eq!(Some(message), reassembler.add("third", third[1].clone()));
+    }
+}
@@ -0,0 +1,51 @@
+//! Helper for hashing and encoding hashes
+use sodiumoxide::crypto::hash::sha256;
+
+/// Calculates the sha256 of the given bytes
+pub(crate) fn sha256(bytes: &[u8]) -> [u8; 32] {
+    sha256::hash(bytes).0
+}
+
+/// Converts the given bytes to a lower case hex string
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+/// Converts the given hex string to a 32 byte hash
+///
+/// Returns `None` if the string is not a valid hash
+pub(crate) fn from_hex(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+
+    let bytes = match decode_hex(hex) {
+        Some(bytes) => bytes,
+        None        => return None,
+    };
+    let mut result = [0; 32];
+    result.copy_from_slice(&bytes);
+    Some(result)
+}
+
+/// Converts the given hex string to bytes
+///
+/// Returns `None` if the string is not valid hex
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut result = Vec::with_capacity(hex.len() / 2);
+    for i in 0..hex.len() / 2 {
+        match u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16) {
+            Ok(val) => result.push(val),
+            Err(_)  => return None,
+        };
+    }
+    Some(result)
+}
@@ -22,7 +22,7 @@
 //! extern crate carina_core_protocol;
 //! extern crate sodiumoxide;
 //! 
-//! use carina_core_protocol::{Nacl, Payload, MessageBuilder};
+//! use carina_core_protocol::{fragment, FragmentKind, Nacl, Payload, MessageBuilder};
 //! use carina_core_protocol::payloads::EmptyPayload;
 //! use sodiumoxide::crypto::box_;
 //! use std::net::UdpSocket;
@@ -46,8 +46,10 @@
 //! 
 //!     // create a new udp socket
 //!     let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
-//!     // send the message
-//!     socket.send_to(&message, "0.0.0.0:45000").unwrap();
+//!     // send the message, split into datagrams that fit the MTU
+//!     for datagram in fragment(FragmentKind::Data, 1, &message).unwrap() {
+//!         socket.send_to(&datagram, "0.0.0.0:45000").unwrap();
+//!     }
 //! }
 //! ```
 
@@ -63,15 +65,22 @@ extern crate rand;
 extern crate time;
 
 mod events;
+mod fragment;
+mod hash;
 mod nacl;
 mod receive_message;
+mod replay;
 mod send_message_builder;
 
 /// Module that contains all avaiable payloads
 pub mod payloads;
+pub mod merkle;
 /// Contains helper for events
 pub use self::events::Events;
+pub use self::fragment::{fragment, Fragment, FragmentKind, Reassembler, FRAGMENT_HEADER, MAX_DATAGRAM, MAX_FRAGMENT_DATA};
+pub use self::merkle::verify_proof;
 pub use self::payloads::Payload;
 pub use self::nacl::Nacl;
-pub use self::receive_message::decrypt;
+pub use self::receive_message::{decrypt, decrypt_introduction, decrypt_precomputed};
+pub use self::replay::{Replay, ReplayWindow, REPLAY_WINDOW};
 pub use self::send_message_builder::MessageBuilder;
\ No newline at end of file
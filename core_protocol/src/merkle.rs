@@ -0,0 +1,211 @@
+//! Merkle tree over the entries of a block
+//!
+//! Leaves and inner nodes are hashed with different prefixes so that an
+//! inner node can never be passed off as an entry.
+//! If a level has an odd number of nodes, the last node is moved up to the
+//! next level unchanged.
+use hash::{from_hex, sha256, to_hex};
+use payloads::block::{BlockHeader, NewBlockContentPayload};
+
+/// Prefix for hashing a leaf
+const LEAF_PREFIX: u8 = 0;
+/// Prefix for hashing an inner node
+const NODE_PREFIX: u8 = 1;
+
+/// Hash of a single block entry
+pub fn entry_hash(entry: &NewBlockContentPayload) -> [u8; 32] {
+    let unique_key = entry.unique_key.as_bytes();
+    let content = entry.content.as_bytes();
+
+    let mut bytes = Vec::with_capacity(2 + unique_key.len() + content.len());
+    bytes.push(LEAF_PREFIX);
+    bytes.push(unique_key.len() as u8);
+    bytes.extend(unique_key);
+    bytes.extend(content);
+    sha256(&bytes)
+}
+
+/// Hash of an inner node
+pub(crate) fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = Vec::with_capacity(65);
+    bytes.push(NODE_PREFIX);
+    bytes.extend(left.iter());
+    bytes.extend(right.iter());
+    sha256(&bytes)
+}
+
+/// Calculates the merkle root of the given entries as hex string
+///
+/// The entries must already be in canonical order.
+/// The root of a block without entries is the hash of an empty input.
+pub fn merkle_root(entries: &[NewBlockContentPayload]) -> String {
+    let mut level: Vec<[u8; 32]> = entries.iter().map(entry_hash).collect();
+
+    if level.is_empty() {
+        return to_hex(&sha256(&[]));
+    }
+
+    while level.len() > 1 {
+        let mut next = Vec::with_capacity((level.len() + 1) / 2);
+        for pair in level.chunks(2) {
+            if pair.len() == 2 {
+                next.push(node_hash(&pair[0], &pair[1]));
+            } else {
+                next.push(pair[0]);
+            }
+        }
+        level = next;
+    }
+
+    to_hex(&level[0])
+}
+
+/// A single step on the way from an entry to the merkle root
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProofStep {
+    /// Hash of the sibling node as hex string
+    pub hash: String,
+    /// true if the sibling is the left node
+    pub is_left: bool,
+}
+
+/// Proof that an entry is part of a block
+///
+/// Contains all siblings from the entry up to the root.
+/// Levels where the node has no sibling are skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MerkleProof {
+    /// Siblings ordered from the leaf to the root
+    pub steps: Vec<ProofStep>,
+}
+
+/// Creates the proof for the entry at the given position
+///
+/// The entries must already be in canonical order.
+/// Returns `None` if there is no entry at the position.
+pub fn merkle_proof(entries: &[NewBlockContentPayload], position: usize) -> Option<MerkleProof> {
+    if position >= entries.len() {
+        return None;
+    }
+
+    let mut level: Vec<[u8; 32]> = entries.iter().map(entry_hash).collect();
+    let mut position = position;
+    let mut steps = Vec::new();
+
+    while level.len() > 1 {
+        if position % 2 == 1 {
+            steps.push(ProofStep { hash: to_hex(&level[position - 1]), is_left: true });
+        } else if position + 1 < level.len() {
+            steps.push(ProofStep { hash: to_hex(&level[position + 1]), is_left: false });
+        }
+
+        let mut next = Vec::with_capacity((level.len() + 1) / 2);
+        for pair in level.chunks(2) {
+            if pair.len() == 2 {
+                next.push(node_hash(&pair[0], &pair[1]));
+            } else {
+                next.push(pair[0]);
+            }
+        }
+        level = next;
+        position /= 2;
+    }
+
+    Some(MerkleProof { steps })
+}
+
+/// Verifies that the given entry is part of the block with the given header
+///
+/// Does not need anything besides the header, so it can be used without
+/// access to the block or a node.
+pub fn verify_proof(header: &BlockHeader, entry: &NewBlockContentPayload, proof: &MerkleProof) -> bool {
+    let root = match from_hex(&header.merkle_root) {
+        Some(root) => root,
+        None       => return false,
+    };
+
+    let mut current = entry_hash(entry);
+    for step in &proof.steps {
+        let sibling = match from_hex(&step.hash) {
+            Some(sibling) => sibling,
+            None          => return false,
+        };
+
+        current = if step.is_left {
+            node_hash(&sibling, &current)
+        } else {
+            node_hash(&current, &sibling)
+        };
+    }
+
+    current == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(unique_key: &str, content: &str) -> NewBlockContentPayload {
+        NewBlockContentPayload {
+            unique_key: unique_key.to_string(),
+            content: content.to_string()
+        }
+    }
+
+    #[test]
+    fn test_empty_root() {
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            merkle_root(&[])
+        );
+    }
+
+    #[test]
+    fn test_single_entry() {
+        let entries = vec![entry("a", "content")];
+        assert_eq!(to_hex(&entry_hash(&entries[0])), merkle_root(&entries));
+    }
+
+    #[test]
+    fn test_odd_entries() {
+        let entries = vec![entry("a", "1"), entry("b", "2"), entry("c", "3")];
+
+        let left = node_hash(&entry_hash(&entries[0]), &entry_hash(&entries[1]));
+        let expected = node_hash(&left, &entry_hash(&entries[2]));
+        assert_eq!(to_hex(&expected), merkle_root(&entries));
+    }
+
+    #[test]
+    fn test_proofs() {
+        for count in 1..12 {
+            let entries: Vec<NewBlockContentPayload> = (0..count)
+                .map(|i| entry(&format!("key_{:02}", i), &format!("content {}", i)))
+                .collect();
+            let header = BlockHeader {
+                index: 1,
+                timestamp: 0,
+                prev: "0".repeat(64),
+                merkle_root: merkle_root(&entries),
+                difficulty: 0,
+                nonce: 0,
+            };
+
+            for position in 0..count {
+                let proof = merkle_proof(&entries, position).unwrap();
+                assert!(verify_proof(&header, &entries[position], &proof));
+
+                let forged = entry(&entries[position].unique_key, "forged");
+                assert!(!verify_proof(&header, &forged, &proof));
+            }
+
+            assert_eq!(None, merkle_proof(&entries, count));
+        }
+    }
+
+    #[test]
+    fn test_content_changes_root() {
+        let entries = vec![entry("a", "1"), entry("b", "2")];
+        let changed = vec![entry("a", "1"), entry("b", "3")];
+        assert_ne!(merkle_root(&entries), merkle_root(&changed));
+    }
+}
@@ -1,24 +1,42 @@
+use failure::Error;
+use hash::sha256;
+use sodiumoxide::crypto::auth;
 use sodiumoxide::crypto::box_;
-use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, SecretKey};
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, PrecomputedKey, PublicKey, SecretKey};
+use sodiumoxide::crypto::scalarmult::{scalarmult_base, Scalar};
+use sodiumoxide::crypto::sign;
+use sodiumoxide::randombytes::randombytes;
+use std::fs::{rename, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
-/// Struct that holds the given secret key and the current nonce
+/// Number of nonces that are reserved in the nonce file at once
+const NONCE_RESERVATION: u64 = 1 << 20;
+
+/// Struct that holds the given secret key and the nonce source
 ///
 /// Used to encrypt the message
 /// The secret key can only be obtained by this crate
 ///
-/// When creating a new instance of this struct, a random nonce
-/// is generated
-/// When a new message is constructed the nonce incremented and returned.
-/// That way there is a different nonce for each message and two peers
-/// won´t start with the same nonce
-/// 
+/// A nonce consists of a counter in the first 8 bytes (little endian) and
+/// a random prefix in the remaining 16 bytes. The prefix is generated once
+/// per process. All clones of an instance share the same counter, so no
+/// matter how often the struct is cloned, every nonce is only used once.
+///
+/// Optionally the counter is persisted in a nonce file. The counter is
+/// reserved in blocks and a restarted process continues after the last
+/// reserved block, so even a broken random generator can´t produce a nonce
+/// twice.
+///
 /// ```
 /// extern crate carina_core_protocol;
 /// extern crate sodiumoxide;
-/// 
+///
 /// use carina_core_protocol::Nacl;
 /// use sodiumoxide::crypto::box_;
-/// 
+///
 /// fn main() {
 ///     let (_, secret_key) = box_::gen_keypair();
 ///     let mut nacl = Nacl::new(secret_key);
@@ -27,19 +45,108 @@ use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, SecretKey};
 #[derive(Clone, Debug)]
 pub struct Nacl {
     secret_key: SecretKey,
-    nonce: Nonce,
+    nonces: Arc<NonceSource>,
+}
+
+/// Hands out unique nonces
+#[derive(Debug)]
+struct NonceSource {
+    /// random part of every nonce
+    prefix: [u8; 16],
+    /// counter of the next nonce
+    counter: AtomicU64,
+    /// file the reserved counters are written to
+    file: Option<PathBuf>,
+    /// first counter that is not reserved in the file
+    reserved: Mutex<u64>,
+}
+
+impl NonceSource {
+    /// Creates a source with a new random prefix
+    fn new(start: u64, file: Option<PathBuf>) -> Self {
+        let mut prefix = [0; 16];
+        prefix.copy_from_slice(&randombytes(16));
+
+        Self {
+            prefix,
+            counter: AtomicU64::new(start),
+            file,
+            reserved: Mutex::new(start),
+        }
+    }
+
+    /// Returns the next nonce
+    fn next(&self) -> Nonce {
+        let counter = self.counter.fetch_add(1, Ordering::SeqCst);
+        if counter == u64::max_value() {
+            panic!("Nonce counter is exhausted");
+        }
+        self.reserve(counter);
+
+        let mut nonce = [0; 24];
+        for (i, byte) in nonce.iter_mut().take(8).enumerate() {
+            *byte = ((counter >> (i * 8)) & 0xFF) as u8;
+        }
+        nonce[8..].copy_from_slice(&self.prefix);
+        Nonce(nonce)
+    }
+
+    /// Makes sure the counter is reserved in the nonce file
+    ///
+    /// Panics if the file can´t be written, because sending with a nonce
+    /// that isn´t persisted could reuse it after a restart.
+    fn reserve(&self, counter: u64) {
+        let path = match self.file {
+            Some(ref path) => path,
+            None           => return,
+        };
+
+        let mut reserved = self.reserved.lock().unwrap();
+        if counter < *reserved {
+            return;
+        }
+
+        let next = counter.saturating_add(NONCE_RESERVATION);
+        if let Err(e) = write_nonce_file(path, next) {
+            panic!("Error writing nonce file {:?}. {}", path, e);
+        }
+        *reserved = next;
+    }
 }
 
 impl Nacl {
     /// Creates a new instance with the given secret key
     pub fn new(secret_key: SecretKey) -> Self {
-        let nonce = box_::gen_nonce();
-        Self { secret_key, nonce }
+        Self {
+            secret_key,
+            nonces: Arc::new(NonceSource::new(0, None)),
+        }
+    }
+
+    /// Creates a new instance that persists the nonce counter in the given
+    /// file
+    ///
+    /// If the file exists, the counter continues after the highest value
+    /// reserved in it.
+    pub fn with_nonce_file<P: AsRef<Path>>(secret_key: SecretKey, path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let start = if path.exists() {
+            let mut content = String::new();
+            File::open(&path)?.read_to_string(&mut content)?;
+            content.trim().parse::<u64>()?
+        } else {
+            0
+        };
+
+        Ok(Self {
+            secret_key,
+            nonces: Arc::new(NonceSource::new(start, Some(path))),
+        })
     }
 
-    /// Increments the nonce and returns its new value
+    /// Returns a nonce that was never returned before
     pub fn get_nonce(&mut self) -> Nonce {
-        self.nonce.increment_le()
+        self.nonces.next()
     }
 
     /// Gets the secret key
@@ -48,13 +155,152 @@ impl Nacl {
     pub(crate) fn get_secret_key(&self) -> SecretKey {
         self.secret_key.clone()
     }
+
+    /// Long-term public key belonging to the secret key
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(scalarmult_base(&Scalar(self.secret_key.0)).0)
+    }
+
+    /// Precomputes the key shared with the given peer
+    ///
+    /// Sealing and opening with the precomputed key skips the expensive
+    /// Curve25519 multiplication, so the result should be cached per peer.
+    pub fn precompute(&self, public_key: &PublicKey) -> PrecomputedKey {
+        box_::precompute(public_key, &self.secret_key)
+    }
+
+    /// Public key used for verifying signatures of this peer
+    ///
+    /// The signing keys are derived from the secret key, so they don´t
+    /// need to be configured separately. The secret key is hashed with a
+    /// label first, so the same key is never used by two algorithms.
+    pub fn sign_public_key(&self) -> sign::PublicKey {
+        self.sign_keypair().0
+    }
+
+    /// Signs the given message
+    pub fn sign(&self, message: &[u8]) -> sign::Signature {
+        sign::sign_detached(message, &self.sign_keypair().1)
+    }
+
+    /// Creates a cookie for the given data
+    ///
+    /// Only this peer can create and verify the cookie, so a peer that sends
+    /// it back must have received it.
+    pub fn cookie(&self, data: &[u8]) -> [u8; 32] {
+        auth::authenticate(data, &self.cookie_key()).0
+    }
+
+    /// true if the cookie was created by `cookie` for the given data
+    pub fn verify_cookie(&self, cookie: &[u8; 32], data: &[u8]) -> bool {
+        auth::verify(&auth::Tag(*cookie), data, &self.cookie_key())
+    }
+
+    /// Derives the key of the cookies from the secret key
+    fn cookie_key(&self) -> auth::Key {
+        let mut seed = b"carina-cookie".to_vec();
+        seed.extend_from_slice(&self.secret_key.0);
+        auth::Key(sha256(&seed))
+    }
+
+    /// Derives the signing key pair from the secret key
+    fn sign_keypair(&self) -> (sign::PublicKey, sign::SecretKey) {
+        let mut seed = b"carina-sign".to_vec();
+        seed.extend_from_slice(&self.secret_key.0);
+        sign::keypair_from_seed(&sign::Seed(sha256(&seed)))
+    }
 }
 
 impl Default for Nacl {
     fn default() -> Self {
         let (_, secret_key) = box_::gen_keypair();
-        let nonce = box_::gen_nonce();
+        Nacl::new(secret_key)
+    }
+}
+
+/// Writes the counter to a temporary file and moves it over the nonce file
+fn write_nonce_file(path: &Path, counter: u64) -> Result<(), Error> {
+    let mut temp = path.as_os_str().to_os_string();
+    temp.push(".tmp");
+
+    {
+        let mut file = File::create(&temp)?;
+        file.write_all(counter.to_string().as_bytes())?;
+        file.sync_all()?;
+    }
+    rename(&temp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::{decode_hex, to_hex};
+    use std::collections::HashSet;
+    use std::env::temp_dir;
+    use std::fs::remove_file;
+
+    #[test]
+    fn test_clones_share_nonces() {
+        let mut nacl = Nacl::default();
+        let mut clone = nacl.clone();
+
+        let mut nonces = HashSet::new();
+        for _ in 0..100 {
+            assert!(nonces.insert(nacl.get_nonce().0));
+            assert!(nonces.insert(clone.get_nonce().0));
+        }
+    }
+
+    #[test]
+    fn test_processes_use_different_prefixes() {
+        let (_, secret_key) = box_::gen_keypair();
+        let mut first = Nacl::new(secret_key.clone());
+        let mut second = Nacl::new(secret_key);
+
+        assert_ne!(first.get_nonce().0, second.get_nonce().0);
+    }
+
+    #[test]
+    fn test_nonce_file() {
+        let path = temp_dir().join(format!("carina_nonce_{}", to_hex(&randombytes(8))));
+        let (_, secret_key) = box_::gen_keypair();
+
+        let mut nacl = Nacl::with_nonce_file(secret_key.clone(), &path).unwrap();
+        assert_eq!(0, nacl.get_nonce().0[0]);
+        nacl.get_nonce();
+
+        // a restart continues after the reserved block
+        let mut restarted = Nacl::with_nonce_file(secret_key, &path).unwrap();
+        let nonce = restarted.get_nonce().0;
+        let mut counter = 0u64;
+        for (i, byte) in nonce.iter().take(8).enumerate() {
+            counter |= u64::from(*byte) << (i * 8);
+        }
+        assert_eq!(NONCE_RESERVATION, counter);
+
+        remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cookie() {
+        let nacl = Nacl::default();
+        let cookie = nacl.cookie(b"127.0.0.1:45000");
+
+        assert!(nacl.verify_cookie(&cookie, b"127.0.0.1:45000"));
+        assert!(!nacl.verify_cookie(&cookie, b"127.0.0.1:45001"));
+        assert!(!Nacl::default().verify_cookie(&cookie, b"127.0.0.1:45000"));
+    }
+
+    #[test]
+    fn test_sign_key_is_derived() {
+        let secret_key = decode_hex("bfeac44f1e15b5cccafd04af97d3817c97e054f11d8cda6ab9552affc185996a").unwrap();
+        let nacl = Nacl::new(SecretKey::from_slice(&secret_key).unwrap());
+        let sign_key = nacl.sign_public_key();
 
-        Self { secret_key, nonce }
+        assert_eq!("2633a68a71e41c9a19ed824485c27b4b7817cd74ce64ca7634fa3254b9427886", to_hex(&sign_key.0));
+        // the secret key itself is not used as seed
+        assert_ne!(sign::keypair_from_seed(&sign::Seed(nacl.secret_key.0)).0, sign_key);
+        assert!(sign::verify_detached(&nacl.sign(b"block"), b"block", &sign_key));
     }
 }
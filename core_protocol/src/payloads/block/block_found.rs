@@ -0,0 +1,64 @@
+use failure::Error;
+use payloads::block::Block;
+use payloads::Payload;
+
+/// Model for the event `BlockFound`
+///
+/// Send to all peers after a block was mined. Contains the complete block,
+/// so that every peer can validate and store it.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // |                                                                                               |
+/// // //                                                                                             //
+/// // // Block                                                                                       //
+/// // //                                                                                             //
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockFoundPayload {
+    /// The mined block including its nonce
+    pub block: Block,
+}
+
+impl Payload for BlockFoundPayload {
+    fn new() -> Self {
+        Self {
+            block: Block::new(0, "0".repeat(64), Vec::new()),
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        Ok(Self {
+            block: Block::parse(bytes)?,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        self.block.to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use payloads::block::NewBlockContentPayload;
+    use protocol_builder_parser::Parser;
+
+    #[test]
+    fn test_block_found() {
+        let entries = vec![NewBlockContentPayload {
+            unique_key: String::from("key"),
+            content: "a".repeat(400),
+        }];
+        let mut block = Block::new(3, "0".repeat(64), entries);
+        block.header.nonce = 4312;
+        let payload = BlockFoundPayload { block };
+
+        let complete = Parser::parse_payload(&payload.clone().to_bytes());
+        let parsed = BlockFoundPayload::parse(complete).unwrap();
+        assert_eq!(payload, parsed);
+    }
+}
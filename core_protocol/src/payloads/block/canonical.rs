@@ -0,0 +1,246 @@
+use failure::Error;
+use merkle::merkle_root;
+use payloads::block::{BlockHeader, NewBlockContentPayload, DEFAULT_DIFFICULTY};
+use payloads::block::header::HEADER_FIELDS;
+use payloads::Payload;
+use protocol_builder_parser::{Builder, Parser};
+use time;
+
+/// A block consisting of a header and its entries
+///
+/// The entries are kept sorted by their unique key and every unique key
+/// only exists once. That way every peer builds the same block from the
+/// same entries.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // |                                                                                               |
+/// // //                                                                                             //
+/// // // Header                                                                                      //
+/// // //                                                                                             //
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Number of entries (unsigned)                                                                  |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Unique key                                                                                    |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Number of content fields (unsigned)                                                           |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // //                                                                                             //
+/// // // Content []                                                                                  //
+/// // //                                                                                             //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // //                                                                                             //
+/// // // Next entries ...                                                                            //
+/// // //                                                                                             //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    /// Header of the block
+    pub header: BlockHeader,
+    /// Entries of the block in canonical order
+    pub entries: Vec<NewBlockContentPayload>,
+}
+
+impl Block {
+    /// Creates a new block with the given entries
+    ///
+    /// The entries are brought into canonical order and the merkle root
+    /// is set accordingly. The nonce starts with 0 and the difficulty
+    /// with `DEFAULT_DIFFICULTY`.
+    pub fn new(index: u64, prev: String, entries: Vec<NewBlockContentPayload>) -> Self {
+        let entries = Block::canonical_entries(entries);
+
+        Self {
+            header: BlockHeader {
+                index,
+                timestamp: time::now_utc().to_timespec().sec as u64,
+                prev,
+                merkle_root: merkle_root(&entries),
+                difficulty: DEFAULT_DIFFICULTY,
+                nonce: 0,
+            },
+            entries,
+        }
+    }
+
+    /// Parses a block from its serialized form
+    ///
+    /// Counterpart of `Payload::to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Block::parse(Parser::parse_payload(bytes))
+    }
+
+    /// Hash of the block, which is the hash of the header
+    pub fn hash(&self) -> String {
+        self.header.hash()
+    }
+
+    /// Checks that the entries are in canonical order and that the merkle
+    /// root in the header matches the entries
+    pub fn is_consistent(&self) -> bool {
+        let ordered = self.entries
+            .windows(2)
+            .all(|pair| pair[0].unique_key < pair[1].unique_key);
+
+        ordered && self.header.merkle_root == merkle_root(&self.entries)
+    }
+
+    /// Adds the fields of the block to the given builder
+    ///
+    /// Used by payloads that contain a block.
+    pub(crate) fn build(self, builder: Builder) -> Builder {
+        let mut builder = self.header
+            .build(builder)
+            .add_u64(self.entries.len() as u64);
+
+        for entry in self.entries {
+            let fields = (entry.content.len() as u64 + 254) / 255;
+            builder = builder
+                .add_string(entry.unique_key)
+                .add_u64(fields)
+                .add_string_overflow(entry.content);
+        }
+
+        builder
+    }
+
+    /// Sorts the entries by their unique key and removes duplicated keys
+    ///
+    /// If a unique key exists more than once, the first entry is kept.
+    pub fn canonical_entries(entries: Vec<NewBlockContentPayload>) -> Vec<NewBlockContentPayload> {
+        let mut entries = entries;
+        // stable sort, so the first entry of a key stays in front
+        entries.sort_by(|a, b| a.unique_key.cmp(&b.unique_key));
+        entries.dedup_by(|a, b| a.unique_key == b.unique_key);
+        entries
+    }
+}
+
+impl Payload for Block {
+    fn new() -> Self {
+        Block::new(0, "0".repeat(64), Vec::new())
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < HEADER_FIELDS + 1 {
+            return Err(format_err!("Not enough fields for a block"));
+        }
+
+        let header = BlockHeader::parse(bytes[0..HEADER_FIELDS].to_vec())?;
+        let count = Parser::to_u64(&Parser::vec_to_u8_8(bytes[HEADER_FIELDS].clone())?);
+
+        let mut entries = Vec::new();
+        let mut index = HEADER_FIELDS + 1;
+        for _ in 0..count {
+            if bytes.len() < index + 2 {
+                return Err(format_err!("Block entry is incomplete"));
+            }
+
+            let unique_key = Parser::to_string(&bytes[index])?;
+            let fields = Parser::to_u64(&Parser::vec_to_u8_8(bytes[index + 1].clone())?) as usize;
+            index += 2;
+
+            // the number of fields comes from the wire and may be anything
+            let end = match index.checked_add(fields) {
+                Some(end) if end <= bytes.len() => end,
+                _                               => return Err(format_err!("Block entry content is incomplete")),
+            };
+
+            let content = Parser::combine(&bytes[index..end]);
+            entries.push(NewBlockContentPayload {
+                unique_key,
+                content: Parser::to_string(&content)?,
+            });
+            index = end;
+        }
+
+        Ok(Self { header, entries })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        self.build(Builder::new()).build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol_builder_parser::Parser;
+
+    fn entry(unique_key: &str, content: &str) -> NewBlockContentPayload {
+        NewBlockContentPayload {
+            unique_key: unique_key.to_string(),
+            content: content.to_string()
+        }
+    }
+
+    #[test]
+    fn test_building_and_parsing() {
+        let block = Block::new(
+            12,
+            "f".repeat(64),
+            vec![entry("b", &"b".repeat(600)), entry("a", ""), entry("c", &"c".repeat(255))]
+        );
+
+        let complete = Parser::parse_payload(&block.clone().to_bytes());
+        let parsed = Block::parse(complete).unwrap();
+
+        assert_eq!(block, parsed);
+        assert!(parsed.is_consistent());
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        let first = Block::new(1, "0".repeat(64), vec![entry("b", "2"), entry("a", "1"), entry("b", "3")]);
+        let second = Block::new(1, "0".repeat(64), vec![entry("a", "1"), entry("b", "2")]);
+
+        assert_eq!(first.entries, second.entries);
+        assert_eq!(first.header.merkle_root, second.header.merkle_root);
+    }
+
+    #[test]
+    fn test_inconsistent_block() {
+        let mut block = Block::new(1, "0".repeat(64), vec![entry("a", "1"), entry("b", "2")]);
+        assert!(block.is_consistent());
+
+        block.entries[1].content = String::from("changed");
+        assert!(!block.is_consistent());
+    }
+
+    #[test]
+    fn test_invalid_entry_length() {
+        let block = Block::new(1, "0".repeat(64), vec![entry("a", "1")]);
+        let mut complete = Parser::parse_payload(&block.to_bytes());
+
+        complete[HEADER_FIELDS + 2] = vec![0xFF; 8];
+        assert!(Block::parse(complete.clone()).is_err());
+
+        complete[HEADER_FIELDS + 2] = vec![2, 0, 0, 0, 0, 0, 0, 0];
+        assert!(Block::parse(complete).is_err());
+    }
+
+    quickcheck! {
+        #[allow(trivial_casts)]
+        fn test_quickcheck(index: u64, nonce: u64, contents: Vec<(String, String)>) -> bool {
+            let entries = contents
+                .into_iter()
+                .filter(|(key, _)| key.len() < 256)
+                .map(|(key, content)| entry(&key, &content))
+                .collect();
+            let mut block = Block::new(index, "0".repeat(64), entries);
+            block.header.nonce = nonce;
+
+            let complete = Parser::parse_payload(&block.clone().to_bytes());
+            let parsed = Block::parse(complete).unwrap();
+
+            assert_eq!(block, parsed);
+            true
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use failure::Error;
+use merkle::{MerkleProof, ProofStep};
+use payloads::block::{BlockHeader, NewBlockContentPayload};
+use payloads::block::header::HEADER_FIELDS;
+use payloads::Payload;
+use protocol_builder_parser::{Builder, Parser};
+
+/// Model for the event `GetProof`
+///
+/// Requests the proof that the content with the given unique key is part
+/// of the chain. The block hash is optional, if it is empty the peer
+/// searches the chain for the content.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Unique key                                                                                    |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Block hash                                                                                    |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct GetProofPayload {
+    /// Unique key of the content
+    pub unique_key: String,
+    /// Hash of the block containing the content, empty if unknown
+    pub block: String,
+}
+
+impl Payload for GetProofPayload {
+    fn new() -> Self {
+        Self {
+            unique_key: String::new(),
+            block: String::new(),
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        match bytes.len() {
+            0 => Ok(Self::new()),
+            1 => Ok(Self {
+                unique_key: Parser::to_string(&bytes[0])?,
+                block: String::new(),
+            }),
+            _ => Ok(Self {
+                unique_key: Parser::to_string(&bytes[0])?,
+                block: Parser::to_string(&bytes[1])?,
+            }),
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        Builder::new()
+            .add_string(self.unique_key)
+            .add_string(self.block)
+            .build()
+    }
+}
+
+/// Model for the event `GetProofAck`
+///
+/// Answer to `GetProof`. If the content was not found, `found` is false
+/// and the remaining fields are empty.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Found                 | Empty                                                                 |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // //                                                                                             //
+/// // // Header                                                                                      //
+/// // //                                                                                             //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Unique key                                                                                    |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Number of content fields (unsigned)                                                           |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // // Content []                                                                                  //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Number of steps (unsigned)                                                                    |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Is left               | Sibling hash                                                          |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct GetProofAckPayload {
+    /// true if the content is part of a block
+    pub found: bool,
+    /// Header of the block containing the content
+    pub header: BlockHeader,
+    /// The content itself
+    pub entry: NewBlockContentPayload,
+    /// Proof that the entry is part of the block
+    pub proof: MerkleProof,
+}
+
+impl GetProofAckPayload {
+    /// Creates an answer for content that is not known
+    pub fn not_found(unique_key: String) -> Self {
+        Self {
+            found: false,
+            header: BlockHeader::new(),
+            entry: NewBlockContentPayload {
+                unique_key,
+                content: String::new(),
+            },
+            proof: MerkleProof::default(),
+        }
+    }
+}
+
+impl Payload for GetProofAckPayload {
+    fn new() -> Self {
+        GetProofAckPayload::not_found(String::new())
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < HEADER_FIELDS + 4 {
+            return Err(format_err!("Not enough fields for a proof"));
+        }
+
+        let found = bytes[0] == vec![1];
+        let header = BlockHeader::parse(bytes[1..HEADER_FIELDS + 1].to_vec())?;
+        let unique_key = Parser::to_string(&bytes[HEADER_FIELDS + 1])?;
+        let fields = Parser::to_u64(&Parser::vec_to_u8_8(bytes[HEADER_FIELDS + 2].clone())?) as usize;
+
+        let mut index = HEADER_FIELDS + 3;
+
+        // the number of fields comes from the wire and may be anything,
+        // the content must still leave room for the number of steps
+        let end = match index.checked_add(fields) {
+            Some(end) if end < bytes.len() => end,
+            _                              => return Err(format_err!("Proof content is incomplete")),
+        };
+        let content = Parser::to_string(&Parser::combine(&bytes[index..end]))?;
+        index = end;
+
+        let count = Parser::to_u64(&Parser::vec_to_u8_8(bytes[index].clone())?);
+        index += 1;
+
+        let mut steps = Vec::new();
+        for _ in 0..count {
+            if bytes.len() < index + 2 {
+                return Err(format_err!("Proof step is incomplete"));
+            }
+
+            steps.push(ProofStep {
+                is_left: bytes[index] == vec![1],
+                hash: Parser::to_string(&bytes[index + 1])?,
+            });
+            index += 2;
+        }
+
+        Ok(Self {
+            found,
+            header,
+            entry: NewBlockContentPayload { unique_key, content },
+            proof: MerkleProof { steps },
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let fields = (self.entry.content.len() as u64 + 254) / 255;
+        let builder = Builder::new().add_u8(self.found as u8);
+        let mut builder = self.header
+            .build(builder)
+            .add_string(self.entry.unique_key)
+            .add_u64(fields)
+            .add_string_overflow(self.entry.content)
+            .add_u64(self.proof.steps.len() as u64);
+
+        for step in self.proof.steps {
+            builder = builder
+                .add_u8(step.is_left as u8)
+                .add_string(step.hash);
+        }
+
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merkle::{merkle_proof, merkle_root, verify_proof};
+    use protocol_builder_parser::Parser;
+
+    #[test]
+    fn test_building_and_parsing() {
+        let entries = vec![
+            NewBlockContentPayload { unique_key: String::from("a"), content: String::from("1") },
+            NewBlockContentPayload { unique_key: String::from("b"), content: "2".repeat(300) },
+            NewBlockContentPayload { unique_key: String::from("c"), content: String::from("3") },
+        ];
+        let mut header = BlockHeader::new();
+        header.merkle_root = merkle_root(&entries);
+        header.difficulty = 20;
+
+        let payload = GetProofAckPayload {
+            found: true,
+            header: header.clone(),
+            entry: entries[1].clone(),
+            proof: merkle_proof(&entries, 1).unwrap(),
+        };
+
+        let complete = Parser::parse_payload(&payload.clone().to_bytes());
+        let parsed = GetProofAckPayload::parse(complete).unwrap();
+
+        assert_eq!(payload, parsed);
+        assert!(verify_proof(&parsed.header, &parsed.entry, &parsed.proof));
+    }
+
+    #[test]
+    fn test_not_found() {
+        let payload = GetProofAckPayload::not_found(String::from("unknown"));
+
+        let complete = Parser::parse_payload(&payload.clone().to_bytes());
+        let parsed = GetProofAckPayload::parse(complete).unwrap();
+
+        assert!(!parsed.found);
+        assert_eq!(String::from("unknown"), parsed.entry.unique_key);
+    }
+
+    #[test]
+    fn test_field_count_overflow() {
+        let mut bytes = Parser::parse_payload(&GetProofAckPayload::not_found(String::from("a")).to_bytes());
+        bytes[HEADER_FIELDS + 2] = vec![255; 8];
+
+        assert!(GetProofAckPayload::parse(bytes).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use failure::Error;
+use hash::{sha256, to_hex};
+use payloads::Payload;
+use protocol_builder_parser::{Builder, Parser};
+use time;
+
+/// Difficulty of the first block and of every block before the first
+/// retarget
+///
+/// Equals a hash prefix of four hex zeros.
+pub const DEFAULT_DIFFICULTY: u32 = 16;
+
+/// Number of fields a serialized header uses
+pub(crate) const HEADER_FIELDS: usize = 6;
+
+/// Header of a block
+///
+/// The hash of the header is the hash of the block. It commits to the
+/// entries of the block through the merkle root.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Index (unsigned)                                                                              |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Timestamp (unsigned)                                                                          |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Prev                                                                                          |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Merkle root                                                                                   |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Difficulty (unsigned)                                                                         |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Nonce (unsigned)                                                                              |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockHeader {
+    /// Index of the block
+    pub index: u64,
+    /// Unix timestamp in seconds the block was created
+    pub timestamp: u64,
+    /// Hash of the previous block
+    pub prev: String,
+    /// Merkle root over all entries of the block
+    pub merkle_root: String,
+    /// Number of leading zero bits the hash of the block must have
+    pub difficulty: u32,
+    /// Nonce that is changed while mining
+    pub nonce: u64,
+}
+
+impl BlockHeader {
+    /// Calculates the hash of the header as hex string
+    pub fn hash(&self) -> String {
+        to_hex(&sha256(&self.hash_input()))
+    }
+
+    /// Adds the fields of the header to the given builder
+    ///
+    /// Used by payloads that contain a header.
+    pub(crate) fn build(self, builder: Builder) -> Builder {
+        builder
+            .add_u64(self.index)
+            .add_u64(self.timestamp)
+            .add_string(self.prev)
+            .add_string(self.merkle_root)
+            .add_u32(self.difficulty)
+            .add_u64(self.nonce)
+    }
+
+    /// Bytes the hash is calculated from
+    fn hash_input(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(u64_to_bytes(self.index).iter());
+        bytes.extend(u64_to_bytes(self.timestamp).iter());
+        bytes.push(self.prev.len() as u8);
+        bytes.extend(self.prev.as_bytes());
+        bytes.push(self.merkle_root.len() as u8);
+        bytes.extend(self.merkle_root.as_bytes());
+        bytes.extend(u64_to_bytes(u64::from(self.difficulty)).iter());
+        bytes.extend(u64_to_bytes(self.nonce).iter());
+        bytes
+    }
+}
+
+impl Payload for BlockHeader {
+    fn new() -> Self {
+        Self {
+            index: 0,
+            timestamp: time::now_utc().to_timespec().sec as u64,
+            prev: String::new(),
+            merkle_root: String::new(),
+            difficulty: DEFAULT_DIFFICULTY,
+            nonce: 0,
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < HEADER_FIELDS {
+            return Err(format_err!("Not enough fields for a block header"));
+        }
+
+        Ok(Self {
+            index: Parser::to_u64(&Parser::vec_to_u8_8(bytes[0].clone())?),
+            timestamp: Parser::to_u64(&Parser::vec_to_u8_8(bytes[1].clone())?),
+            prev: Parser::to_string(&bytes[2])?,
+            merkle_root: Parser::to_string(&bytes[3])?,
+            difficulty: Parser::to_u32(&Parser::vec_to_u8_4(bytes[4].clone())?),
+            nonce: Parser::to_u64(&Parser::vec_to_u8_8(bytes[5].clone())?),
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        self.build(Builder::new()).build()
+    }
+}
+
+pub(crate) fn u64_to_bytes(value: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = ((value >> (i * 8)) & 0xFF) as u8;
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol_builder_parser::Parser;
+
+    #[test]
+    fn test_building_and_parsing() {
+        let header = BlockHeader {
+            index: 5,
+            timestamp: 1530000000,
+            prev: "0".repeat(64),
+            merkle_root: "a".repeat(64),
+            difficulty: 18,
+            nonce: 87451651,
+        };
+
+        let complete = Parser::parse_payload(&header.clone().to_bytes());
+        let parsed = BlockHeader::parse(complete).unwrap();
+        assert_eq!(header, parsed);
+        assert_eq!(header.hash(), parsed.hash());
+    }
+
+    #[test]
+    fn test_nonce_changes_hash() {
+        let mut header = BlockHeader::new();
+        let hash = header.hash();
+
+        header.nonce += 1;
+        assert_ne!(hash, header.hash());
+        assert_eq!(64, hash.len());
+    }
+
+    #[test]
+    fn test_difficulty_changes_hash() {
+        let mut header = BlockHeader::new();
+        let hash = header.hash();
+
+        header.difficulty += 1;
+        assert_ne!(hash, header.hash());
+    }
+}
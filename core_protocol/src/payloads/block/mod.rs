@@ -1,5 +1,19 @@
+mod block_found;
 mod calc_block;
+mod canonical;
+mod get_proof;
+mod header;
 mod new_block_content;
+mod reorg;
+mod sync;
+mod vote;
 
+pub use self::block_found::BlockFoundPayload;
 pub use self::calc_block::CalcBlockPayload;
-pub use self::new_block_content::NewBlockContentPayload;
\ No newline at end of file
+pub use self::canonical::Block;
+pub use self::get_proof::{GetProofAckPayload, GetProofPayload};
+pub use self::header::{BlockHeader, DEFAULT_DIFFICULTY};
+pub use self::new_block_content::NewBlockContentPayload;
+pub use self::reorg::ReorgPayload;
+pub use self::sync::{GetBlockAckPayload, GetBlockPayload, GetHeadersPayload, HeadersPayload, TipPayload, MAX_HEADERS};
+pub use self::vote::{BlockVotePayload, FinalityCertificate};
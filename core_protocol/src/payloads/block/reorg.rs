@@ -0,0 +1,129 @@
+use failure::Error;
+use payloads::Payload;
+use protocol_builder_parser::{Builder, Parser};
+
+/// Model for the event `Reorg`
+///
+/// Only created by the local node when the main chain is replaced by a
+/// branch with more work. Never send to other peers.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Height (unsigned)                                                                             |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Old tip                                                                                       |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | New tip                                                                                       |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Number of disconnected blocks (unsigned)                                                      |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // // Disconnected []                                                                             //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Number of connected blocks (unsigned)                                                         |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // // Connected []                                                                                //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReorgPayload {
+    /// First height that was replaced
+    pub height: u64,
+    /// Hash of the tip before the reorganisation
+    pub old_tip: String,
+    /// Hash of the tip after the reorganisation
+    pub new_tip: String,
+    /// Hashes of the blocks removed from the main chain, lowest height first
+    pub disconnected: Vec<String>,
+    /// Hashes of the blocks added to the main chain, lowest height first
+    pub connected: Vec<String>,
+}
+
+impl Payload for ReorgPayload {
+    fn new() -> Self {
+        Self {
+            height: 0,
+            old_tip: String::new(),
+            new_tip: String::new(),
+            disconnected: Vec::new(),
+            connected: Vec::new(),
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < 5 {
+            return Err(format_err!("Not enough fields for a reorg"));
+        }
+
+        let height = Parser::to_u64(&Parser::vec_to_u8_8(bytes[0].clone())?);
+        let old_tip = Parser::to_string(&bytes[1])?;
+        let new_tip = Parser::to_string(&bytes[2])?;
+
+        let mut index = 3;
+        let mut lists = Vec::new();
+        for _ in 0..2 {
+            if bytes.len() <= index {
+                return Err(format_err!("Reorg is incomplete"));
+            }
+
+            let count = Parser::to_u64(&Parser::vec_to_u8_8(bytes[index].clone())?) as usize;
+            index += 1;
+            if bytes.len() < index + count {
+                return Err(format_err!("Reorg is incomplete"));
+            }
+
+            let mut hashes = Vec::new();
+            for field in &bytes[index..index + count] {
+                hashes.push(Parser::to_string(field)?);
+            }
+            lists.push(hashes);
+            index += count;
+        }
+
+        let connected = lists.pop().unwrap_or_default();
+        let disconnected = lists.pop().unwrap_or_default();
+        Ok(Self { height, old_tip, new_tip, disconnected, connected })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut builder = Builder::new()
+            .add_u64(self.height)
+            .add_string(self.old_tip)
+            .add_string(self.new_tip)
+            .add_u64(self.disconnected.len() as u64);
+
+        for hash in self.disconnected {
+            builder = builder.add_string(hash);
+        }
+
+        builder = builder.add_u64(self.connected.len() as u64);
+        for hash in self.connected {
+            builder = builder.add_string(hash);
+        }
+
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol_builder_parser::Parser;
+
+    #[test]
+    fn test_building_and_parsing() {
+        let payload = ReorgPayload {
+            height: 4,
+            old_tip: "a".repeat(64),
+            new_tip: "b".repeat(64),
+            disconnected: vec!["a".repeat(64)],
+            connected: vec!["c".repeat(64), "b".repeat(64)],
+        };
+
+        let complete = Parser::parse_payload(&payload.clone().to_bytes());
+        assert_eq!(payload, ReorgPayload::parse(complete).unwrap());
+    }
+}
@@ -0,0 +1,349 @@
+use failure::Error;
+use payloads::block::header::HEADER_FIELDS;
+use payloads::block::{Block, BlockHeader};
+use payloads::Payload;
+use protocol_builder_parser::{Builder, Parser};
+
+/// Maximum number of headers in a single `GetHeadersAck`
+pub const MAX_HEADERS: u64 = 100;
+
+/// Model for the event `GetTipAck`
+///
+/// Answer to `GetTip`. Contains the latest block of the peer. If the peer
+/// has no block yet, `hash` is empty.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Height (unsigned)                                                                             |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Hash                                                                                          |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TipPayload {
+    /// Height of the latest block
+    pub height: u64,
+    /// Hash of the latest block, empty if there is no block
+    pub hash: String,
+}
+
+impl Payload for TipPayload {
+    fn new() -> Self {
+        Self {
+            height: 0,
+            hash: String::new(),
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(format_err!("Not enough fields for a tip"));
+        }
+
+        Ok(Self {
+            height: Parser::to_u64(&Parser::vec_to_u8_8(bytes[0].clone())?),
+            hash: Parser::to_string(&bytes[1])?,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        Builder::new()
+            .add_u64(self.height)
+            .add_string(self.hash)
+            .build()
+    }
+}
+
+/// Model for the event `GetHeaders`
+///
+/// Requests the headers of the main chain starting with the given height.
+/// At most `MAX_HEADERS` are returned.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Start (unsigned)                                                                              |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Count (unsigned)                                                                              |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct GetHeadersPayload {
+    /// Height of the first header
+    pub start: u64,
+    /// Number of requested headers
+    pub count: u64,
+}
+
+impl Payload for GetHeadersPayload {
+    fn new() -> Self {
+        Self {
+            start: 0,
+            count: MAX_HEADERS,
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(format_err!("Not enough fields for a header request"));
+        }
+
+        Ok(Self {
+            start: Parser::to_u64(&Parser::vec_to_u8_8(bytes[0].clone())?),
+            count: Parser::to_u64(&Parser::vec_to_u8_8(bytes[1].clone())?),
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        Builder::new()
+            .add_u64(self.start)
+            .add_u64(self.count)
+            .build()
+    }
+}
+
+/// Model for the event `GetHeadersAck`
+///
+/// Answer to `GetHeaders`. The headers are in ascending order, starting
+/// with the height `start`. The list is empty if the peer has no block at
+/// that height.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Start (unsigned)                                                                              |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Number of headers (unsigned)                                                                  |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // //                                                                                             //
+/// // // Headers []                                                                                  //
+/// // //                                                                                             //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadersPayload {
+    /// Height of the first header
+    pub start: u64,
+    /// Headers in ascending order
+    pub headers: Vec<BlockHeader>,
+}
+
+impl Payload for HeadersPayload {
+    fn new() -> Self {
+        Self {
+            start: 0,
+            headers: Vec::new(),
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(format_err!("Not enough fields for headers"));
+        }
+
+        let count = Parser::to_u64(&Parser::vec_to_u8_8(bytes[1].clone())?) as usize;
+        if count as u64 > MAX_HEADERS || bytes.len() < 2 + count * HEADER_FIELDS {
+            return Err(format_err!("Headers are incomplete"));
+        }
+
+        let mut headers = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = 2 + i * HEADER_FIELDS;
+            headers.push(BlockHeader::parse(bytes[start..start + HEADER_FIELDS].to_vec())?);
+        }
+
+        Ok(Self {
+            start: Parser::to_u64(&Parser::vec_to_u8_8(bytes[0].clone())?),
+            headers,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut builder = Builder::new()
+            .add_u64(self.start)
+            .add_u64(self.headers.len() as u64);
+
+        for header in self.headers {
+            builder = header.build(builder);
+        }
+
+        builder.build()
+    }
+}
+
+/// Model for the event `GetBlock`
+///
+/// Requests the block with the given hash.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Hash                                                                                          |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct GetBlockPayload {
+    /// Hash of the requested block
+    pub hash: String,
+}
+
+impl Payload for GetBlockPayload {
+    fn new() -> Self {
+        Self {
+            hash: String::new(),
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.is_empty() {
+            return Err(format_err!("Not enough fields for a block request"));
+        }
+
+        Ok(Self {
+            hash: Parser::to_string(&bytes[0])?,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        Builder::new()
+            .add_string(self.hash)
+            .build()
+    }
+}
+
+/// Model for the event `GetBlockAck`
+///
+/// Answer to `GetBlock`. Contains the requested hash, whether the block was
+/// found and the block itself. If the block was not found, an empty block
+/// is send.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Hash                                                                                          |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Found                 | Empty                                                                 |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // |                                                                                               |
+/// // //                                                                                             //
+/// // // Block                                                                                       //
+/// // //                                                                                             //
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct GetBlockAckPayload {
+    /// Hash of the requested block
+    pub hash: String,
+    /// true if the peer knows the block
+    pub found: bool,
+    /// The block, empty if it was not found
+    pub block: Block,
+}
+
+impl GetBlockAckPayload {
+    /// Creates an answer for a block that is not known
+    pub fn not_found(hash: String) -> Self {
+        Self {
+            hash,
+            found: false,
+            block: Block::new(0, String::new(), Vec::new()),
+        }
+    }
+}
+
+impl Payload for GetBlockAckPayload {
+    fn new() -> Self {
+        GetBlockAckPayload::not_found(String::new())
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < 3 {
+            return Err(format_err!("Not enough fields for a block answer"));
+        }
+
+        Ok(Self {
+            hash: Parser::to_string(&bytes[0])?,
+            found: bytes[1] == vec![1],
+            block: Block::parse(bytes[2..].to_vec())?,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let builder = Builder::new()
+            .add_string(self.hash)
+            .add_u8(self.found as u8);
+        self.block.build(builder).build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use payloads::block::NewBlockContentPayload;
+    use protocol_builder_parser::Parser;
+
+    #[test]
+    fn test_tip() {
+        let payload = TipPayload {
+            height: 42,
+            hash: "a".repeat(64),
+        };
+
+        let complete = Parser::parse_payload(&payload.clone().to_bytes());
+        assert_eq!(payload, TipPayload::parse(complete).unwrap());
+    }
+
+    #[test]
+    fn test_headers() {
+        let request = GetHeadersPayload { start: 10, count: 20 };
+        let complete = Parser::parse_payload(&request.clone().to_bytes());
+        assert_eq!(request, GetHeadersPayload::parse(complete).unwrap());
+
+        let mut header = BlockHeader::new();
+        header.index = 10;
+        header.prev = "0".repeat(64);
+        let mut second = header.clone();
+        second.index = 11;
+        second.prev = header.hash();
+
+        let payload = HeadersPayload {
+            start: 10,
+            headers: vec![header, second],
+        };
+        let complete = Parser::parse_payload(&payload.clone().to_bytes());
+        assert_eq!(payload, HeadersPayload::parse(complete).unwrap());
+    }
+
+    #[test]
+    fn test_block() {
+        let request = GetBlockPayload { hash: "a".repeat(64) };
+        let complete = Parser::parse_payload(&request.clone().to_bytes());
+        assert_eq!(request, GetBlockPayload::parse(complete).unwrap());
+
+        let entries = vec![NewBlockContentPayload {
+            unique_key: String::from("key"),
+            content: "a".repeat(300),
+        }];
+        let block = Block::new(1, "0".repeat(64), entries);
+        let payload = GetBlockAckPayload {
+            hash: block.hash(),
+            found: true,
+            block,
+        };
+        let complete = Parser::parse_payload(&payload.clone().to_bytes());
+        assert_eq!(payload, GetBlockAckPayload::parse(complete).unwrap());
+
+        let payload = GetBlockAckPayload::not_found("a".repeat(64));
+        let complete = Parser::parse_payload(&payload.clone().to_bytes());
+        let parsed = GetBlockAckPayload::parse(complete).unwrap();
+        assert!(!parsed.found);
+        assert_eq!("a".repeat(64), parsed.hash);
+    }
+}
@@ -0,0 +1,316 @@
+use failure::Error;
+use hash::{decode_hex, to_hex};
+use nacl::Nacl;
+use payloads::block::header::u64_to_bytes;
+use payloads::Payload;
+use protocol_builder_parser::{Builder, Parser};
+use sodiumoxide::crypto::sign;
+
+/// Reasons are cut off after this many bytes, so they fit into one field
+const MAX_REASON_LENGTH: usize = 200;
+/// Number of fields a serialized vote uses
+const VOTE_FIELDS: usize = 6;
+
+/// Model for the event `BlockVote`
+///
+/// Answer to `BlockFound`. Contains the verdict of a peer about a block,
+/// signed with the signing key of the peer. If the block was rejected,
+/// `reason` explains why.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Index (unsigned)                                                                              |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Hash                                                                                          |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Accepted              | Empty                                                                 |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Reason                                                                                        |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Public key                                                                                    |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Signature                                                                                     |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockVotePayload {
+    /// Index of the block
+    pub index: u64,
+    /// Hash calculated by the voting peer
+    pub hash: String,
+    /// true if the block is valid
+    pub accepted: bool,
+    /// Why the block was rejected, empty if it was accepted
+    pub reason: String,
+    /// Signing public key of the voting peer as hex
+    pub public_key: String,
+    /// Signature over all other fields as hex
+    pub signature: String,
+}
+
+impl BlockVotePayload {
+    /// Creates a new vote signed with the signing key of the given nacl
+    pub fn signed(nacl: &Nacl, index: u64, hash: String, accepted: bool, reason: String) -> Self {
+        let mut reason = reason;
+        while reason.len() > MAX_REASON_LENGTH {
+            reason.pop();
+        }
+
+        let mut vote = Self {
+            index,
+            hash,
+            accepted,
+            reason,
+            public_key: to_hex(&nacl.sign_public_key().0),
+            signature: String::new(),
+        };
+        vote.signature = to_hex(&nacl.sign(&vote.signed_bytes()).0);
+        vote
+    }
+
+    /// Checks that the vote is signed by the given key
+    ///
+    /// The key contained in the vote is chosen by the sender, so it must be
+    /// compared to the known key of the voting peer.
+    pub fn is_signed_by(&self, key: &sign::PublicKey) -> bool {
+        self.public_key == to_hex(&key.0) && self.verify()
+    }
+
+    /// Checks that the signature matches the public key and the content
+    ///
+    /// This doesn´t tell who signed the vote, see `is_signed_by`.
+    pub fn verify(&self) -> bool {
+        let public_key = match decode_hex(&self.public_key).and_then(|key| sign::PublicKey::from_slice(&key)) {
+            Some(key) => key,
+            None      => return false,
+        };
+        let signature = match decode_hex(&self.signature).and_then(|signature| sign::Signature::from_slice(&signature)) {
+            Some(signature) => signature,
+            None            => return false,
+        };
+
+        sign::verify_detached(&signature, &self.signed_bytes(), &public_key)
+    }
+
+    /// Bytes the signature is calculated from
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(b"carina_block_vote".iter());
+        bytes.extend(u64_to_bytes(self.index).iter());
+        bytes.push(self.hash.len() as u8);
+        bytes.extend(self.hash.as_bytes());
+        bytes.push(self.accepted as u8);
+        bytes.push(self.reason.len() as u8);
+        bytes.extend(self.reason.as_bytes());
+        bytes
+    }
+
+    /// Adds the fields of the vote to the given builder
+    fn build(self, builder: Builder) -> Builder {
+        builder
+            .add_u64(self.index)
+            .add_string(self.hash)
+            .add_u8(self.accepted as u8)
+            .add_string(self.reason)
+            .add_string(self.public_key)
+            .add_string(self.signature)
+    }
+}
+
+impl Payload for BlockVotePayload {
+    fn new() -> Self {
+        Self {
+            index: 0,
+            hash: String::new(),
+            accepted: false,
+            reason: String::new(),
+            public_key: String::new(),
+            signature: String::new(),
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < VOTE_FIELDS {
+            return Err(format_err!("Not enough fields for a block vote"));
+        }
+
+        Ok(Self {
+            index: Parser::to_u64(&Parser::vec_to_u8_8(bytes[0].clone())?),
+            hash: Parser::to_string(&bytes[1])?,
+            accepted: bytes[2] == vec![1],
+            reason: Parser::to_string(&bytes[3])?,
+            public_key: Parser::to_string(&bytes[4])?,
+            signature: Parser::to_string(&bytes[5])?,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        self.build(Builder::new()).build()
+    }
+}
+
+/// Signed votes of a quorum of peers for a block
+///
+/// Stored next to the block as proof that the block was accepted.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Index (unsigned)                                                                              |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Hash                                                                                          |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Number of votes (unsigned)                                                                    |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // //                                                                                             //
+/// // // Votes []                                                                                    //
+/// // //                                                                                             //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct FinalityCertificate {
+    /// Index of the block
+    pub index: u64,
+    /// Hash of the block
+    pub hash: String,
+    /// Votes of the peers
+    pub votes: Vec<BlockVotePayload>,
+}
+
+impl FinalityCertificate {
+    /// Parses a certificate from its serialized form
+    ///
+    /// Counterpart of `Payload::to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        FinalityCertificate::parse(Parser::parse_payload(bytes))
+    }
+
+    /// Number of valid votes accepting the block
+    ///
+    /// Only votes signed by one of the given signing keys of the peers are
+    /// counted. Votes with an invalid signature, for another block or from
+    /// a key that already voted are not counted.
+    pub fn accepted(&self, signers: &[sign::PublicKey]) -> usize {
+        let mut voters: Vec<&sign::PublicKey> = Vec::new();
+
+        for vote in &self.votes {
+            if !vote.accepted || vote.index != self.index || vote.hash != self.hash {
+                continue;
+            }
+
+            let signer = signers
+                .iter()
+                .find(|signer| !voters.contains(signer) && vote.is_signed_by(signer));
+            if let Some(signer) = signer {
+                voters.push(signer);
+            }
+        }
+        voters.len()
+    }
+}
+
+impl Payload for FinalityCertificate {
+    fn new() -> Self {
+        Self {
+            index: 0,
+            hash: String::new(),
+            votes: Vec::new(),
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < 3 {
+            return Err(format_err!("Not enough fields for a finality certificate"));
+        }
+
+        let count = Parser::to_u64(&Parser::vec_to_u8_8(bytes[2].clone())?) as usize;
+        if bytes.len() < 3 + count * VOTE_FIELDS {
+            return Err(format_err!("Finality certificate is incomplete"));
+        }
+
+        let mut votes = Vec::new();
+        for i in 0..count {
+            let start = 3 + i * VOTE_FIELDS;
+            votes.push(BlockVotePayload::parse(bytes[start..start + VOTE_FIELDS].to_vec())?);
+        }
+
+        Ok(Self {
+            index: Parser::to_u64(&Parser::vec_to_u8_8(bytes[0].clone())?),
+            hash: Parser::to_string(&bytes[1])?,
+            votes,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut builder = Builder::new()
+            .add_u64(self.index)
+            .add_string(self.hash)
+            .add_u64(self.votes.len() as u64);
+
+        for vote in self.votes {
+            builder = vote.build(builder);
+        }
+
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol_builder_parser::Parser;
+    use sodiumoxide::crypto::box_;
+
+    fn vote(nacl: &Nacl, accepted: bool) -> BlockVotePayload {
+        BlockVotePayload::signed(nacl, 4, "0".repeat(64), accepted, String::new())
+    }
+
+    fn nacl() -> Nacl {
+        Nacl::new(box_::gen_keypair().1)
+    }
+
+    #[test]
+    fn test_vote() {
+        let (_, secret_key) = box_::gen_keypair();
+        let nacl = Nacl::new(secret_key);
+        let payload = BlockVotePayload::signed(&nacl, 4, "0".repeat(64), false, "x".repeat(300));
+        assert_eq!(MAX_REASON_LENGTH, payload.reason.len());
+        assert!(payload.verify());
+
+        let complete = Parser::parse_payload(&payload.clone().to_bytes());
+        let parsed = BlockVotePayload::parse(complete).unwrap();
+        assert_eq!(payload, parsed);
+        assert!(parsed.verify());
+
+        let mut changed = parsed.clone();
+        changed.accepted = true;
+        assert!(!changed.verify());
+
+        assert!(parsed.is_signed_by(&nacl.sign_public_key()));
+        assert!(!parsed.is_signed_by(&Nacl::new(box_::gen_keypair().1).sign_public_key()));
+    }
+
+    #[test]
+    fn test_certificate() {
+        let (first, second, third, unknown) = (nacl(), nacl(), nacl(), nacl());
+        let signers = vec![first.sign_public_key(), second.sign_public_key(), third.sign_public_key()];
+        let mut certificate = FinalityCertificate {
+            index: 4,
+            hash: "0".repeat(64),
+            votes: vec![vote(&first, true), vote(&second, true), vote(&third, false), vote(&first, true), vote(&unknown, true)],
+        };
+        assert_eq!(2, certificate.accepted(&signers));
+        assert_eq!(1, certificate.accepted(&signers[1..]));
+
+        let complete = Parser::parse_payload(&certificate.clone().to_bytes());
+        let parsed = FinalityCertificate::parse(complete).unwrap();
+        assert_eq!(certificate, parsed);
+
+        certificate.hash = "1".repeat(64);
+        assert_eq!(0, certificate.accepted(&signers));
+    }
+}
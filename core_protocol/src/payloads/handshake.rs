@@ -0,0 +1,92 @@
+use failure::Error;
+use hash::{from_hex, to_hex};
+use payloads::Payload;
+use protocol_builder_parser::{Builder, Parser};
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::PublicKey;
+
+/// Model for the events `Handshake` and `HandshakeAck`
+///
+/// Both sides send a fresh ephemeral public key. The key shared between the
+/// two ephemeral keys is used for all further messages of the session.
+/// Handshakes are sealed with the long-term keys of the peers, that way the
+/// ephemeral keys are authenticated.
+///
+/// The initiator chooses a random session id, the answer repeats it.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Session id (unsigned)                                                                         |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Ephemeral public key (hex)                                                                    |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandshakePayload {
+    /// Id of the session
+    pub session_id: u64,
+    /// Ephemeral public key of the sender
+    pub public_key: PublicKey,
+}
+
+impl Payload for HandshakePayload {
+    fn new() -> Self {
+        Self {
+            session_id: 0,
+            public_key: PublicKey([0; 32]),
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(format_err!("Not enough fields for a handshake"));
+        }
+
+        let public_key = match from_hex(&Parser::to_string(&bytes[1])?) {
+            Some(key) => PublicKey(key),
+            None      => return Err(format_err!("Invalid ephemeral public key")),
+        };
+
+        Ok(Self {
+            session_id: Parser::to_u64(&Parser::vec_to_u8_8(bytes[0].clone())?),
+            public_key,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        Builder::new()
+            .add_u64(self.session_id)
+            .add_string(to_hex(&self.public_key.0))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sodiumoxide::crypto::box_;
+
+    #[test]
+    fn test_building_and_parsing() {
+        let (public_key, _) = box_::gen_keypair();
+        let handshake = HandshakePayload {
+            session_id: 8_446_744_073_709,
+            public_key,
+        };
+
+        let complete = Parser::parse_payload(&handshake.clone().to_bytes());
+        let parsed = HandshakePayload::parse(complete).unwrap();
+        assert_eq!(handshake, parsed);
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let bytes = Builder::new()
+            .add_u64(1)
+            .add_string(String::from("abc"))
+            .build();
+
+        assert!(HandshakePayload::parse(Parser::parse_payload(&bytes)).is_err());
+    }
+}
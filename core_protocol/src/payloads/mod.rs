@@ -19,10 +19,16 @@
 //! 
 //! `Payload`: Payload of the request
 mod empty;
+mod handshake;
 mod payload;
+mod peers;
+mod register;
 
 /// Contains payloads that have to do with blocks
 pub mod block;
 
 pub use self::empty::EmptyPayload;
-pub use self::payload::Payload;
\ No newline at end of file
+pub use self::handshake::HandshakePayload;
+pub use self::payload::Payload;
+pub use self::peers::{PeerEntry, PeersPayload, MAX_PEERS};
+pub use self::register::{RegisterPayload, REGISTER_PADDING};
\ No newline at end of file
@@ -0,0 +1,160 @@
+use failure::Error;
+use hash::{from_hex, to_hex};
+use payloads::Payload;
+use protocol_builder_parser::{Builder, Parser};
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::PublicKey;
+
+/// Maximum number of peers in a single `RegisterAck` or `GetPeersAck`
+pub const MAX_PEERS: u64 = 64;
+
+/// Address and public key of a single peer
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerEntry {
+    /// Address the peer is reachable at
+    pub address: String,
+    /// Long-term public key of the peer
+    pub public_key: PublicKey,
+}
+
+/// Model for the events `RegisterAck` and `GetPeersAck`
+///
+/// Contains the peers known by the sender. At most `MAX_PEERS` peers are
+/// contained. A `RegisterAck` to an unknown peer contains no peers but a
+/// cookie, that the peer has to send back with its next `Register`.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Number of peers (unsigned)                                                                    |
+/// // |                                                                                               |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // //                                                                                             //
+/// // // Peers [] (address, public key (hex))                                                        //
+/// // //                                                                                             //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Cookie (hex, optional)                                                                        |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeersPayload {
+    /// Known peers
+    pub peers: Vec<PeerEntry>,
+    /// Cookie the receiver has to send back with its next `Register`
+    pub cookie: Option<[u8; 32]>,
+}
+
+impl Payload for PeersPayload {
+    fn new() -> Self {
+        Self {
+            peers: Vec::new(),
+            cookie: None,
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.is_empty() {
+            return Err(format_err!("Not enough fields for peers"));
+        }
+
+        let count = Parser::to_u64(&Parser::vec_to_u8_8(bytes[0].clone())?) as usize;
+        if count as u64 > MAX_PEERS || bytes.len() < 1 + count * 2 {
+            return Err(format_err!("Peers are incomplete"));
+        }
+
+        let mut peers = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = 1 + i * 2;
+            let public_key = match from_hex(&Parser::to_string(&bytes[start + 1])?) {
+                Some(key) => PublicKey(key),
+                None      => return Err(format_err!("Invalid public key of a peer")),
+            };
+
+            peers.push(PeerEntry {
+                address: Parser::to_string(&bytes[start])?,
+                public_key,
+            });
+        }
+
+        let cookie = match bytes.get(1 + count * 2) {
+            Some(field) if !field.is_empty() => match from_hex(&Parser::to_string(field)?) {
+                Some(cookie) => Some(cookie),
+                None         => return Err(format_err!("Invalid register cookie")),
+            },
+            _                                => None,
+        };
+
+        Ok(Self {
+            peers,
+            cookie,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut builder = Builder::new()
+            .add_u64(self.peers.len() as u64);
+
+        for peer in self.peers {
+            builder = builder
+                .add_string(peer.address)
+                .add_string(to_hex(&peer.public_key.0));
+        }
+        if let Some(cookie) = self.cookie {
+            builder = builder.add_string(to_hex(&cookie));
+        }
+
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sodiumoxide::crypto::box_;
+
+    #[test]
+    fn test_building_and_parsing() {
+        let peers = PeersPayload {
+            peers: vec![
+                PeerEntry {
+                    address: String::from("127.0.0.1:45002"),
+                    public_key: box_::gen_keypair().0,
+                },
+                PeerEntry {
+                    address: String::from("127.0.0.1:45003"),
+                    public_key: box_::gen_keypair().0,
+                },
+            ],
+            cookie: None,
+        };
+
+        let complete = Parser::parse_payload(&peers.clone().to_bytes());
+        let parsed = PeersPayload::parse(complete).unwrap();
+        assert_eq!(peers, parsed);
+    }
+
+    #[test]
+    fn test_cookie() {
+        let peers = PeersPayload {
+            peers: Vec::new(),
+            cookie: Some([3; 32]),
+        };
+
+        let complete = Parser::parse_payload(&peers.clone().to_bytes());
+        assert_eq!(peers, PeersPayload::parse(complete).unwrap());
+    }
+
+    #[test]
+    fn test_empty() {
+        let complete = Parser::parse_payload(&PeersPayload::new().to_bytes());
+        assert_eq!(PeersPayload::new(), PeersPayload::parse(complete).unwrap());
+    }
+
+    #[test]
+    fn test_too_many_peers() {
+        let bytes = Builder::new()
+            .add_u64(MAX_PEERS + 1)
+            .build();
+
+        assert!(PeersPayload::parse(Parser::parse_payload(&bytes)).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use failure::Error;
+use hash::{from_hex, to_hex};
+use payloads::Payload;
+use protocol_builder_parser::{Builder, Parser};
+
+/// Number of bytes every `Register` is padded with
+///
+/// The answer to a `Register` of an unknown peer may only be a few times
+/// larger than the request, so the padding makes room for the peer list.
+pub const REGISTER_PADDING: usize = 768;
+
+/// Model for the event `Register`
+///
+/// An unknown peer first registers without a cookie and gets a
+/// `RegisterAck` that only contains a cookie. Sending the cookie back
+/// proves that the peer receives messages at its address. The padding is
+/// ignored.
+///
+/// ```
+/// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // | Cookie (hex, empty if none)                                                                   |
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// // //                                                                                             //
+/// // // Padding                                                                                     //
+/// // //                                                                                             //
+/// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisterPayload {
+    /// Cookie of the `RegisterAck`, `None` for the first `Register`
+    pub cookie: Option<[u8; 32]>,
+}
+
+impl Payload for RegisterPayload {
+    fn new() -> Self {
+        Self {
+            cookie: None,
+        }
+    }
+
+    fn parse(bytes: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if bytes.is_empty() || bytes[0].is_empty() {
+            return Ok(Self::new());
+        }
+
+        match from_hex(&Parser::to_string(&bytes[0])?) {
+            Some(cookie) => Ok(Self { cookie: Some(cookie) }),
+            None         => Err(format_err!("Invalid register cookie")),
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let cookie = match self.cookie {
+            Some(cookie) => to_hex(&cookie),
+            None         => String::new(),
+        };
+
+        Builder::new()
+            .add_string(cookie)
+            .add_string_overflow("0".repeat(REGISTER_PADDING))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_building_and_parsing() {
+        let payload = RegisterPayload {
+            cookie: Some([7; 32]),
+        };
+
+        let bytes = payload.clone().to_bytes();
+        assert!(bytes.len() > REGISTER_PADDING);
+        assert_eq!(payload, RegisterPayload::parse(Parser::parse_payload(&bytes)).unwrap());
+
+        let complete = Parser::parse_payload(&RegisterPayload::new().to_bytes());
+        assert_eq!(RegisterPayload::new(), RegisterPayload::parse(complete).unwrap());
+    }
+}
@@ -1,15 +1,106 @@
 use failure::Error;
 use nacl::Nacl;
 use sodiumoxide::crypto::box_;
-use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, PublicKey};
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, PrecomputedKey, PublicKey};
 
 /// Tries to decrypt the given message
 /// 
 /// If successful it will return a new decrypted `Vec<u8>` without the nonce
+///
+/// Replays are not detected here, the receiver must check the nonce with a
+/// `ReplayWindow` of the sender.
 pub fn decrypt(bytes: &[u8], nacl: &Nacl, public_key: &PublicKey) -> Result<Vec<u8>, Error> {
+    if bytes.len() < 24 {
+        return Err(format_err!("Message is too short."));
+    }
+
     let nonce = Nonce::from_slice(&bytes[0..24]).unwrap();
     match box_::open(&bytes[24..], &nonce, &public_key, &nacl.get_secret_key()) {
         Ok(val) => Ok(val),
         Err(_) => Err(format_err!("Error decrypting incoming message.")),
     }
-}
\ No newline at end of file
+}
+/// Same as `decrypt`, but opens with a key created by `Nacl::precompute`
+pub fn decrypt_precomputed(bytes: &[u8], key: &PrecomputedKey) -> Result<Vec<u8>, Error> {
+    if bytes.len() < 24 {
+        return Err(format_err!("Message is too short."));
+    }
+
+    let nonce = Nonce::from_slice(&bytes[0..24]).unwrap();
+    match box_::open_precomputed(&bytes[24..], &nonce, key) {
+        Ok(val) => Ok(val),
+        Err(_) => Err(format_err!("Error decrypting incoming message.")),
+    }
+}
+
+/// Opens a message built with `MessageBuilder::build_introduction`
+///
+/// # Return
+/// - `Result<(PublicKey, Nonce, Vec<u8>), Error>` -> public key of the
+///   sender, nonce of the message and the decrypted message
+pub fn decrypt_introduction(bytes: &[u8], nacl: &Nacl) -> Result<(PublicKey, Nonce, Vec<u8>), Error> {
+    if bytes.len() < 32 + 24 {
+        return Err(format_err!("Message is too short."));
+    }
+
+    // unwrap ok, the length is checked above
+    let public_key = PublicKey::from_slice(&bytes[0..32]).unwrap();
+    let nonce = Nonce::from_slice(&bytes[32..56]).unwrap();
+    let message = decrypt(&bytes[32..], nacl, &public_key)?;
+    Ok((public_key, nonce, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use payloads::{EmptyPayload, Payload};
+    use send_message_builder::MessageBuilder;
+
+    #[test]
+    fn test_precomputed_matches_plain() {
+        let (our_pk, our_sk) = box_::gen_keypair();
+        let (their_pk, their_sk) = box_::gen_keypair();
+        let mut ours = Nacl::new(our_sk);
+        let theirs = Nacl::new(their_sk);
+        let our_key = ours.precompute(&their_pk);
+
+        let plain = MessageBuilder::new()
+            .set_event_code(0)
+            .set_payload(EmptyPayload::new())
+            .build(&mut ours, &their_pk);
+        let precomputed = MessageBuilder::new()
+            .set_event_code(0)
+            .set_payload(EmptyPayload::new())
+            .build_precomputed(&mut ours, &our_key);
+
+        let key = theirs.precompute(&our_pk);
+        assert_eq!(decrypt(&plain, &theirs, &our_pk).unwrap(), decrypt_precomputed(&plain, &key).unwrap());
+        assert_eq!(decrypt(&precomputed, &theirs, &our_pk).unwrap(), decrypt_precomputed(&precomputed, &key).unwrap());
+    }
+
+    #[test]
+    fn test_introduction() {
+        let (our_pk, our_sk) = box_::gen_keypair();
+        let (their_pk, their_sk) = box_::gen_keypair();
+        let mut ours = Nacl::new(our_sk);
+        let theirs = Nacl::new(their_sk);
+        assert_eq!(our_pk, ours.public_key());
+
+        let message = MessageBuilder::new()
+            .set_event_code(4)
+            .set_payload(EmptyPayload::new())
+            .build_introduction(&mut ours, &their_pk);
+
+        let (public_key, nonce, decrypted) = decrypt_introduction(&message, &theirs).unwrap();
+        assert_eq!(our_pk, public_key);
+        assert_eq!(&message[32..56], &nonce.0[..]);
+        assert_eq!(4, decrypted[1]);
+
+        // a plain message isn´t an introduction
+        let plain = MessageBuilder::new()
+            .set_event_code(4)
+            .set_payload(EmptyPayload::new())
+            .build(&mut ours, &their_pk);
+        assert!(decrypt_introduction(&plain, &theirs).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+//! Detects messages that are received more than once
+//!
+//! The nonce of every message is read as two parts. The first 8 bytes are
+//! a little endian counter that `Nacl` increments for every message. The
+//! remaining 16 bytes are a prefix that stays the same while the counter
+//! doesn´t overflow, so it identifies the session of a peer.
+//!
+//! For the current prefix of a peer a sliding window of `REPLAY_WINDOW`
+//! counters is tracked. Counters that were already seen or that are older
+//! than the window are rejected.
+//!
+//! A recorded message of an older session carries a prefix the window may
+//! not know anymore, so a new prefix alone doesn´t prove that the peer
+//! started a new session. Only a message that was opened with a session
+//! key proves it, because the session was negotiated with a fresh
+//! ephemeral key. Such a prefix becomes the current one and all other
+//! prefixes are retired, so messages of an older session are rejected.
+//! Unproven prefixes get their own window until they are proven, but they
+//! never replace the current prefix.
+//!
+//! Prefixes are never forgotten, otherwise a recorded message of a
+//! forgotten prefix would be accepted again. Retired prefixes are kept for
+//! the lifetime of the window, a new retired prefix needs a new session of
+//! the peer. If `MAX_UNPROVEN` prefixes are waiting for their proof, further
+//! unproven prefixes are rejected until a session proves one of them.
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::Nonce;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+/// Number of counters below the highest seen counter that are tracked
+pub const REPLAY_WINDOW: u64 = 128;
+/// Number of unproven prefixes that are tracked
+const MAX_UNPROVEN: usize = 8;
+
+/// Reason a nonce was rejected
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Replay {
+    /// The nonce was already seen
+    Duplicate,
+    /// The nonce is older than the window
+    TooOld,
+    /// The nonce belongs to a session that was replaced
+    Retired,
+    /// The nonce belongs to an unproven session that can´t be tracked
+    Untracked,
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Replay::Duplicate => write!(f, "nonce was already used"),
+            Replay::TooOld    => write!(f, "nonce is too old"),
+            Replay::Retired   => write!(f, "nonce belongs to an old session"),
+            Replay::Untracked => write!(f, "nonce belongs to too many unproven sessions"),
+        }
+    }
+}
+
+/// Counters seen with a single prefix
+#[derive(Clone, Debug)]
+struct Counters {
+    /// highest counter seen
+    highest: u64,
+    /// bit `n` is set if the counter `highest - n` was seen
+    seen: u128,
+}
+
+impl Counters {
+    /// Creates the counters of a prefix that was first seen with `counter`
+    fn new(counter: u64) -> Self {
+        Self {
+            highest: counter,
+            seen: 1,
+        }
+    }
+
+    /// Checks the counter and remembers it
+    fn check(&mut self, counter: u64) -> Result<(), Replay> {
+        if counter > self.highest {
+            let shift = counter - self.highest;
+            self.seen = if shift >= REPLAY_WINDOW { 0 } else { self.seen << shift };
+            self.seen |= 1;
+            self.highest = counter;
+            return Ok(());
+        }
+
+        let age = self.highest - counter;
+        if age >= REPLAY_WINDOW {
+            return Err(Replay::TooOld);
+        }
+
+        let bit = 1u128 << age;
+        if self.seen & bit != 0 {
+            return Err(Replay::Duplicate);
+        }
+        self.seen |= bit;
+        Ok(())
+    }
+}
+
+/// Sliding window over the nonces of a single peer
+#[derive(Clone, Debug, Default)]
+pub struct ReplayWindow {
+    /// prefix of the current session and its counters
+    current: Option<([u8; 16], Counters)>,
+    /// prefixes that were not proven by a session yet
+    unproven: VecDeque<([u8; 16], Counters)>,
+    /// prefixes of replaced sessions
+    retired: HashSet<[u8; 16]>,
+}
+
+impl ReplayWindow {
+    /// Creates an empty window
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks the nonce of a received message and remembers it
+    ///
+    /// Must only be called for messages that were decrypted successfully,
+    /// otherwise anybody could move the window.
+    ///
+    /// # Params
+    /// - `nonce` -> nonce of the message
+    /// - `proven` -> true if the message was opened with a session key
+    pub fn check(&mut self, nonce: &Nonce, proven: bool) -> Result<(), Replay> {
+        let (counter, prefix) = split(nonce);
+
+        if let Some((ref current, ref mut counters)) = self.current {
+            if *current == prefix {
+                return counters.check(counter);
+            }
+        }
+        if self.retired.contains(&prefix) {
+            return Err(Replay::Retired);
+        }
+
+        let position = self.unproven.iter().position(|(known, _)| *known == prefix);
+        if !proven && self.current.is_some() {
+            return match position {
+                Some(position) => self.unproven[position].1.check(counter),
+                None           => {
+                    // forgetting a prefix would accept its messages again
+                    if self.unproven.len() >= MAX_UNPROVEN {
+                        return Err(Replay::Untracked);
+                    }
+                    self.unproven.push_back((prefix, Counters::new(counter)));
+                    Ok(())
+                },
+            };
+        }
+
+        // the prefix replaces the current one
+        let counters = match position {
+            Some(position) => {
+                self.unproven[position].1.check(counter)?;
+                self.unproven[position].1.clone()
+            },
+            None           => Counters::new(counter),
+        };
+
+        let replaced: Vec<[u8; 16]> = self.current
+            .take()
+            .into_iter()
+            .chain(self.unproven.drain(..))
+            .map(|(old, _)| old)
+            .filter(|old| *old != prefix)
+            .collect();
+        self.retired.extend(replaced);
+
+        self.current = Some((prefix, counters));
+        Ok(())
+    }
+}
+
+/// Splits the nonce into its counter and its prefix
+fn split(nonce: &Nonce) -> (u64, [u8; 16]) {
+    let counter = nonce.0[0..8]
+        .iter()
+        .enumerate()
+        .fold(0, |value, (i, byte)| value | u64::from(*byte) << (i * 8));
+
+    let mut prefix = [0; 16];
+    prefix.copy_from_slice(&nonce.0[8..24]);
+    (counter, prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nonce(counter: u64, prefix: u8) -> Nonce {
+        let mut bytes = [prefix; 24];
+        for (i, byte) in bytes.iter_mut().take(8).enumerate() {
+            *byte = ((counter >> (i * 8)) & 0xFF) as u8;
+        }
+        Nonce(bytes)
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let mut window = ReplayWindow::new();
+
+        assert_eq!(Ok(()), window.check(&nonce(10, 1), false));
+        assert_eq!(Err(Replay::Duplicate), window.check(&nonce(10, 1), false));
+        assert_eq!(Ok(()), window.check(&nonce(11, 1), false));
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut window = ReplayWindow::new();
+
+        assert_eq!(Ok(()), window.check(&nonce(20, 1), false));
+        assert_eq!(Ok(()), window.check(&nonce(15, 1), false));
+        assert_eq!(Ok(()), window.check(&nonce(17, 1), false));
+        assert_eq!(Err(Replay::Duplicate), window.check(&nonce(15, 1), false));
+    }
+
+    #[test]
+    fn test_too_old() {
+        let mut window = ReplayWindow::new();
+
+        assert_eq!(Ok(()), window.check(&nonce(1, 1), false));
+        assert_eq!(Ok(()), window.check(&nonce(1 + REPLAY_WINDOW, 1), false));
+        assert_eq!(Err(Replay::TooOld), window.check(&nonce(1, 1), false));
+        assert_eq!(Ok(()), window.check(&nonce(2, 1), false));
+    }
+
+    #[test]
+    fn test_new_session() {
+        let mut window = ReplayWindow::new();
+
+        assert_eq!(Ok(()), window.check(&nonce(500, 1), false));
+        assert_eq!(Ok(()), window.check(&nonce(3, 2), true));
+        assert_eq!(Err(Replay::Retired), window.check(&nonce(501, 1), false));
+        assert_eq!(Ok(()), window.check(&nonce(4, 2), false));
+    }
+
+    #[test]
+    fn test_unproven_session() {
+        let mut window = ReplayWindow::new();
+
+        assert_eq!(Ok(()), window.check(&nonce(500, 1), false));
+        // a recorded message of an unknown session doesn´t replace the
+        // current one
+        assert_eq!(Ok(()), window.check(&nonce(3, 2), false));
+        assert_eq!(Err(Replay::Duplicate), window.check(&nonce(3, 2), false));
+        assert_eq!(Ok(()), window.check(&nonce(501, 1), false));
+
+        // a restarted peer proves its prefix with the new session
+        assert_eq!(Ok(()), window.check(&nonce(5, 3), false));
+        assert_eq!(Ok(()), window.check(&nonce(6, 3), true));
+        assert_eq!(Err(Replay::Duplicate), window.check(&nonce(5, 3), true));
+        assert_eq!(Err(Replay::Retired), window.check(&nonce(502, 1), false));
+        assert_eq!(Err(Replay::Retired), window.check(&nonce(4, 2), false));
+    }
+
+    #[test]
+    fn test_untracked_session() {
+        let mut window = ReplayWindow::new();
+
+        assert_eq!(Ok(()), window.check(&nonce(500, 1), false));
+        for prefix in 2..(2 + MAX_UNPROVEN as u8) {
+            assert_eq!(Ok(()), window.check(&nonce(1, prefix), false));
+        }
+
+        // the unproven prefixes are not forgotten, so a new one is rejected
+        let untracked = 2 + MAX_UNPROVEN as u8;
+        assert_eq!(Err(Replay::Untracked), window.check(&nonce(1, untracked), false));
+        assert_eq!(Err(Replay::Duplicate), window.check(&nonce(1, 2), false));
+        assert_eq!(Ok(()), window.check(&nonce(501, 1), false));
+
+        // a session proves the prefix, all others are retired for good
+        assert_eq!(Ok(()), window.check(&nonce(2, untracked), true));
+        for prefix in 1..untracked {
+            assert_eq!(Err(Replay::Retired), window.check(&nonce(3, prefix), false));
+        }
+    }
+
+    #[test]
+    fn test_retired_never_forgotten() {
+        let mut window = ReplayWindow::new();
+
+        for prefix in 1..100 {
+            assert_eq!(Ok(()), window.check(&nonce(1, prefix), true));
+        }
+        for prefix in 1..99 {
+            assert_eq!(Err(Replay::Retired), window.check(&nonce(2, prefix), false));
+            assert_eq!(Err(Replay::Retired), window.check(&nonce(2, prefix), true));
+        }
+    }
+}
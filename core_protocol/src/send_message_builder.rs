@@ -2,7 +2,7 @@
 use nacl::Nacl;
 use payloads::Payload;
 use sodiumoxide::crypto::box_;
-use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::PublicKey;
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{PrecomputedKey, PublicKey};
 
 ///This struct represents the structure of the protocol
 #[derive(Clone, Debug, PartialEq)]
@@ -53,4 +53,40 @@ impl<T: Payload> MessageBuilder<T> {
         payload.extend(encrypted);
         payload
     }
+
+    /// Same as `build`, but seals with a key created by `Nacl::precompute`
+    pub fn build_precomputed(self, nacl: &mut Nacl, key: &PrecomputedKey) -> Vec<u8> {
+        let nonce = nacl.get_nonce();
+        let mut payload = Vec::new();
+        payload.extend(nonce.0.iter());
+
+        let mut result = vec![self.version, self.event_code];
+        result.append(&mut self.payload.to_bytes());
+
+        payload.extend(box_::seal_precomputed(&result, &nonce, key));
+        payload
+    }
+
+    /// Builds a message for a peer that doesn´t know our public key yet
+    ///
+    /// The message is sealed like with `build`, but our public key is put
+    /// in front of it, so the receiver is able to open it. Only used for
+    /// `Register`, opened with `decrypt_introduction`.
+    ///
+    /// ```
+    /// //  00 01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+    /// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+    /// // | Public key of the sender                                                                      |
+    /// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+    /// // //                                                                                             //
+    /// // // Sealed message                                                                              //
+    /// // //                                                                                             //
+    /// // +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+    /// ```
+    pub fn build_introduction(self, nacl: &mut Nacl, public_key: &PublicKey) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.extend(nacl.public_key().0.iter());
+        payload.extend(self.build(nacl, public_key));
+        payload
+    }
 }